@@ -0,0 +1,15 @@
+//! Overhead of parcheck per step: `cargo bench --features enable`.
+//!
+//! A plain `harness = false` binary rather than a `criterion` benchmark: [`Overhead::measure`]
+//! averages the cost per step over all explored schedules itself, and keeps the bench free of
+//! dev-dependencies.
+
+use parcheck::bench::Overhead;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    for (tasks, operations, schedules) in [(2, 4, 70), (3, 3, 1000), (4, 8, 1000)] {
+        let overhead = Overhead::measure(tasks, operations, schedules).await;
+        println!("{tasks} tasks x {operations} operations: {overhead}");
+    }
+}
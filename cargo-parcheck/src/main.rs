@@ -0,0 +1,214 @@
+//! `cargo parcheck`: runs parcheck tests with common combinations of environment variables and
+//! inspects traces and exported reports.
+
+mod report;
+
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    path::Path,
+    process::{Command, ExitCode},
+};
+
+use parcheck::Trace;
+
+use crate::report::Summary;
+
+const USAGE: &str = "\
+Usage: cargo parcheck <command> [args]
+
+Commands:
+  test [options] [<cargo test args>] [-- <test binary args>]
+      Runs `cargo test` with parcheck environment variables:
+        --replay <trace|file>    replay a schedule (PARCHECK_REPLAY)
+        --max-iterations <n>     limit explored schedules (PARCHECK_MAX_ITERATIONS)
+        --seed <n>               make exploration reproducible (PARCHECK_SEED)
+        --matrix <names>         run selected configurations (PARCHECK_MATRIX)
+        --otel-file <path>       export iterations (PARCHECK_OTEL_FILE, `otel` feature)
+        --interactive            choose steps from stdin (PARCHECK_INTERACTIVE, `interactive` feature)
+        --dry-run                print the command instead of running it
+  show <trace|file>
+      Prints steps of a trace, one per line.
+  convert <trace|file> [--to compact|test] [--name <test name>]
+      Converts a trace: `compact` is the PARCHECK_REPLAY format, `test` is a replay test.
+  report <file>
+      Summarizes iterations exported with PARCHECK_OTEL_FILE.";
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // invoked as `cargo parcheck ...`
+    if args.first().is_some_and(|arg| arg == "parcheck") {
+        args.remove(0);
+    }
+
+    match run(args) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Vec<String>) -> Result<ExitCode, String> {
+    let mut args = args.into_iter();
+    match args.next().as_deref() {
+        Some("test") => test(args),
+        Some("show") => {
+            let trace = read_trace(&single_arg(args)?)?;
+            print!("{}", pretty(&trace));
+            Ok(ExitCode::SUCCESS)
+        }
+        Some("convert") => convert(args),
+        Some("report") => {
+            let path = single_arg(args)?;
+            let export =
+                fs::read_to_string(&path).map_err(|err| format!("can't read {path}: {err}"))?;
+            let summary = Summary::parse(&export);
+            print!("{}", summary.render());
+            Ok(if summary.failures.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            })
+        }
+        Some("help" | "--help" | "-h") | None => {
+            println!("{USAGE}");
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(other) => Err(format!("unknown command '{other}'")),
+    }
+}
+
+fn test(mut args: impl Iterator<Item = String>) -> Result<ExitCode, String> {
+    let mut envs: Vec<(&str, String)> = Vec::new();
+    let mut cargo_args = Vec::new();
+    let mut test_args = Vec::new();
+    let mut dry_run = false;
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{name} requires a value"));
+        match arg.as_str() {
+            "--replay" => {
+                let trace = read_trace(&value("--replay")?)?;
+                envs.push(("PARCHECK_REPLAY", trace.to_string()));
+            }
+            "--max-iterations" => {
+                let max_iterations = value("--max-iterations")?;
+                max_iterations
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid --max-iterations '{max_iterations}'"))?;
+                envs.push(("PARCHECK_MAX_ITERATIONS", max_iterations));
+            }
+            "--seed" => {
+                let seed = value("--seed")?;
+                seed.parse::<u64>()
+                    .map_err(|_| format!("invalid --seed '{seed}'"))?;
+                envs.push(("PARCHECK_SEED", seed));
+            }
+            "--matrix" => envs.push(("PARCHECK_MATRIX", value("--matrix")?)),
+            "--otel-file" => envs.push(("PARCHECK_OTEL_FILE", value("--otel-file")?)),
+            "--interactive" => {
+                envs.push(("PARCHECK_INTERACTIVE", "1".into()));
+                // prompts are written to stderr, which is captured by the test harness
+                test_args.extend(["--nocapture".into(), "--test-threads=1".into()]);
+            }
+            "--dry-run" => dry_run = true,
+            "--" => test_args.extend(args.by_ref()),
+            _ => cargo_args.push(arg),
+        }
+    }
+
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let mut command = Command::new(&cargo);
+    command.arg("test").args(&cargo_args);
+    if !test_args.is_empty() {
+        command.arg("--").args(&test_args);
+    }
+    command.envs(envs.iter().map(|(name, value)| (name, value)));
+
+    if dry_run {
+        let mut line: Vec<String> = envs
+            .iter()
+            .map(|(name, value)| format!("{name}={value:?}"))
+            .collect();
+        line.push(format!("{cargo} test"));
+        line.extend(cargo_args);
+        if !test_args.is_empty() {
+            line.push("--".into());
+            line.extend(test_args);
+        }
+        println!("{}", line.join(" "));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let status = command
+        .status()
+        .map_err(|err| format!("failed to run {cargo}: {err}"))?;
+    Ok(if status.success() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn convert(mut args: impl Iterator<Item = String>) -> Result<ExitCode, String> {
+    let mut trace = None;
+    let mut to = String::from("compact");
+    let mut name = String::from("replay");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--to" => to = args.next().ok_or("--to requires a value")?,
+            "--name" => name = args.next().ok_or("--name requires a value")?,
+            _ if trace.is_none() => trace = Some(read_trace(&arg)?),
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+    let trace = trace.ok_or("missing trace")?;
+
+    match to.as_str() {
+        "compact" => println!("{trace}"),
+        "test" => print!("{}", trace.to_replay_test(&name)),
+        _ => return Err(format!("unknown trace format '{to}'")),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Trace given inline or as a path to a file containing it.
+fn read_trace(arg: &str) -> Result<Trace, String> {
+    let trace = if Path::new(arg).is_file() {
+        fs::read_to_string(arg).map_err(|err| format!("can't read {arg}: {err}"))?
+    } else {
+        arg.to_owned()
+    };
+    trace
+        .trim()
+        .parse()
+        .map_err(|err| format!("can't parse trace: {err}"))
+}
+
+/// One line per step.
+fn pretty(trace: &Trace) -> String {
+    let mut out = String::new();
+    for (index, step) in trace.steps().iter().enumerate() {
+        write!(out, "{index:>4}  task '{}'", step.task_name()).unwrap();
+        if let Some(task_id) = step.task_id() {
+            write!(out, " (#{task_id})").unwrap();
+        }
+        write!(out, "  {}", step.operation_name()).unwrap();
+        if let Some(fault) = step.injected_fault() {
+            write!(out, "  [injected {fault}]").unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn single_arg(mut args: impl Iterator<Item = String>) -> Result<String, String> {
+    let arg = args.next().ok_or("missing argument")?;
+    match args.next() {
+        Some(extra) => Err(format!("unexpected argument '{extra}'")),
+        None => Ok(arg),
+    }
+}
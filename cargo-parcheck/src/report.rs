@@ -0,0 +1,67 @@
+//! Summary of iterations exported with `PARCHECK_OTEL_FILE` (OTLP JSON, one iteration per line).
+
+use std::fmt::Write as _;
+
+const FAILURE_STATUS: &str = r#""status":{"code":2,"message":""#;
+const TRACE_ATTRIBUTE: &str = r#"{"key":"parcheck.trace","value":{"stringValue":""#;
+
+#[derive(Debug, Default)]
+pub(crate) struct Summary {
+    pub(crate) iterations: u64,
+    /// Failure message and trace of failed iterations.
+    pub(crate) failures: Vec<(String, String)>,
+}
+
+impl Summary {
+    pub(crate) fn parse(export: &str) -> Self {
+        let mut summary = Self::default();
+        for line in export.lines().filter(|line| !line.trim().is_empty()) {
+            summary.iterations += 1;
+            if let Some(message) = string_after(line, FAILURE_STATUS) {
+                let trace = string_after(line, TRACE_ATTRIBUTE).unwrap_or_default();
+                summary.failures.push((message, trace));
+            }
+        }
+        summary
+    }
+
+    pub(crate) fn render(&self) -> String {
+        let mut out = format!(
+            "iterations: {}\nfailed: {}\n",
+            self.iterations,
+            self.failures.len()
+        );
+        for (message, trace) in &self.failures {
+            write!(
+                out,
+                "\nfailure: {message}\n  replay with: cargo parcheck test --replay {trace:?}\n"
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+/// JSON string value that starts right after `prefix`.
+fn string_after(line: &str, prefix: &str) -> Option<String> {
+    let start = line.find(prefix)? + prefix.len();
+    let mut value = String::new();
+    let mut chars = line[start..].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                'u' => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    value.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+                }
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
@@ -0,0 +1,95 @@
+use std::{env, fs, process::Command};
+
+const TRACE: &str = "0:writer.lock > 1:reader.read > 0:writer.commit!serialization_failure";
+
+fn cargo_parcheck(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-parcheck"))
+        .arg("parcheck")
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn shows_trace_steps() {
+    let (success, stdout) = cargo_parcheck(&["show", TRACE]);
+    assert!(success);
+    assert_eq!(
+        stdout,
+        "   0  task 'writer' (#0)  lock\n   \
+            1  task 'reader' (#1)  read\n   \
+            2  task 'writer' (#0)  commit  [injected serialization_failure]\n"
+    );
+}
+
+#[test]
+fn converts_traces() {
+    let path = env::temp_dir().join("cargo_parcheck_converts_traces.trace");
+    fs::write(&path, format!("{TRACE}\n")).unwrap();
+
+    let (success, stdout) = cargo_parcheck(&["convert", path.to_str().unwrap()]);
+    assert!(success);
+    assert_eq!(stdout, format!("{TRACE}\n"));
+
+    let (success, stdout) = cargo_parcheck(&["convert", TRACE, "--to", "test", "--name", "t"]);
+    assert!(success);
+    assert!(stdout.starts_with("#[tokio::test]\nasync fn t() {\n"));
+    assert!(stdout.contains(r#".run(["writer", "reader"], || async {"#));
+
+    // same grammar as `parcheck::Trace`
+    let (success, stdout) = cargo_parcheck(&["convert", r"0:worker\:a.op > 1:worker\:b.op"]);
+    assert!(success);
+    assert_eq!(stdout, "0:worker\\:a.op > 1:worker\\:b.op\n");
+
+    let (success, _) = cargo_parcheck(&["convert", "not a trace"]);
+    assert!(!success);
+}
+
+#[test]
+fn passes_options_to_cargo_test() {
+    let (success, stdout) = cargo_parcheck(&[
+        "test",
+        "--dry-run",
+        "--replay",
+        TRACE,
+        "--max-iterations",
+        "10",
+        "--seed",
+        "7",
+        "-p",
+        "app",
+        "--",
+        "my_test",
+    ]);
+    assert!(success);
+    assert!(stdout.starts_with(&format!(
+        "PARCHECK_REPLAY={TRACE:?} PARCHECK_MAX_ITERATIONS=\"10\" PARCHECK_SEED=\"7\" "
+    )));
+    assert!(stdout.ends_with(" test -p app -- my_test\n"));
+
+    let (success, _) = cargo_parcheck(&["test", "--max-iterations", "many"]);
+    assert!(!success);
+}
+
+#[test]
+fn renders_reports() {
+    let path = env::temp_dir().join("cargo_parcheck_renders_reports.jsonl");
+    let span = |status: &str| {
+        format!(
+            r#"{{"resourceSpans":[{{"scopeSpans":[{{"spans":[{{"name":"parcheck.iteration","attributes":[{{"key":"parcheck.trace","value":{{"stringValue":"0:a.op"}}}}]{status}}}]}}]}}]}}"#
+        )
+    };
+    let failed = span(r#","status":{"code":2,"message":"assertion \"x\" failed"}"#);
+    fs::write(&path, format!("{}\n{failed}\n", span(""))).unwrap();
+
+    let (success, stdout) = cargo_parcheck(&["report", path.to_str().unwrap()]);
+    assert!(!success);
+    assert_eq!(
+        stdout,
+        "iterations: 2\nfailed: 1\n\nfailure: assertion \"x\" failed\n  replay with: cargo parcheck test --replay \"0:a.op\"\n"
+    );
+}
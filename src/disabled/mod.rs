@@ -13,6 +13,45 @@ macro_rules! task {
 
 #[macro_export]
 macro_rules! operation {
+    ($name:literal, timeout, { $fut:expr }) => {
+        async { Ok::<_, $crate::TimedOut>($fut.await) }
+    };
+    ($name:literal, $locks:expr, timeout, { $fut:expr }) => {{
+        {
+            let _ = || $locks;
+        }
+        async { Ok::<_, $crate::TimedOut>($fut.await) }
+    }};
+    ($name:literal, serializable, { $fut:expr }) => {
+        async { Ok::<_, $crate::SerializationFailure>($fut.await) }
+    };
+    ($name:literal, $locks:expr, serializable, { $fut:expr }) => {{
+        {
+            let _ = || $locks;
+        }
+        async { Ok::<_, $crate::SerializationFailure>($fut.await) }
+    }};
+    ($name:literal, unique = $key:expr, { $fut:expr }) => {{
+        {
+            let _ = || $key;
+        }
+        async { Ok::<_, $crate::UniqueViolation>($fut.await) }
+    }};
+    ($name:literal, $locks:expr, unique = $key:expr, { $fut:expr }) => {{
+        {
+            let _ = || ($locks, $key);
+        }
+        async { Ok::<_, $crate::UniqueViolation>($fut.await) }
+    }};
+    ($name:literal, cost = $cost:ident, { $fut:expr }) => {{
+        $fut
+    }};
+    ($name:literal, $locks:expr, cost = $cost:ident, { $fut:expr }) => {{
+        {
+            let _ = || $locks;
+        }
+        $fut
+    }};
     ($name:literal, $locks:expr, { $fut:expr }) => {{
         {
             let _ = || $locks;
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+#[cfg(feature = "backtrace")]
+const MAX_FRAMES: usize = 10;
+
+#[cfg(feature = "backtrace")]
+const INTERNAL_PREFIXES: &[&str] = &[
+    "std::",
+    "core::",
+    "alloc::",
+    "tokio::",
+    "futures_util::",
+    "futures_core::",
+    "tracing::",
+    "parcheck::",
+    "test::",
+    "fn(",
+    "__",
+];
+
+/// Captures a shortened backtrace of an operation call site: frames from std, tokio, futures and
+/// parcheck itself are skipped. Returns `None` unless `backtrace` feature is enabled.
+#[cfg(feature = "backtrace")]
+#[allow(clippy::unnecessary_wraps)] // same signature as without the feature
+pub(crate) fn capture() -> Option<Arc<str>> {
+    let backtrace = std::backtrace::Backtrace::force_capture().to_string();
+
+    let mut frames: Vec<Vec<&str>> = Vec::new();
+    for line in backtrace.lines() {
+        let trimmed = line.trim_start();
+        let is_frame_start = trimmed
+            .split_once(": ")
+            .is_some_and(|(idx, _)| idx.bytes().all(|b| b.is_ascii_digit()));
+        if is_frame_start {
+            frames.push(vec![line]);
+        } else if let Some(frame) = frames.last_mut() {
+            frame.push(line);
+        }
+    }
+
+    let shortened = frames
+        .into_iter()
+        .filter(|frame| {
+            let symbol = frame[0]
+                .trim_start()
+                .split_once(": ")
+                .map_or("", |(_, symbol)| symbol.trim_start_matches('<'));
+            !INTERNAL_PREFIXES
+                .iter()
+                .any(|prefix| symbol.starts_with(prefix))
+        })
+        .take(MAX_FRAMES)
+        .flatten()
+        .collect::<Vec<&str>>()
+        .join("\n");
+
+    Some(shortened.into())
+}
+
+#[cfg(not(feature = "backtrace"))]
+pub(crate) fn capture() -> Option<Arc<str>> {
+    None
+}
@@ -0,0 +1,153 @@
+use std::{fmt, time::Duration};
+
+use futures_util::future::{join, join_all};
+use tokio::time::Instant;
+
+use crate::{
+    enabled::{
+        controller::{Controller, ControllerConfig, ControllerError},
+        operation::{operation, OperationMetadata},
+        random::Rng,
+        runner::WAIT_TIMEOUT,
+        schedule_tree::ScheduleTree,
+        strategy::CostConfig,
+        task::{task, StepSpan, TaskName},
+    },
+    ParcheckLock,
+};
+
+static NOOP: OperationMetadata = OperationMetadata::new("noop", file!(), line!());
+
+/// Instrumentation cost of parcheck, measured by exploring schedules of tasks that execute empty
+/// operations. Times are averaged per step, so results of different parcheck versions (or
+/// machines) can be compared with [`Overhead::regressed`].
+///
+/// ```ignore
+/// let overhead = parcheck::bench::Overhead::measure(3, 4, 1000).await;
+/// println!("{overhead}");
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Overhead {
+    steps: u64,
+    ready: Duration,
+    tree: Duration,
+    permit: Duration,
+}
+
+impl Overhead {
+    /// Explores up to `schedules` schedules of `tasks` tasks executing `operations` empty
+    /// operations each. Must be called within a tokio runtime.
+    pub async fn measure(tasks: usize, operations: usize, schedules: u64) -> Self {
+        let names: Vec<TaskName> = (0..tasks)
+            .map(|index| TaskName(format!("bench:{index}").into()))
+            .collect();
+        let config = ControllerConfig {
+            fast_permits: true,
+            ..ControllerConfig::default()
+        };
+        let mut tree = ScheduleTree::new(&names, CostConfig::default());
+        let mut rng = Rng::with_seed(0);
+        let mut overhead = Self::default();
+
+        for _ in 0..schedules {
+            let mut path_rng = rng.fork();
+            let Some(mut cursor) = tree.pick_unfinished_path(&mut rng) else {
+                break;
+            };
+            let mut controller = Controller::register(&names, &config);
+            let steps = async {
+                loop {
+                    let start = Instant::now();
+                    controller.ready(WAIT_TIMEOUT).await?;
+                    overhead.ready += start.elapsed();
+
+                    let start = Instant::now();
+                    let choice = cursor.visit_and_pick(controller.tasks(), &mut path_rng);
+                    overhead.tree += start.elapsed();
+                    let Some(choice) = choice else {
+                        return controller.check_finished();
+                    };
+
+                    let start = Instant::now();
+                    controller
+                        .step_forward(
+                            choice.task_id,
+                            Duration::ZERO,
+                            StepSpan::default(),
+                            choice.inject_fault,
+                        )
+                        .await?;
+                    overhead.permit += start.elapsed();
+                    overhead.steps += 1;
+                }
+            };
+            let scenario = join_all(names.iter().map(|name| {
+                task(&name.0, async {
+                    for _ in 0..operations {
+                        operation(&NOOP, Vec::<ParcheckLock>::new(), async {}).await;
+                    }
+                })
+            }));
+            let (result, _) = join(steps, scenario).await;
+            result.unwrap_or_else(|error: ControllerError| panic!("benchmark failed: {error}"));
+        }
+        overhead
+    }
+
+    /// Number of measured steps.
+    #[must_use]
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Time spent waiting until every task requested an operation or finished.
+    #[must_use]
+    pub fn ready_scan(&self) -> Duration {
+        self.per_step(self.ready)
+    }
+
+    /// Time spent picking the next step and updating the schedule tree.
+    #[must_use]
+    pub fn tree_update(&self) -> Duration {
+        self.per_step(self.tree)
+    }
+
+    /// Time from granting a permit until the (empty) operation finished, i.e. the overhead of a
+    /// single operation.
+    #[must_use]
+    pub fn permit_round_trip(&self) -> Duration {
+        self.per_step(self.permit)
+    }
+
+    /// Total overhead of a step.
+    #[must_use]
+    pub fn step(&self) -> Duration {
+        self.per_step(self.ready + self.tree + self.permit)
+    }
+
+    /// Whether a step takes more than `1 + tolerance` times `baseline` (e.g. [`Overhead::step`]
+    /// measured with a previous version).
+    #[must_use]
+    pub fn regressed(&self, baseline: Duration, tolerance: f64) -> bool {
+        self.step().as_secs_f64() > baseline.as_secs_f64() * (1.0 + tolerance)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn per_step(&self, total: Duration) -> Duration {
+        Duration::from_nanos((total.as_nanos() / u128::from(self.steps.max(1))) as u64)
+    }
+}
+
+impl fmt::Display for Overhead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} per step (ready scan {:?}, tree update {:?}, permit round trip {:?}), {} steps",
+            self.step(),
+            self.ready_scan(),
+            self.tree_update(),
+            self.permit_round_trip(),
+            self.steps,
+        )
+    }
+}
@@ -0,0 +1,79 @@
+use std::{fs, process, time::Duration};
+
+use futures_util::FutureExt;
+
+use crate::enabled::runner::{StepContext, StepHook};
+
+/// Step before which [`Runner::break_at`](crate::Runner::break_at) pauses the iteration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Step with the index within the iteration, e.g. of a replayed trace.
+    Step(usize),
+    /// Every step executing an operation with the name.
+    Operation(String),
+}
+
+impl Breakpoint {
+    /// Parses value of `PARCHECK_BREAK_AT` environment variable: index of a step or name of an
+    /// operation.
+    pub(crate) fn from_env_value(value: &str) -> Self {
+        value
+            .parse()
+            .map_or_else(|_| Self::Operation(value.to_owned()), Self::Step)
+    }
+
+    pub(crate) fn matches(&self, ctx: &StepContext<'_>) -> bool {
+        match self {
+            Self::Step(index) => ctx.index() == *index,
+            Self::Operation(name) => ctx.step().operation_name() == name,
+        }
+    }
+}
+
+impl From<usize> for Breakpoint {
+    fn from(index: usize) -> Self {
+        Self::Step(index)
+    }
+}
+
+impl From<&str> for Breakpoint {
+    fn from(name: &str) -> Self {
+        Self::Operation(name.to_owned())
+    }
+}
+
+impl From<String> for Breakpoint {
+    fn from(name: String) -> Self {
+        Self::Operation(name)
+    }
+}
+
+/// Breakpoint hook that prints the paused step and the process id to stderr, then waits until a
+/// debugger is attached to the process (detected on Linux, elsewhere the step continues right
+/// away). Used for breakpoints of `PARCHECK_BREAK_AT` environment variable.
+#[must_use]
+pub fn wait_for_debugger() -> StepHook {
+    Box::new(|ctx| {
+        eprintln!(
+            "parcheck: paused before step {} ({:?}), attach a debugger to process {} to continue",
+            ctx.index(),
+            ctx.step(),
+            process::id()
+        );
+        async {
+            while debugger_attached() == Some(false) {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+        .boxed()
+    })
+}
+
+/// Whether a debugger is attached to the process, `None` if it can't be detected.
+fn debugger_attached() -> Option<bool> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let tracer = status
+        .lines()
+        .find_map(|line| line.strip_prefix("TracerPid:"))?;
+    Some(tracer.trim() != "0")
+}
@@ -0,0 +1,99 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::enabled::{
+    random::Rng, schedule_tree::ScheduleTree, strategy::CostConfig, task::TaskName,
+};
+
+const HEADER: &str = "parcheck-checkpoint v1";
+
+/// Exploration state saved to a file, see [`Runner::checkpoint`](crate::Runner::checkpoint).
+///
+/// The file starts with a header line, followed by the number of finished iterations, the state
+/// of the random number generator and the [`ScheduleTree`].
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    interval: Duration,
+    last_saved: Instant,
+}
+
+/// State restored from a checkpoint.
+pub(crate) struct Restored {
+    pub(crate) tree: ScheduleTree,
+    pub(crate) iterations: u64,
+    /// `None` if the run used a custom random source.
+    pub(crate) rng: Option<Rng>,
+}
+
+impl Checkpoint {
+    pub(crate) fn new(path: PathBuf, interval: Duration) -> Self {
+        Self {
+            path,
+            interval,
+            last_saved: Instant::now(),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// State saved by a previous run exploring the same tasks, `None` if there's no checkpoint or
+    /// it can't be parsed (e.g. written by a different version or for a changed test).
+    pub(crate) fn load(&self, tasks: &[TaskName], costs: CostConfig) -> Option<Restored> {
+        let contents = fs::read_to_string(&self.path).ok()?;
+        let mut lines = contents.lines();
+        if lines.next()? != HEADER {
+            return None;
+        }
+        let iterations = lines.next()?.strip_prefix("iterations ")?.parse().ok()?;
+        let rng = match lines.next()?.strip_prefix("rng ")? {
+            "-" => None,
+            state => Some(Rng::with_seed(state.parse().ok()?)),
+        };
+        let tree = ScheduleTree::read_checkpoint(lines, tasks, costs)?;
+        Some(Restored {
+            tree,
+            iterations,
+            rng,
+        })
+    }
+
+    /// Saves the state if `interval` passed since it was last saved.
+    pub(crate) fn save_periodically(&mut self, tree: &ScheduleTree, iterations: u64, rng: &Rng) {
+        if self.last_saved.elapsed() >= self.interval {
+            self.save(tree, iterations, rng);
+        }
+    }
+
+    /// Writes the state to a temporary file that replaces the checkpoint, so that an interrupted
+    /// run never leaves a partially written checkpoint behind.
+    pub(crate) fn save(&mut self, tree: &ScheduleTree, iterations: u64, rng: &Rng) {
+        let mut contents = String::new();
+        writeln!(contents, "{HEADER}").unwrap();
+        writeln!(contents, "iterations {iterations}").unwrap();
+        match rng.state() {
+            Some(state) => writeln!(contents, "rng {state}").unwrap(),
+            None => writeln!(contents, "rng -").unwrap(),
+        }
+        tree.write_checkpoint(&mut contents);
+
+        if let Some(dir) = self.path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let tmp = self.path.with_extension("tmp");
+        if fs::write(&tmp, contents).is_ok() {
+            let _ = fs::rename(&tmp, &self.path);
+        }
+        self.last_saved = Instant::now();
+    }
+
+    /// Removes the checkpoint once the exploration is finished.
+    pub(crate) fn remove(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
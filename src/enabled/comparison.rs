@@ -0,0 +1,114 @@
+use std::fmt;
+
+use crate::enabled::trace::Trace;
+
+/// Variant of the scenario passed to the body by [`Runner::compare`](crate::Runner::compare),
+/// e.g. code before and after a fix (feature flag off and on).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Before,
+    After,
+}
+
+/// Outcomes of both variants of a scenario per schedule, see
+/// [`Runner::compare`](crate::Runner::compare). Displayed as the schedules whose outcomes differ,
+/// one per line.
+#[derive(Debug, Clone, Default)]
+pub struct Comparison {
+    pub(crate) schedules: Vec<ComparedSchedule>,
+}
+
+impl Comparison {
+    /// Every explored schedule, in order of exploration.
+    #[must_use]
+    pub fn schedules(&self) -> &[ComparedSchedule] {
+        &self.schedules
+    }
+
+    /// Schedules whose outcome changed.
+    pub fn differences(&self) -> impl Iterator<Item = &ComparedSchedule> {
+        self.schedules.iter().filter(|schedule| schedule.changed())
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for schedule in self.differences() {
+            writeln!(f, "{schedule}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Schedule explored with [`Variant::Before`] and replayed with [`Variant::After`].
+#[derive(Debug, Clone)]
+pub struct ComparedSchedule {
+    pub(crate) before_trace: Trace,
+    pub(crate) after_trace: Trace,
+    pub(crate) before: Option<String>,
+    pub(crate) after: Option<String>,
+}
+
+impl ComparedSchedule {
+    /// Schedule explored with [`Variant::Before`].
+    #[must_use]
+    pub fn trace(&self) -> &Trace {
+        &self.before_trace
+    }
+
+    /// Schedule executed with [`Variant::After`]: the same as [`ComparedSchedule::trace`] unless
+    /// it [diverged](ComparedSchedule::diverged).
+    #[must_use]
+    pub fn after_trace(&self) -> &Trace {
+        &self.after_trace
+    }
+
+    /// Panic message of [`Variant::Before`], `None` if it passed.
+    #[must_use]
+    pub fn before(&self) -> Option<&str> {
+        self.before.as_deref()
+    }
+
+    /// Panic message of [`Variant::After`], `None` if it passed.
+    #[must_use]
+    pub fn after(&self) -> Option<&str> {
+        self.after.as_deref()
+    }
+
+    /// Whether the variants had different outcomes (one failed and the other didn't, or they
+    /// failed with different messages).
+    #[must_use]
+    pub fn changed(&self) -> bool {
+        self.before != self.after
+    }
+
+    /// Whether [`Variant::After`] couldn't follow the schedule (e.g. it executes different
+    /// operations) and continued with other steps.
+    #[must_use]
+    pub fn diverged(&self) -> bool {
+        self.before_trace != self.after_trace
+    }
+}
+
+impl fmt::Display for ComparedSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // first line of the panic message, so that every schedule is displayed on one line
+        let outcome = |outcome: &Option<String>| {
+            outcome.as_ref().map_or_else(
+                || "passed".to_owned(),
+                |message| format!("failed ({})", message.lines().next().unwrap_or_default()),
+            )
+        };
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.before_trace,
+            outcome(&self.before),
+            outcome(&self.after)
+        )?;
+        if self.diverged() {
+            write!(f, " (diverged: {})", self.after_trace)?;
+        }
+        Ok(())
+    }
+}
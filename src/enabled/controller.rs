@@ -1,25 +1,78 @@
-use std::{collections::HashMap, fmt, mem::replace, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    mem::{self, replace},
+    ptr,
+    sync::Arc,
+    time::Duration,
+};
 
 use tokio::{
     sync::{mpsc, oneshot},
-    time::error::Elapsed,
+    time::{error::Elapsed, Instant},
 };
 
 use crate::{
     enabled::{
-        operation::OperationMetadata,
-        task::{OperationPermit, Task, TaskEvent, TaskId, TaskName},
+        lock_model::{LockModel, SharedExclusive},
+        operation::{Fallible, Fault, OperationMetadata},
+        report::ScopeStats,
+        scope_id::{Lock, ScopeId},
+        task::{
+            OperationPermit, Registrations, SequencedEvent, StepSpan, Task, TaskEvent, TaskId,
+            TaskName,
+        },
+        trace::StepTiming,
     },
-    ParcheckLock,
+    scope::TypedLock,
 };
 
+#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub(crate) struct ControllerConfig {
+    pub(crate) task_deadlines: HashMap<TaskName, Duration>,
+    pub(crate) fair_locks: bool,
+    pub(crate) unique_op_names: bool,
+    pub(crate) strict: bool,
+    pub(crate) detect_overlaps: bool,
+    pub(crate) leak_cancelled_locks: bool,
+    /// Set by `Runner::run_with_handles`.
+    pub(crate) registrations: Option<Registrations>,
+    /// [`SharedExclusive`] if not set.
+    pub(crate) lock_model: Option<Arc<dyn LockModel>>,
+    /// Whether the last unfinished task starts operations without waiting for permits. Only
+    /// valid if nothing needs to happen between picking a step and executing it (hooks, latency).
+    pub(crate) fast_permits: bool,
+}
+
 pub(crate) struct Controller {
     tasks: Vec<(Task, TaskState)>,
+    progress: Vec<TaskProgress>,
     locked_state: LockedState,
+    /// Keys of unique operations executed in this iteration, with tasks that executed them.
+    unique_keys: Vec<(TaskId, Box<str>)>,
+    /// With fair locks: waiting tasks that were blocked by locks of other tasks, with the number
+    /// of the scheduling round they were blocked at.
+    lock_queue: Option<Vec<(TaskId, u64)>>,
+    rounds: u64,
     // TODO: spawn tasks
     #[allow(dead_code)]
-    events_tx: mpsc::UnboundedSender<(TaskId, TaskEvent)>,
-    events_rx: mpsc::UnboundedReceiver<(TaskId, TaskEvent)>,
+    events_tx: mpsc::UnboundedSender<SequencedEvent>,
+    events_rx: mpsc::UnboundedReceiver<SequencedEvent>,
+    /// Received events that weren't processed yet. Ready events are processed in order of task
+    /// ids (and sequence numbers within a task), regardless of the order they arrived in, so that
+    /// replays of a schedule observe the same sequence of events.
+    pending_events: BTreeMap<(TaskId, u64), TaskEvent>,
+    /// Last finished operation (or all operations finished in the iteration with
+    /// `detect_overlaps`). Operations are granted one at a time, so they can't overlap.
+    finished_ops: Vec<FinishedOperation>,
+    /// Task, start and end of the last finished operation, taken by `step_forward`.
+    last_executed: Option<(TaskId, std::time::Instant, std::time::Instant)>,
+    detect_overlaps: bool,
+    leak_cancelled_locks: bool,
+    fast_permits: bool,
+    /// Tasks cancelled since the last call to `take_cancelled`.
+    cancelled: Vec<TaskId>,
 }
 
 pub(crate) enum TaskState {
@@ -27,9 +80,13 @@ pub(crate) enum TaskState {
     ExecutingOutsideOperation,
     WaitingToStartOperation {
         metadata: &'static OperationMetadata,
-        permit: oneshot::Sender<OperationPermit>,
-        locks: Vec<ParcheckLock>,
-        blocked_locks: Vec<ParcheckLock>,
+        /// `None` if the operation already started with a fast permit.
+        permit: Option<oneshot::Sender<OperationPermit>>,
+        locks: Vec<Lock>,
+        blocked_locks: Vec<Lock>,
+        backtrace: Option<Arc<str>>,
+        fault: Option<Fallible>,
+        fault_injectable: bool,
     },
     ExecutingOperation {
         metadata: &'static OperationMetadata,
@@ -38,6 +95,193 @@ pub(crate) enum TaskState {
     Invalid,
 }
 
+struct FinishedOperation {
+    task_id: TaskId,
+    task: TaskName,
+    metadata: &'static OperationMetadata,
+    started_at: std::time::Instant,
+    finished_at: std::time::Instant,
+}
+
+impl FinishedOperation {
+    fn overlaps(&self, other: &Self) -> bool {
+        self.started_at < other.finished_at && other.started_at < self.finished_at
+    }
+}
+
+struct TaskProgress {
+    deadline: Option<Duration>,
+    started_at: Option<Instant>,
+    last_op: Option<&'static OperationMetadata>,
+    /// Serialization failure was injected into an operation of the task in this iteration.
+    serialization_failed: bool,
+    /// Operation that failed with serialization failure and wasn't retried yet.
+    pending_retry: Option<&'static OperationMetadata>,
+    /// Distinct operation sites executed by the task, tracked with `unique_op_names`.
+    op_sites: Option<Vec<&'static OperationMetadata>>,
+    /// Sequence number of the next event of the task to process.
+    next_seq: u64,
+}
+
+/// Misuse of parcheck (or a violated invariant) detected by the controller. Fails the iteration
+/// instead of panicking inside the control future, so that the runner can report it once,
+/// together with the trace.
+#[derive(Debug)]
+pub(crate) enum ControllerError {
+    TimedOut {
+        tasks: Vec<String>,
+    },
+    NotWaiting {
+        task: TaskName,
+        state: String,
+    },
+    NotExecutable {
+        task_id: TaskId,
+    },
+    BlockedByLocks {
+        task: TaskName,
+        locks: Vec<Lock>,
+    },
+    LockConflict {
+        scope: String,
+    },
+    DuplicateOperationName {
+        task: TaskName,
+        first: &'static OperationMetadata,
+        second: &'static OperationMetadata,
+    },
+    UnexpectedOperationFinished {
+        task: TaskName,
+    },
+    UnreleasedLocks {
+        task: TaskName,
+        scopes: Vec<String>,
+    },
+    MissingRetry {
+        task: TaskName,
+        operation: &'static OperationMetadata,
+    },
+    DeadlineExceeded {
+        task: TaskName,
+        deadline: Duration,
+        last_op: Option<&'static OperationMetadata>,
+    },
+    Unfinished {
+        tasks: Vec<String>,
+    },
+    EventAfterFinish {
+        task: TaskName,
+        event: String,
+    },
+    StillAlive {
+        task: TaskName,
+        last_op: Option<&'static OperationMetadata>,
+    },
+    NoOperations {
+        task: TaskName,
+    },
+    ParallelOperations {
+        first: (TaskName, &'static OperationMetadata),
+        second: (TaskName, &'static OperationMetadata),
+    },
+}
+
+impl fmt::Display for ControllerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TimedOut { tasks } => write!(f, "timed out, tasks: {tasks:#?}"),
+            Self::NotWaiting { task, state } => write!(
+                f,
+                "task '{}' can't execute an operation, it isn't waiting to start one: {state}",
+                task.0
+            ),
+            Self::NotExecutable { task_id } => {
+                write!(f, "task with id {} can't execute an operation", task_id.0)
+            }
+            Self::BlockedByLocks { task, locks } => write!(
+                f,
+                "task '{}' can't execute an operation, it is blocked by locks: {locks:?}",
+                task.0
+            ),
+            Self::LockConflict { scope } => write!(f, "conflicting locks acquired on {scope}"),
+            Self::DuplicateOperationName {
+                task,
+                first,
+                second,
+            } => write!(
+                f,
+                "task '{}': operations at {}:{} and {}:{} have the same name '{}'",
+                task.0, first.file, first.line, second.file, second.line, second.name
+            ),
+            Self::UnexpectedOperationFinished { task } => write!(
+                f,
+                "task '{}': received OperationFinished when not inside operation",
+                task.0
+            ),
+            Self::UnreleasedLocks { task, scopes } => write!(
+                f,
+                "task '{}': finished without releasing locks: {scopes:?}",
+                task.0
+            ),
+            Self::MissingRetry { task, operation } => write!(
+                f,
+                "task '{}': finished without retrying operation {operation} after serialization failure",
+                task.0
+            ),
+            Self::DeadlineExceeded {
+                task,
+                deadline,
+                last_op: Some(last_op),
+            } => write!(
+                f,
+                "task '{}' exceeded deadline of {deadline:?} (last operation: {last_op})",
+                task.0
+            ),
+            Self::DeadlineExceeded {
+                task,
+                deadline,
+                last_op: None,
+            } => write!(
+                f,
+                "task '{}' exceeded deadline of {deadline:?} (no operations executed)",
+                task.0
+            ),
+            Self::Unfinished { tasks } => write!(f, "some tasks did not finish: {tasks:#?}"),
+            Self::EventAfterFinish { task, event } => write!(
+                f,
+                "task '{}': received {event} after iteration finished (leaked task or operation future?)",
+                task.0
+            ),
+            Self::StillAlive {
+                task,
+                last_op: Some(last_op),
+            } => write!(
+                f,
+                "task '{}' is still alive after iteration finished (last operation: {last_op})",
+                task.0
+            ),
+            Self::StillAlive {
+                task,
+                last_op: None,
+            } => write!(
+                f,
+                "task '{}' is still alive after iteration finished",
+                task.0
+            ),
+            Self::NoOperations { task } => write!(
+                f,
+                "task '{}' finished without executing any operations (are `parcheck::operation!` annotations missing or disabled?)",
+                task.0
+            ),
+            Self::ParallelOperations { first, second } => write!(
+                f,
+                "operation {} of task '{}' executed in parallel with operation {} of task '{}' (operations must only run when granted by parcheck, are there operations in spawned futures?)",
+                first.1, first.0 .0, second.1, second.0 .0
+            ),
+        }
+    }
+}
+
 impl fmt::Display for OperationMetadata {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "'{}' (at {}:{})", self.name, self.file, self.line)
@@ -81,31 +325,93 @@ impl TaskState {
             _ => None,
         }
     }
+
+    /// Fault that can be injected instead of executing the operation, if task can execute.
+    pub(crate) fn injectable_fault(&self) -> Option<Fault> {
+        match self {
+            Self::WaitingToStartOperation {
+                fault: Some(fallible),
+                fault_injectable: true,
+                ..
+            } if self.can_execute() => Some(fallible.fault),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn locks(&self) -> &[Lock] {
+        match self {
+            Self::WaitingToStartOperation { locks, .. } => locks,
+            _ => &[],
+        }
+    }
+
+    pub(crate) fn backtrace(&self) -> Option<&Arc<str>> {
+        match self {
+            Self::WaitingToStartOperation { backtrace, .. } => backtrace.as_ref(),
+            _ => None,
+        }
+    }
 }
 
 impl Controller {
-    pub(crate) fn register(initial_tasks: &[TaskName]) -> Self {
+    pub(crate) fn register(initial_tasks: &[TaskName], config: &ControllerConfig) -> Self {
         let (events_tx, events_rx) = mpsc::unbounded_channel();
         let tasks = initial_tasks
             .iter()
             .enumerate()
             .map(|(i, name)| {
                 (
-                    Task::register(TaskId(i), name.clone(), events_tx.clone()),
+                    Task::register(
+                        TaskId(i),
+                        name.clone(),
+                        events_tx.clone(),
+                        config.registrations.as_ref(),
+                    ),
                     TaskState::NotStarted,
                 )
             })
             .collect();
+        let progress = initial_tasks
+            .iter()
+            .map(|name| TaskProgress {
+                deadline: config.task_deadlines.get(name).copied(),
+                started_at: None,
+                last_op: None,
+                serialization_failed: false,
+                pending_retry: None,
+                op_sites: config.unique_op_names.then(Vec::new),
+                next_seq: 0,
+            })
+            .collect();
 
         Self {
             tasks,
-            locked_state: LockedState::new(),
+            progress,
+            locked_state: LockedState::new(
+                config
+                    .lock_model
+                    .clone()
+                    .unwrap_or_else(|| Arc::new(SharedExclusive)),
+            ),
+            unique_keys: Vec::new(),
+            lock_queue: config.fair_locks.then(Vec::new),
+            rounds: 0,
             events_tx,
             events_rx,
+            pending_events: BTreeMap::new(),
+            finished_ops: Vec::new(),
+            last_executed: None,
+            detect_overlaps: config.detect_overlaps,
+            leak_cancelled_locks: config.leak_cancelled_locks,
+            fast_permits: config.fast_permits,
+            cancelled: Vec::new(),
         }
     }
 
-    pub(crate) async fn ready(&mut self, timeout: Duration) -> &[(Task, TaskState)] {
+    pub(crate) async fn ready(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<&[(Task, TaskState)], ControllerError> {
         let this = &mut *self;
         let result = tokio::time::timeout(timeout, async move {
             loop {
@@ -126,29 +432,113 @@ impl Controller {
                         };
                         *blocked_locks = this.locked_state.blocked(task.id(), locks);
                     }
-                    break;
+                    if this.lock_queue.is_some() {
+                        this.wait_in_lock_queue();
+                    }
+                    if this.fast_permits {
+                        this.grant_fast_permits();
+                    }
+                    return Ok(());
                 }
 
-                this.recv_event().await;
+                this.recv_event().await?;
             }
         })
         .await;
 
         match result {
-            Ok(()) => &self.tasks,
-            Err(Elapsed { .. }) => {
-                let tasks = self
+            Ok(result) => result.map(|()| self.tasks.as_slice()),
+            Err(Elapsed { .. }) => Err(ControllerError::TimedOut {
+                tasks: self
                     .tasks
                     .iter()
                     .map(|(task, state)| format!("task '{}': {state:?}", task.name().0))
-                    .collect::<Vec<_>>();
-                panic!("timed out, tasks: {tasks:#?}");
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Lets the last unfinished task start its next operations right away: with a single
+    /// choice at every step, waiting for a permit only adds a round trip. Not once locks of other
+    /// (cancelled) tasks are held, which may block the task.
+    fn grant_fast_permits(&self) {
+        let mut unfinished = self
+            .tasks
+            .iter()
+            .filter(|(_, state)| !matches!(state, TaskState::Finished));
+        let (Some((task, _)), None) = (unfinished.next(), unfinished.next()) else {
+            return;
+        };
+        if self
+            .locked_state
+            .scopes
+            .values()
+            .flatten()
+            .all(|(holder, _)| *holder == task.id())
+        {
+            task.grant_fast_permits();
+        }
+    }
+
+    /// Blocks tasks that could acquire their locks, but conflict with a task that was blocked
+    /// at an earlier round (and can acquire its locks now), so that contended scopes are acquired
+    /// in FIFO order. Order of tasks blocked at the same round is still explored.
+    fn wait_in_lock_queue(&mut self) {
+        let lock_queue = self.lock_queue.as_mut().expect("fair locks");
+        self.rounds += 1;
+        for (task, state) in &self.tasks {
+            if let TaskState::WaitingToStartOperation { blocked_locks, .. } = state {
+                if !blocked_locks.is_empty()
+                    && !lock_queue.iter().any(|(queued, _)| *queued == task.id())
+                {
+                    lock_queue.push((task.id(), self.rounds));
+                }
+            }
+        }
+
+        let eligible: Vec<(u64, TaskId, Vec<Lock>)> = self
+            .tasks
+            .iter()
+            .filter_map(|(task, state)| match state {
+                TaskState::WaitingToStartOperation {
+                    locks,
+                    blocked_locks,
+                    ..
+                } if blocked_locks.is_empty() => {
+                    let round = lock_queue
+                        .iter()
+                        .find_map(|(queued, round)| (*queued == task.id()).then_some(*round))
+                        .unwrap_or(u64::MAX);
+                    Some((round, task.id(), locks.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        for (round, id, locks) in &eligible {
+            let blocked: Vec<Lock> = eligible
+                .iter()
+                .filter(|(other_round, ..)| other_round < round)
+                .flat_map(|(_, _, other_locks)| {
+                    conflicting_locks(&*self.locked_state.model, locks, other_locks)
+                })
+                .collect();
+            if let TaskState::WaitingToStartOperation { blocked_locks, .. } =
+                &mut self.tasks[id.0].1
+            {
+                *blocked_locks = blocked;
             }
         }
     }
 
-    pub(crate) async fn step_forward(&mut self, id: TaskId) {
-        let (_, state) = &mut self.tasks[id.0];
+    pub(crate) async fn step_forward(
+        &mut self,
+        id: TaskId,
+        latency: Duration,
+        step_span: StepSpan,
+        inject_fault: bool,
+    ) -> Result<Option<StepTiming>, ControllerError> {
+        let (task, state) = &mut self.tasks[id.0];
 
         let prev = replace(state, TaskState::Invalid);
         let TaskState::WaitingToStartOperation {
@@ -156,33 +546,139 @@ impl Controller {
             permit,
             locks,
             blocked_locks,
+            fault,
+            ..
         } = prev
         else {
-            panic!("step_forward: task not waiting: {prev:?}");
+            return Err(ControllerError::NotWaiting {
+                task: task.name().clone(),
+                state: format!("{prev:?}"),
+            });
         };
+        if !blocked_locks.is_empty() {
+            return Err(ControllerError::BlockedByLocks {
+                task: task.name().clone(),
+                locks: blocked_locks,
+            });
+        }
         *state = TaskState::ExecutingOperation { metadata };
+        self.progress[id.0].last_op = Some(metadata);
+        if let Some(lock_queue) = &mut self.lock_queue {
+            lock_queue.retain(|(queued, _)| *queued != id);
+        }
+        self.locked_state.acquire_locks(id, &locks)?;
 
-        assert!(
-            blocked_locks.is_empty(),
-            "step_forward: blocked by locks: {blocked_locks:?}"
-        );
-        self.locked_state.acquire_locks(id, &locks);
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
 
-        // ignore error (channel closed)
-        let _ = permit.send(OperationPermit::Granted);
+        let granted_at = std::time::Instant::now();
+        self.last_executed = None;
+        if let Some(permit) = permit {
+            // ignore error (channel closed)
+            let _ = permit.send(OperationPermit::Granted {
+                step_span,
+                inject_fault,
+            });
+        }
 
         while matches!(self.tasks[id.0], (_, TaskState::ExecutingOperation { .. })) {
-            self.recv_event().await;
+            self.recv_event().await?;
         }
+        // not available if the operation was cancelled
+        let timing = self
+            .last_executed
+            .take()
+            .filter(|(task_id, ..)| *task_id == id)
+            .map(|(_, started_at, finished_at)| StepTiming {
+                granted_at: granted_at.min(started_at),
+                finished_at,
+            });
 
         self.locked_state.release_locks(id, &locks);
+
+        let progress = &mut self.progress[id.0];
+        if inject_fault
+            && fault
+                .as_ref()
+                .is_some_and(|fault| fault.fault == Fault::SerializationFailure)
+        {
+            progress.serialization_failed = true;
+            progress.pending_retry = Some(metadata);
+        } else if progress
+            .pending_retry
+            .is_some_and(|pending| ptr::eq(pending, metadata))
+        {
+            progress.pending_retry = None;
+        }
+
+        if let Some(key) = fault.and_then(|fault| fault.unique_key) {
+            if !inject_fault {
+                self.unique_keys.push((id, key));
+                for (task, state) in &mut self.tasks {
+                    if let TaskState::WaitingToStartOperation {
+                        fault,
+                        fault_injectable,
+                        ..
+                    } = state
+                    {
+                        *fault_injectable |=
+                            unique_key_taken(&self.unique_keys, task.id(), fault.as_ref());
+                    }
+                }
+            }
+        }
+        Ok(timing)
     }
 
     pub(crate) fn tasks(&self) -> &[(Task, TaskState)] {
         &self.tasks
     }
 
-    pub(crate) fn assert_finished(&self) {
+    /// Lock usage of scopes in this iteration.
+    pub(crate) fn scope_stats(&self) -> HashMap<String, ScopeStats> {
+        let mut stats = HashMap::<String, ScopeStats>::new();
+        for (scope, scope_stats) in &self.locked_state.stats {
+            stats
+                .entry(scope.name().to_owned())
+                .or_default()
+                .merge(scope_stats);
+        }
+        stats
+    }
+
+    /// Tasks holding a lock on `scope`.
+    pub(crate) fn holders(&self, scope: &ScopeId) -> &[(TaskId, Mode)] {
+        self.locked_state
+            .scopes
+            .get(scope)
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Tasks holding a lock on scopes named `name`.
+    pub(crate) fn holders_by_name(&self, name: &str) -> Vec<(TaskId, Mode)> {
+        self.locked_state
+            .scopes
+            .iter()
+            .filter(|(scope, _)| scope.name() == name)
+            .flat_map(|(_, holders)| holders.iter().copied())
+            .collect()
+    }
+
+    /// Whether another task can't acquire a scope in `requested` mode while it's `held`.
+    pub(crate) fn conflicts(&self, held: Mode, requested: Mode) -> bool {
+        self.locked_state.conflicts(held, requested)
+    }
+
+    /// Tasks holding locks that conflict with locks requested by (waiting) task `id`.
+    pub(crate) fn blockers(&self, id: TaskId) -> Vec<TaskId> {
+        let TaskState::WaitingToStartOperation { locks, .. } = &self.tasks[id.0].1 else {
+            return Vec::new();
+        };
+        self.locked_state.blockers(id, locks)
+    }
+
+    pub(crate) fn check_finished(&self) -> Result<(), ControllerError> {
         let unfinished = self
             .tasks
             .iter()
@@ -195,87 +691,332 @@ impl Controller {
             })
             .collect::<Vec<_>>();
 
-        assert!(
-            unfinished.is_empty(),
-            "some tasks did not finish: {unfinished:#?}"
-        );
+        if unfinished.is_empty() {
+            Ok(())
+        } else {
+            Err(ControllerError::Unfinished { tasks: unfinished })
+        }
     }
 
-    async fn recv_event(&mut self) {
-        // Channel can't be closed here because controller keeps a sender too.
-        let (id, event) = self.events_rx.recv().await.expect("channel closed");
+    /// Tasks that finished the iteration without executing any operation.
+    pub(crate) fn idle_tasks(&self) -> Vec<TaskName> {
+        self.tasks
+            .iter()
+            .zip(&self.progress)
+            .filter(|(_, progress)| progress.last_op.is_none())
+            .map(|((task, _), _)| task.name().clone())
+            .collect()
+    }
+
+    pub(crate) fn check_executed_operations(&self) -> Result<(), ControllerError> {
+        match self.idle_tasks().into_iter().next() {
+            Some(task) => Err(ControllerError::NoOperations { task }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that nothing controlled outlived the iteration: no events were sent after all tasks
+    /// finished and no futures still hold on to any of the tasks.
+    pub(crate) fn check_no_leaks(&mut self) -> Result<(), ControllerError> {
+        self.drain_events();
+        if let Some(((id, _), event)) = self.pending_events.pop_first() {
+            let (task, _) = &self.tasks[id.0];
+            return Err(ControllerError::EventAfterFinish {
+                task: task.name().clone(),
+                event: format!("{event:?}"),
+            });
+        }
+
+        for ((task, _), progress) in self.tasks.iter().zip(&self.progress) {
+            if task.is_shared() {
+                return Err(ControllerError::StillAlive {
+                    task: task.name().clone(),
+                    last_op: progress.last_op,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves events that already arrived to `pending_events`.
+    fn drain_events(&mut self) {
+        while let Ok(received) = self.events_rx.try_recv() {
+            self.pending_events
+                .insert((received.task_id, received.seq), received.event);
+        }
+    }
+
+    /// Takes the first pending event that is next in sequence of its task.
+    fn next_event(&mut self) -> Option<(TaskId, TaskEvent)> {
+        let key = *self
+            .pending_events
+            .keys()
+            .find(|(id, seq)| self.progress[id.0].next_seq == *seq)?;
+        self.progress[key.0 .0].next_seq += 1;
+        self.pending_events.remove(&key).map(|event| (key.0, event))
+    }
+
+    /// Waits for the next event in order (see `pending_events`).
+    async fn receive(&mut self) -> Result<(TaskId, TaskEvent), ControllerError> {
+        loop {
+            self.drain_events();
+            if let Some(next) = self.next_event() {
+                return Ok(next);
+            }
+
+            // Channel can't be closed here because controller keeps a sender too.
+            let received = match self.nearest_deadline() {
+                Some((id, deadline)) => {
+                    match tokio::time::timeout_at(deadline, self.events_rx.recv()).await {
+                        Ok(received) => received,
+                        Err(Elapsed { .. }) => return Err(self.deadline_exceeded(id)),
+                    }
+                }
+                None => self.events_rx.recv().await,
+            };
+            let received = received.expect("channel closed");
+            self.pending_events
+                .insert((received.task_id, received.seq), received.event);
+        }
+    }
+
+    /// Records finished operations. Fails if an operation started before the last finished operation of
+    /// another task finished or, with `detect_overlaps`, if it overlaps any operation finished
+    /// in this iteration.
+    fn record_finished(&mut self, id: TaskId, event: &TaskEvent) -> Result<(), ControllerError> {
+        let TaskEvent::OperationFinished {
+            metadata,
+            started_at,
+            finished_at,
+        } = event
+        else {
+            return Ok(());
+        };
+        let next = FinishedOperation {
+            task_id: id,
+            task: self.tasks[id.0].0.name().clone(),
+            metadata,
+            started_at: *started_at,
+            finished_at: *finished_at,
+        };
+        let overlapping = if self.detect_overlaps {
+            self.finished_ops.iter().find(|op| op.overlaps(&next))
+        } else {
+            self.finished_ops
+                .last()
+                .filter(|last| last.task_id != next.task_id && next.started_at < last.finished_at)
+        };
+        if let Some(op) = overlapping {
+            let (first, second) = if op.started_at <= next.started_at {
+                ((op.task.clone(), op.metadata), (next.task, next.metadata))
+            } else {
+                ((next.task, next.metadata), (op.task.clone(), op.metadata))
+            };
+            return Err(ControllerError::ParallelOperations { first, second });
+        }
+
+        if !self.detect_overlaps {
+            self.finished_ops.clear();
+        }
+        self.last_executed = Some((id, next.started_at, next.finished_at));
+        self.finished_ops.push(next);
+        Ok(())
+    }
+
+    async fn recv_event(&mut self) -> Result<(), ControllerError> {
+        let (id, event) = self.receive().await?;
+        self.record_finished(id, &event)?;
+
         let (task, state) = &mut self.tasks[id.0];
         *state = match event {
-            TaskEvent::TaskStarted => TaskState::ExecutingOutsideOperation,
+            TaskEvent::TaskStarted => {
+                self.progress[id.0].started_at = Some(Instant::now());
+                TaskState::ExecutingOutsideOperation
+            }
             TaskEvent::OperationPermitRequested {
                 metadata,
                 permit,
                 locks,
+                backtrace,
+                fault,
             } => {
                 if let TaskState::ExecutingOperation { metadata: other } = state {
-                    let _ = permit
-                        .send(super::task::OperationPermit::OperationAlreadyInProgress { other });
-                    return;
+                    let _ = permit.map(|permit| {
+                        permit.send(OperationPermit::OperationAlreadyInProgress { other })
+                    });
+                    return Ok(());
                 };
 
+                if let Some(op_sites) = &mut self.progress[id.0].op_sites {
+                    if let Some(other) = op_sites
+                        .iter()
+                        .find(|other| other.name == metadata.name && !ptr::eq(**other, metadata))
+                    {
+                        return Err(ControllerError::DuplicateOperationName {
+                            task: task.name().clone(),
+                            first: other,
+                            second: metadata,
+                        });
+                    }
+                    if !op_sites.iter().any(|other| ptr::eq(*other, metadata)) {
+                        op_sites.push(metadata);
+                    }
+                }
+
                 TaskState::WaitingToStartOperation {
                     metadata,
                     permit,
                     blocked_locks: Vec::new(),
                     locks,
+                    backtrace,
+                    fault_injectable: match &fault {
+                        None => false,
+                        Some(Fallible {
+                            unique_key: Some(_),
+                            ..
+                        }) => unique_key_taken(&self.unique_keys, id, fault.as_ref()),
+                        Some(Fallible {
+                            fault: Fault::SerializationFailure,
+                            ..
+                        }) => !self.progress[id.0].serialization_failed,
+                        Some(_) => true,
+                    },
+                    fault,
                 }
             }
-            TaskEvent::OperationFinished => {
+            TaskEvent::OperationFinished { .. } => {
                 let TaskState::ExecutingOperation { .. } = state else {
-                    panic!(
-                        "task '{}': received OperationFinished when not inside operation",
-                        task.name().0
-                    );
+                    return Err(ControllerError::UnexpectedOperationFinished {
+                        task: task.name().clone(),
+                    });
                 };
 
                 TaskState::ExecutingOutsideOperation
             }
             TaskEvent::TaskFinished => {
-                let locks = self.locked_state.acquired_locks(id);
-                assert!(
-                    locks.is_empty(),
-                    "task '{}': finished without releasing locks: {locks:?}",
-                    task.name().0
-                );
+                let scopes = self.locked_state.acquired_locks(id);
+                if !scopes.is_empty() {
+                    return Err(ControllerError::UnreleasedLocks {
+                        task: task.name().clone(),
+                        scopes,
+                    });
+                }
+
+                let progress = &self.progress[id.0];
+                if let Some(operation) = progress.pending_retry {
+                    return Err(ControllerError::MissingRetry {
+                        task: task.name().clone(),
+                        operation,
+                    });
+                }
+                if let (Some(deadline), Some(started_at)) = (progress.deadline, progress.started_at)
+                {
+                    if started_at.elapsed() > deadline {
+                        return Err(self.deadline_exceeded(id));
+                    }
+                }
+                TaskState::Finished
+            }
+            TaskEvent::TaskCancelled => {
+                if !self.leak_cancelled_locks {
+                    self.locked_state.release_all(id);
+                }
+                self.progress[id.0].pending_retry = None;
+                self.cancelled.push(id);
                 TaskState::Finished
             }
         };
+        Ok(())
+    }
+
+    /// Tasks that were cancelled since the last call.
+    pub(crate) fn take_cancelled(&mut self) -> Vec<TaskId> {
+        mem::take(&mut self.cancelled)
+    }
+
+    fn nearest_deadline(&self) -> Option<(TaskId, Instant)> {
+        self.tasks
+            .iter()
+            .zip(&self.progress)
+            .filter(|((_, state), _)| !matches!(state, TaskState::Finished))
+            .filter_map(|((task, _), progress)| {
+                Some((task.id(), progress.started_at? + progress.deadline?))
+            })
+            .min_by_key(|(_, deadline)| *deadline)
+    }
+
+    fn deadline_exceeded(&self, id: TaskId) -> ControllerError {
+        let (task, _) = &self.tasks[id.0];
+        let progress = &self.progress[id.0];
+        ControllerError::DeadlineExceeded {
+            task: task.name().clone(),
+            deadline: progress.deadline.unwrap_or_default(),
+            last_op: progress.last_op,
+        }
     }
 }
 
-#[derive(Debug)]
 struct LockedState {
-    scopes: HashMap<String, Vec<(TaskId, Mode)>>,
+    scopes: HashMap<ScopeId, Vec<(TaskId, Mode)>>,
+    stats: HashMap<ScopeId, ScopeStats>,
+    model: Arc<dyn LockModel>,
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
-enum Mode {
+pub(crate) enum Mode {
     Shared,
     Exclusive,
+    Custom(&'static str),
+}
+
+impl Mode {
+    /// Scope and mode the lock acquires, `None` for releases.
+    pub(crate) fn of(lock: &Lock) -> Option<(&ScopeId, Mode)> {
+        match lock {
+            TypedLock::AcquireShared { scope } => Some((scope, Mode::Shared)),
+            TypedLock::AcquireExclusive { scope } => Some((scope, Mode::Exclusive)),
+            TypedLock::Acquire { scope, mode } => Some((scope, Mode::Custom(mode))),
+            TypedLock::Release { .. } => None,
+        }
+    }
+}
+
+impl fmt::Debug for LockedState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockedState")
+            .field("scopes", &self.scopes)
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
 }
 
 impl LockedState {
-    fn new() -> Self {
+    fn new(model: Arc<dyn LockModel>) -> Self {
         Self {
             scopes: HashMap::default(),
+            stats: HashMap::default(),
+            model,
         }
     }
 
-    fn blocked(&self, task_id: TaskId, locks: &[ParcheckLock]) -> Vec<ParcheckLock> {
+    fn conflicts(&self, held: Mode, requested: Mode) -> bool {
+        !self.model.compatible(held.into(), requested.into())
+    }
+
+    fn has_conflict(&self, task_id: TaskId, mode: Mode, holders: &[(TaskId, Mode)]) -> bool {
+        holders.iter().any(|(holder_task_id, holder_mode)| {
+            *holder_task_id != task_id && self.conflicts(*holder_mode, mode)
+        })
+    }
+
+    fn blocked(&self, task_id: TaskId, locks: &[Lock]) -> Vec<Lock> {
         let mut blockers = Vec::new();
         for lock in locks {
-            let (scope, mode) = match lock {
-                ParcheckLock::AcquireShared { scope } => (scope, Mode::Shared),
-                ParcheckLock::AcquireExclusive { scope } => (scope, Mode::Exclusive),
-                ParcheckLock::Release { .. } => continue,
+            let Some((scope, mode)) = Mode::of(lock) else {
+                continue;
             };
             if let Some(holders) = self.scopes.get(scope) {
-                if has_conflict(task_id, mode, holders) {
+                if self.has_conflict(task_id, mode, holders) {
                     blockers.push(lock.clone());
                 }
             }
@@ -284,40 +1025,77 @@ impl LockedState {
         blockers
     }
 
-    fn acquire_locks(&mut self, task_id: TaskId, locks: &[ParcheckLock]) {
+    fn blockers(&self, task_id: TaskId, locks: &[Lock]) -> Vec<TaskId> {
+        let mut blockers = Vec::new();
         for lock in locks {
-            let (scope, mode) = match lock {
-                ParcheckLock::AcquireShared { scope } => (scope, Mode::Shared),
-                ParcheckLock::AcquireExclusive { scope } => (scope, Mode::Exclusive),
-                ParcheckLock::Release { .. } => continue,
+            let Some((scope, mode)) = Mode::of(lock) else {
+                continue;
             };
+            for (holder_task_id, holder_mode) in self.scopes.get(scope).into_iter().flatten() {
+                if *holder_task_id != task_id
+                    && self.conflicts(*holder_mode, mode)
+                    && !blockers.contains(holder_task_id)
+                {
+                    blockers.push(*holder_task_id);
+                }
+            }
+        }
+        blockers
+    }
+
+    fn acquire_locks(&mut self, task_id: TaskId, locks: &[Lock]) -> Result<(), ControllerError> {
+        for lock in locks {
+            let Some((scope, mode)) = Mode::of(lock) else {
+                continue;
+            };
+
+            if self
+                .scopes
+                .get(scope)
+                .is_some_and(|holders| self.has_conflict(task_id, mode, holders))
+            {
+                return Err(ControllerError::LockConflict {
+                    scope: scope.name().to_owned(),
+                });
+            }
 
             let holders = self.scopes.entry(scope.clone()).or_default();
-            assert!(
-                !has_conflict(task_id, mode, holders),
-                "acquire_locks() acquire lock conflict on {scope}"
-            );
 
+            let stats = self.stats.entry(scope.clone()).or_default();
             if let Some((_, holder_mode)) = holders
                 .iter_mut()
                 .find(|(holder_task_id, _)| *holder_task_id == task_id)
             {
-                if mode == Mode::Exclusive {
+                // shared locks don't downgrade held ones, nothing upgrades exclusive ones
+                if mode != *holder_mode && mode != Mode::Shared && *holder_mode != Mode::Exclusive {
                     *holder_mode = mode;
+                    if mode == Mode::Exclusive {
+                        stats.exclusive_acquisitions += 1;
+                    }
                 }
             } else {
                 holders.push((task_id, mode));
+                if mode == Mode::Exclusive {
+                    stats.exclusive_acquisitions += 1;
+                }
             }
+
+            let shared_holders = holders
+                .iter()
+                .filter(|(_, holder_mode)| *holder_mode == Mode::Shared)
+                .count();
+            stats.max_shared_holders = stats.max_shared_holders.max(shared_holders);
         }
+        Ok(())
     }
 
-    fn release_locks(&mut self, task_id: TaskId, locks: &[ParcheckLock]) {
+    fn release_locks(&mut self, task_id: TaskId, locks: &[Lock]) {
         for lock in locks {
             let scope = match lock {
-                ParcheckLock::AcquireShared { .. } | ParcheckLock::AcquireExclusive { .. } => {
-                    continue
-                }
-                ParcheckLock::Release { scope } => scope,
+                TypedLock::AcquireShared { .. }
+                | TypedLock::AcquireExclusive { .. }
+                | TypedLock::Acquire { .. } => continue,
+                TypedLock::Release { scope } => scope,
             };
 
             let Some(holders) = self.scopes.get_mut(scope) else {
@@ -332,6 +1110,12 @@ impl LockedState {
         }
     }
 
+    fn release_all(&mut self, task_id: TaskId) {
+        for holders in self.scopes.values_mut() {
+            holders.retain(|(holder_task_id, _)| *holder_task_id != task_id);
+        }
+    }
+
     fn acquired_locks(&self, task_id: TaskId) -> Vec<String> {
         self.scopes
             .iter()
@@ -340,18 +1124,40 @@ impl LockedState {
                     .iter()
                     .any(|(holder_task_id, _)| *holder_task_id == task_id)
             })
-            .map(|(scope, _)| scope.clone())
+            .map(|(scope, _)| scope.name().to_owned())
             .collect()
     }
 }
 
-fn has_conflict(task_id: TaskId, mode: Mode, holders: &[(TaskId, Mode)]) -> bool {
-    for (holder_task_id, holder_mode) in holders {
-        if *holder_task_id != task_id
-            && (*holder_mode == Mode::Exclusive || mode == Mode::Exclusive)
-        {
-            return true;
-        }
-    }
-    false
+/// Locks from `locks` that can't be acquired while `other_locks` are held by another task.
+fn conflicting_locks(model: &dyn LockModel, locks: &[Lock], other_locks: &[Lock]) -> Vec<Lock> {
+    locks
+        .iter()
+        .filter(|lock| {
+            let Some((scope, mode)) = Mode::of(lock) else {
+                return false;
+            };
+            other_locks
+                .iter()
+                .filter_map(Mode::of)
+                .any(|(other_scope, other_mode)| {
+                    other_scope == scope && !model.compatible(other_mode.into(), mode.into())
+                })
+        })
+        .cloned()
+        .collect()
+}
+
+/// Whether unique key of the operation was already taken by another task.
+fn unique_key_taken(
+    unique_keys: &[(TaskId, Box<str>)],
+    task_id: TaskId,
+    fault: Option<&Fallible>,
+) -> bool {
+    let Some(key) = fault.and_then(|fault| fault.unique_key.as_ref()) else {
+        return false;
+    };
+    unique_keys
+        .iter()
+        .any(|(other_task_id, other_key)| *other_task_id != task_id && other_key == key)
 }
@@ -0,0 +1,102 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use futures_util::future::{join_all, BoxFuture};
+
+use crate::{
+    enabled::{
+        operation::{faulty_operation, operation, Fault, OperationMetadata},
+        report::Report,
+        runner::runner,
+        scope_id,
+        task::task,
+    },
+    ParcheckLock,
+};
+
+/// Job queue consumed by workers in [`check`]. Implementation is expected to be instrumented with
+/// `parcheck::operation!`, so that claims and acks of different workers are interleaved.
+pub trait JobQueue {
+    /// Claims a job for `worker` and returns its id, or `None` when there are no jobs left. A
+    /// worker that crashed keeps its id when restarted.
+    fn claim(&self, worker: usize) -> BoxFuture<'_, Option<String>>;
+
+    /// Marks job claimed by `worker` as done.
+    fn ack<'a>(&'a self, worker: usize, job: &'a str) -> BoxFuture<'a, ()>;
+}
+
+static PROCESS: OperationMetadata = OperationMetadata::new("process", file!(), line!());
+
+/// Checks that every job is processed exactly once when consumed by `workers` concurrent workers.
+///
+/// Each worker (task `worker:<index>`) claims jobs until the queue is empty, processes each of
+/// them (operation `process`, holding exclusive lock on scope `job:<id>`) and acks it. Schedules
+/// where a worker crashes after claiming a job and before processing it are explored as well: a
+/// crashed worker is restarted, at most once per iteration. `new_queue` is called with all job
+/// ids to create a queue for every iteration.
+pub async fn check<I, Q, N>(workers: usize, jobs: I, new_queue: N) -> Report
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+    Q: JobQueue,
+    N: Fn(&[String]) -> Q,
+{
+    let jobs: Vec<String> = jobs
+        .into_iter()
+        .map(|job| job.as_ref().to_owned())
+        .collect();
+    let names: Vec<String> = (0..workers)
+        .map(|worker| format!("worker:{worker}"))
+        .collect();
+
+    runner()
+        .run(&names, || async {
+            let queue = new_queue(&jobs);
+            let processed = Mutex::new(HashMap::<String, usize>::new());
+
+            join_all(
+                names
+                    .iter()
+                    .enumerate()
+                    .map(|(worker, name)| task(name, consume(&queue, worker, &processed))),
+            )
+            .await;
+
+            let processed = processed.into_inner().unwrap();
+            for job in &jobs {
+                match processed.get(job).copied().unwrap_or(0) {
+                    0 => panic!("job '{job}' was lost"),
+                    1 => {}
+                    n => panic!("job '{job}' was processed {n} times"),
+                }
+            }
+        })
+        .await
+}
+
+async fn consume<Q: JobQueue>(queue: &Q, worker: usize, processed: &Mutex<HashMap<String, usize>>) {
+    let mut crashed = false;
+    while let Some(job) = queue.claim(worker).await {
+        let scope = crate::scope!("job", job);
+        let locks = vec![
+            ParcheckLock::AcquireExclusive {
+                scope: scope.clone(),
+            },
+            ParcheckLock::Release { scope },
+        ];
+        let process = async {
+            *processed.lock().unwrap().entry(job.clone()).or_default() += 1;
+        };
+
+        if crashed {
+            operation(&PROCESS, locks, process).await;
+        } else if faulty_operation(&PROCESS, scope_id::erase(locks), Fault::Crash, process)
+            .await
+            .is_err()
+        {
+            crashed = true;
+            continue;
+        }
+
+        queue.ack(worker, &job).await;
+    }
+}
@@ -0,0 +1,203 @@
+use std::{error::Error, fmt, time::Duration};
+
+use crate::enabled::{
+    controller::{Controller, ControllerConfig, ControllerError},
+    random::Rng,
+    report::Report,
+    runner::WAIT_TIMEOUT,
+    schedule_tree::{Choice, PathCursor, ScheduleTree},
+    strategy::CostConfig,
+    task::{StepSpan, TaskName},
+    trace::{Cancellation, OperationName, Trace, TraceStep},
+};
+
+/// Exhaustive exploration of schedules driven by the caller, for harnesses that need to own the
+/// loop instead of passing a closure to [`Runner::run`](crate::Runner::run).
+///
+/// Every [`Schedule`] returned by [`Exploration::next_schedule`] expects the initial tasks to be
+/// started (with `parcheck::task`) and polled concurrently with [`Schedule::step`]:
+///
+/// ```ignore
+/// let mut exploration = parcheck::Exploration::new(["a", "b"]);
+/// while let Some(mut schedule) = exploration.next_schedule() {
+///     let steps = async {
+///         while schedule.step().await?.is_some() {}
+///         Ok::<_, parcheck::ScheduleError>(())
+///     };
+///     let (steps, ()) = tokio::join!(steps, scenario());
+///     steps?;
+///     schedule.finish()?;
+/// }
+/// ```
+pub struct Exploration {
+    initial_tasks: Vec<TaskName>,
+    config: ControllerConfig,
+    tree: ScheduleTree,
+    rng: Rng,
+    report: Report,
+}
+
+impl Exploration {
+    pub fn new<I>(initial_tasks: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let initial_tasks: Vec<TaskName> = initial_tasks
+            .into_iter()
+            .map(|name| TaskName(name.as_ref().into()))
+            .collect();
+        Self {
+            tree: ScheduleTree::new(&initial_tasks, CostConfig::default()),
+            initial_tasks,
+            config: ControllerConfig::default(),
+            rng: Rng::new(),
+            report: Report::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::with_seed(seed);
+        self
+    }
+
+    /// Starts the next unexplored schedule, `None` once every schedule was explored. Tasks
+    /// started before this call aren't controlled by the schedule.
+    pub fn next_schedule(&mut self) -> Option<Schedule<'_>> {
+        let rng = self.rng.fork();
+        let cursor = self.tree.pick_unfinished_path(&mut self.rng)?;
+        Some(Schedule {
+            controller: Controller::register(&self.initial_tasks, &self.config),
+            trace: Trace::new(),
+            cursor,
+            rng,
+            report: &mut self.report,
+        })
+    }
+
+    /// Whether every schedule was explored.
+    #[must_use]
+    pub fn exhausted(&self) -> bool {
+        !self.tree.has_unfinished_paths()
+    }
+
+    /// Summary of finished schedules.
+    #[must_use]
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+}
+
+/// A single schedule of an [`Exploration`], stepped manually.
+pub struct Schedule<'a> {
+    controller: Controller,
+    trace: Trace,
+    cursor: PathCursor<'a>,
+    rng: Rng,
+    report: &'a mut Report,
+}
+
+impl Schedule<'_> {
+    /// Waits until every task either waits for an operation or finishes, then allows one of the
+    /// operations to execute and waits for it to finish. Returns the executed step, or `None` if
+    /// all tasks finished.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ScheduleError`] if tasks don't behave as expected (e.g. they don't start in time
+    /// or leave locks unreleased).
+    pub async fn step(&mut self) -> Result<Option<&TraceStep>, ScheduleError> {
+        self.controller.ready(WAIT_TIMEOUT).await?;
+        for task_id in self.controller.take_cancelled() {
+            self.trace.cancellations.push(Cancellation {
+                step: self.trace.steps.len(),
+                task_id,
+                task_name: self.controller.tasks()[task_id.0].0.name().clone(),
+            });
+        }
+
+        let tasks = self.controller.tasks();
+        let Some(Choice {
+            task_id,
+            inject_fault,
+        }) = self.cursor.visit_and_pick(tasks, &mut self.rng)
+        else {
+            self.controller.check_finished()?;
+            return Ok(None);
+        };
+
+        let (task, state) = &tasks[task_id.0];
+        let metadata = state
+            .executable_op()
+            .ok_or(ControllerError::NotExecutable { task_id })?;
+        self.trace.steps.push(TraceStep {
+            task_id: Some(task_id),
+            task_name: task.name().clone(),
+            op_name: OperationName::Static(metadata.name()),
+            metadata: Some(metadata),
+            backtrace: state.backtrace().cloned(),
+            timing: None,
+            fault: if inject_fault {
+                state.injectable_fault()
+            } else {
+                None
+            },
+            candidates: Vec::new(),
+        });
+        let timing = self
+            .controller
+            .step_forward(task_id, Duration::ZERO, StepSpan::default(), inject_fault)
+            .await?;
+        let step = self.trace.steps.last_mut().unwrap();
+        step.timing = timing;
+        Ok(Some(step))
+    }
+
+    /// Steps executed so far.
+    #[must_use]
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    /// Checks that tasks released their locks and records the schedule in the
+    /// [report](Exploration::report). Call once [`Schedule::step`] returns `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ScheduleError`] if tasks didn't finish or leaked locks.
+    pub fn finish(mut self) -> Result<Trace, ScheduleError> {
+        let result = self
+            .controller
+            .check_finished()
+            .and_then(|()| self.controller.check_no_leaks());
+        self.report.iterations += 1;
+        self.report.record_scopes(&self.controller.scope_stats());
+        self.report.record(&self.trace, result.is_err());
+        result?;
+        Ok(self.trace)
+    }
+}
+
+/// Tasks of a [`Schedule`] misbehaved (see [`Schedule::step`]).
+pub struct ScheduleError(ControllerError);
+
+impl From<ControllerError> for ScheduleError {
+    fn from(error: ControllerError) -> Self {
+        Self(error)
+    }
+}
+
+impl fmt::Debug for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ScheduleError {}
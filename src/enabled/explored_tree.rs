@@ -0,0 +1,139 @@
+use std::{fmt, ops::Range};
+
+use crate::enabled::task::TaskName;
+
+/// Schedule tree at the end of a run, see [`Report::schedule_tree`](crate::Report::schedule_tree).
+/// The root is the start of an iteration, every other node is reached by executing a single step
+/// (an operation of a task, or its injected fault) after its parent.
+#[derive(Debug, Clone)]
+pub struct ExploredTree {
+    pub(crate) tasks: Vec<TaskName>,
+    pub(crate) nodes: Vec<ExploredNode>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ExploredNode {
+    pub(crate) step: Option<ExploredStep>,
+    pub(crate) status: NodeStatus,
+    pub(crate) children: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ExploredStep {
+    pub(crate) task: usize,
+    pub(crate) operation: Option<&'static str>,
+    pub(crate) inject_fault: bool,
+}
+
+/// Whether a node of [`ExploredTree`] was reached by some iteration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeStatus {
+    Visited,
+    /// Reachable, but no iteration got there (out of iterations, or pruned by
+    /// [`Strategy::ShallowFirst`](crate::Strategy::ShallowFirst)).
+    Unvisited,
+    /// Step couldn't be taken, e.g. because the task finished or was blocked by locks.
+    Unreachable {
+        reason: &'static str,
+    },
+}
+
+/// Node of [`ExploredTree`].
+#[derive(Clone, Copy)]
+pub struct TreeNode<'a> {
+    tree: &'a ExploredTree,
+    id: usize,
+}
+
+impl ExploredTree {
+    #[must_use]
+    pub fn root(&self) -> TreeNode<'_> {
+        TreeNode { tree: self, id: 0 }
+    }
+
+    /// Number of nodes, including unvisited and unreachable ones.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl<'a> TreeNode<'a> {
+    /// Name of the task that executed the step leading to this node, `None` for the root.
+    #[must_use]
+    pub fn task(&self) -> Option<&'a str> {
+        self.step().map(|step| &*self.tree.tasks[step.task].0)
+    }
+
+    /// Operation executed by the step, `None` for the root and for steps of finished tasks.
+    #[must_use]
+    pub fn operation(&self) -> Option<&'static str> {
+        self.step().and_then(|step| step.operation)
+    }
+
+    /// Whether the step injected the operation's fault instead of executing it.
+    #[must_use]
+    pub fn injects_fault(&self) -> bool {
+        self.step().is_some_and(|step| step.inject_fault)
+    }
+
+    #[must_use]
+    pub fn status(&self) -> NodeStatus {
+        self.node().status
+    }
+
+    /// Steps that can follow this one, in the order of task ids followed by injected faults.
+    /// Empty unless the node was visited.
+    pub fn children(&self) -> impl Iterator<Item = TreeNode<'a>> + 'a {
+        let tree = self.tree;
+        self.node()
+            .children
+            .clone()
+            .map(move |id| TreeNode { tree, id })
+    }
+
+    /// Child reached by executing (not faulting) the operation of `task`.
+    #[must_use]
+    pub fn child(&self, task: &str) -> Option<TreeNode<'a>> {
+        self.children()
+            .find(|child| child.task() == Some(task) && !child.injects_fault())
+    }
+
+    /// Whether no iteration is left to explore in the subtree, i.e. none of its nodes is
+    /// [`NodeStatus::Unvisited`].
+    #[must_use]
+    pub fn fully_explored(&self) -> bool {
+        let mut stack = vec![*self];
+        while let Some(node) = stack.pop() {
+            if node.status() == NodeStatus::Unvisited {
+                return false;
+            }
+            stack.extend(node.children());
+        }
+        true
+    }
+
+    fn node(&self) -> &'a ExploredNode {
+        &self.tree.nodes[self.id]
+    }
+
+    fn step(&self) -> Option<ExploredStep> {
+        self.node().step
+    }
+}
+
+impl fmt::Debug for TreeNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TreeNode")
+            .field("task", &self.task())
+            .field("operation", &self.operation())
+            .field("injects_fault", &self.injects_fault())
+            .field("status", &self.status())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt;
+
+use crate::enabled::trace::Trace;
+
+/// Group of a failed iteration and what the runner does about it, decided by
+/// [`Runner::classify_failure`](crate::Runner::classify_failure).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailureClass {
+    name: String,
+    action: FailureAction,
+}
+
+/// What the runner does after an iteration failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureAction {
+    /// Reports the schedule and fails the run, as without a classifier.
+    Fail,
+    /// Counts the failure and continues exploring other schedules.
+    Continue,
+    /// Executes the same schedule again (up to 3 times by default, then the run fails, see
+    /// [`Runner::retry_on`](crate::Runner::retry_on)), e.g. after an error of the test
+    /// environment.
+    Retry,
+}
+
+impl FailureClass {
+    #[must_use]
+    pub fn new(name: impl Into<String>, action: FailureAction) -> Self {
+        Self {
+            name: name.into(),
+            action,
+        }
+    }
+
+    /// Real bug, fails the run.
+    #[must_use]
+    pub fn bug(name: impl Into<String>) -> Self {
+        Self::new(name, FailureAction::Fail)
+    }
+
+    /// Known or accepted failure, exploration continues.
+    #[must_use]
+    pub fn known(name: impl Into<String>) -> Self {
+        Self::new(name, FailureAction::Continue)
+    }
+
+    /// Failure of the infrastructure (e.g. `connection refused`), the schedule is retried.
+    #[must_use]
+    pub fn infrastructure(name: impl Into<String>) -> Self {
+        Self::new(name, FailureAction::Retry)
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn action(&self) -> FailureAction {
+        self.action
+    }
+}
+
+impl fmt::Display for FailureClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// Failed iterations of the same [`FailureClass`], see
+/// [`Report::failure_classes`](crate::Report::failure_classes).
+#[derive(Debug, Clone)]
+pub struct ClassifiedFailures {
+    pub(crate) class: FailureClass,
+    pub(crate) failures: u64,
+    pub(crate) example: Trace,
+}
+
+impl ClassifiedFailures {
+    #[must_use]
+    pub fn class(&self) -> &FailureClass {
+        &self.class
+    }
+
+    /// Number of failed iterations, including retried ones.
+    #[must_use]
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+
+    /// Schedule of the first failed iteration.
+    #[must_use]
+    pub fn example(&self) -> &Trace {
+        &self.example
+    }
+}
+
+impl fmt::Display for ClassifiedFailures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} failures (e.g. {})",
+            self.class, self.failures, self.example
+        )
+    }
+}
@@ -0,0 +1,112 @@
+use std::{env, fmt::Write as _, fs, path::PathBuf};
+
+use crate::enabled::{
+    nextest,
+    trace::{Trace, TraceStep},
+};
+
+/// Most failed schedules kept in a history file. A new schedule replaces the oldest of the ones
+/// that failed least often.
+const MAX_SCHEDULES: usize = 100;
+
+/// Schedules that failed in previous runs (with number of failures), stored in a file: one
+/// `<failures> <trace>` line per schedule. See [`Runner::failure_history`](crate::Runner::failure_history).
+pub(crate) struct FailureHistory {
+    path: PathBuf,
+    failed: Vec<(Trace, u64)>,
+}
+
+impl FailureHistory {
+    /// Reads the history, empty if the file doesn't exist yet. Lines that can't be parsed (e.g.
+    /// written by a different version) are skipped.
+    pub(crate) fn load(path: PathBuf) -> Self {
+        let failed = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let (failures, trace) = line.split_once(' ')?;
+                Some((trace.parse().ok()?, failures.parse().ok()?))
+            })
+            .collect();
+        Self { path, failed }
+    }
+
+    /// History of the current test in the target directory (`CARGO_TARGET_DIR` or `target`),
+    /// `None` if the test thread is unnamed.
+    pub(crate) fn for_current_test() -> Option<Self> {
+        let test = nextest::current_test()?;
+        let target = env::var_os("CARGO_TARGET_DIR").map_or_else(|| "target".into(), PathBuf::from);
+        Some(Self::load(
+            target
+                .join("parcheck-history")
+                .join(format!("{test}.history")),
+        ))
+    }
+
+    pub(crate) fn failed(&self) -> &[(Trace, u64)] {
+        &self.failed
+    }
+
+    /// Records a failed schedule and writes the history (replacing the file at once, so that a
+    /// concurrent or interrupted run doesn't leave a partially written file behind).
+    pub(crate) fn record(&mut self, trace: &Trace) {
+        if let Some((_, failures)) = self.failed.iter_mut().find(|(failed, _)| failed == trace) {
+            *failures += 1;
+        } else {
+            if self.failed.len() >= MAX_SCHEDULES {
+                self.evict();
+            }
+            self.failed.push((trace.clone(), 1));
+        }
+        // stable, schedules that failed equally often stay ordered from oldest to newest
+        self.failed
+            .sort_by_key(|(_, failures)| std::cmp::Reverse(*failures));
+
+        let mut contents = String::new();
+        for (trace, failures) in &self.failed {
+            writeln!(contents, "{failures} {trace}").unwrap();
+        }
+        if let Some(dir) = self.path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let tmp = self.path.with_extension("tmp");
+        if fs::write(&tmp, contents).is_ok() {
+            let _ = fs::rename(&tmp, &self.path);
+        }
+    }
+
+    /// Drops the oldest of the schedules that failed least often.
+    fn evict(&mut self) {
+        let fewest = self.failed.iter().map(|(_, failures)| *failures).min();
+        if let Some(idx) = self
+            .failed
+            .iter()
+            .position(|(_, failures)| Some(*failures) == fewest)
+        {
+            self.failed.remove(idx);
+        }
+    }
+}
+
+/// Number of leading steps of `steps` that match steps of a failed schedule, weighted by its
+/// failures and summed over all failed schedules. Schedules sharing longer prefixes with
+/// frequently failing ones are explored first.
+pub(crate) fn fragility(failed: &[(Trace, u64)], steps: &[(&str, Option<&str>, bool)]) -> u64 {
+    failed
+        .iter()
+        .map(|(trace, failures)| {
+            let matching = steps
+                .iter()
+                .zip(trace.steps())
+                .take_while(|(step, failed)| matches(**step, failed))
+                .count();
+            matching as u64 * failures
+        })
+        .sum()
+}
+
+fn matches((task, operation, inject_fault): (&str, Option<&str>, bool), step: &TraceStep) -> bool {
+    task == step.task_name()
+        && operation == Some(step.operation_name())
+        && inject_fault == step.injected_fault().is_some()
+}
@@ -0,0 +1,54 @@
+use std::{fmt::Debug, future::Future, iter, sync::Arc};
+
+use futures_util::future::join_all;
+
+use crate::enabled::{report::Report, runner::runner, task::task};
+
+/// Checks that executing any of the tasks twice (duplicate delivery) has the same effect as
+/// executing every task once.
+///
+/// The baseline effect is obtained by executing `body` of each task once, sequentially and without
+/// parcheck control. Then, for every task, schedules of all tasks plus a duplicate execution of
+/// that task are explored (using [`runner`], so environment variables apply) and the effect of
+/// each schedule is compared to the baseline. `body` is called with a fresh state for every
+/// iteration and the name of the task to execute.
+pub async fn check<I, S, B, Fut, X, E>(tasks: I, body: B, extract_effect: X) -> Report
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+    S: Default,
+    B: Fn(Arc<S>, &str) -> Fut,
+    Fut: Future<Output = ()>,
+    X: Fn(&S) -> E,
+    E: PartialEq + Debug,
+{
+    let tasks: Vec<String> = tasks
+        .into_iter()
+        .map(|name| name.as_ref().to_owned())
+        .collect();
+
+    let state = Arc::new(S::default());
+    for name in &tasks {
+        body(Arc::clone(&state), name).await;
+    }
+    let baseline = extract_effect(&state);
+
+    let mut report = Report::default();
+    for duplicate in &tasks {
+        let names = || tasks.iter().chain(iter::once(duplicate));
+        let duplicate_report = runner()
+            .run(names(), || async {
+                let state = Arc::new(S::default());
+                join_all(names().map(|name| task(name, body(Arc::clone(&state), name)))).await;
+
+                let effect = extract_effect(&state);
+                assert_eq!(
+                    effect, baseline,
+                    "effect of executing task '{duplicate}' twice differs from executing it once"
+                );
+            })
+            .await;
+        report.merge(&duplicate_report);
+    }
+    report
+}
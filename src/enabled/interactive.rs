@@ -0,0 +1,100 @@
+use std::io::{self, BufRead, Write};
+
+use crate::enabled::{
+    controller::TaskState,
+    operation::OperationMetadata,
+    schedule_tree::{self, Choice},
+    task::Task,
+};
+
+/// Chooses the next step among candidates, returns index of the chosen one.
+pub type ChooseStep = Box<dyn FnMut(&[Candidate<'_>]) -> usize + Send>;
+
+/// Step that can be executed next in [`Runner::interactive`](crate::Runner::interactive) mode.
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate<'a> {
+    task: &'a str,
+    operation: &'static OperationMetadata,
+    fault: Option<&'static str>,
+}
+
+impl Candidate<'_> {
+    #[must_use]
+    pub fn task_name(&self) -> &str {
+        self.task
+    }
+
+    #[must_use]
+    pub fn operation(&self) -> &'static OperationMetadata {
+        self.operation
+    }
+
+    /// Fault that is injected instead of executing the operation, if any.
+    #[must_use]
+    pub fn fault(&self) -> Option<&'static str> {
+        self.fault
+    }
+}
+
+/// Asks `choose` to pick every step.
+pub(crate) fn picker(
+    choose: &mut ChooseStep,
+) -> impl FnMut(&[(Task, TaskState)]) -> Option<Choice> + '_ {
+    move |tasks| {
+        let choices: Vec<(Choice, &TaskState)> = schedule_tree::choices(tasks)
+            .filter(|(_, state)| state.can_execute())
+            .collect();
+        if choices.is_empty() {
+            return None;
+        }
+
+        let candidates: Vec<Candidate<'_>> = choices
+            .iter()
+            .map(|(choice, state)| Candidate {
+                task: &tasks[choice.task_id.0].0.name().0,
+                operation: state.executable_op().expect("executable"),
+                fault: choice
+                    .inject_fault
+                    .then(|| state.injectable_fault().expect("injectable").name()),
+            })
+            .collect();
+        let index = choose(&candidates);
+        let (choice, _) = choices
+            .get(index)
+            .unwrap_or_else(|| panic!("chosen step {index} is not one of the candidates"));
+        Some(*choice)
+    }
+}
+
+/// Prints candidates to stderr and reads index of the next step from stdin. Empty line (or end
+/// of input) chooses the first candidate.
+pub(crate) fn choose_from_stdin(candidates: &[Candidate<'_>]) -> usize {
+    let stdin = io::stdin();
+    loop {
+        eprintln!("parcheck: choose next step:");
+        for (index, candidate) in candidates.iter().enumerate() {
+            let fault = candidate
+                .fault
+                .map(|fault| format!(" (inject {fault})"))
+                .unwrap_or_default();
+            eprintln!(
+                "  [{index}] task '{}': {}{fault}",
+                candidate.task, candidate.operation
+            );
+        }
+        eprint!("> ");
+        let _ = io::stderr().flush();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+            return 0;
+        }
+        match line.trim().parse() {
+            Ok(index) if index < candidates.len() => return index,
+            _ => eprintln!(
+                "parcheck: expected a number from 0 to {}",
+                candidates.len() - 1
+            ),
+        }
+    }
+}
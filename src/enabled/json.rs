@@ -0,0 +1,187 @@
+//! Just enough JSON to export and import traces without pulling in a serialization framework.
+
+use std::{fmt::Write as _, iter::Peekable, str::Chars};
+
+pub(crate) enum Value {
+    Null,
+    // parsed for completeness, trace formats don't have booleans
+    #[allow(dead_code)]
+    Bool(bool),
+    /// Kept as written, callers parse it into the numeric type they expect.
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_usize(&self) -> Option<usize> {
+        match self {
+            Value::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn parse(s: &str) -> Option<Value> {
+    let mut chars = s.chars().peekable();
+    let value = parse_value(&mut chars)?;
+    skip_whitespace(&mut chars);
+    chars.next().is_none().then_some(value)
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars<'_>>) {
+    while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+}
+
+fn parse_value(chars: &mut Peekable<Chars<'_>>) -> Option<Value> {
+    skip_whitespace(chars);
+    match *chars.peek()? {
+        'n' => parse_literal(chars, "null", Value::Null),
+        't' => parse_literal(chars, "true", Value::Bool(true)),
+        'f' => parse_literal(chars, "false", Value::Bool(false)),
+        '"' => parse_string(chars).map(Value::String),
+        '[' => {
+            chars.next();
+            let mut values = Vec::new();
+            if !parse_end(chars, ']') {
+                loop {
+                    values.push(parse_value(chars)?);
+                    if parse_separator(chars, ']')? {
+                        break;
+                    }
+                }
+            }
+            Some(Value::Array(values))
+        }
+        '{' => {
+            chars.next();
+            let mut fields = Vec::new();
+            if !parse_end(chars, '}') {
+                loop {
+                    skip_whitespace(chars);
+                    let key = parse_string(chars)?;
+                    skip_whitespace(chars);
+                    chars.next_if_eq(&':')?;
+                    fields.push((key, parse_value(chars)?));
+                    if parse_separator(chars, '}')? {
+                        break;
+                    }
+                }
+            }
+            Some(Value::Object(fields))
+        }
+        c if c == '-' || c.is_ascii_digit() => {
+            let mut number = String::new();
+            while let Some(c) =
+                chars.next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+            {
+                number.push(c);
+            }
+            Some(Value::Number(number))
+        }
+        _ => None,
+    }
+}
+
+fn parse_literal(chars: &mut Peekable<Chars<'_>>, literal: &str, value: Value) -> Option<Value> {
+    for expected in literal.chars() {
+        chars.next_if_eq(&expected)?;
+    }
+    Some(value)
+}
+
+/// Consumes closing bracket of an empty array or object.
+fn parse_end(chars: &mut Peekable<Chars<'_>>, end: char) -> bool {
+    skip_whitespace(chars);
+    chars.next_if_eq(&end).is_some()
+}
+
+/// Consumes `,` (returns `false`) or closing bracket (returns `true`).
+fn parse_separator(chars: &mut Peekable<Chars<'_>>, end: char) -> Option<bool> {
+    skip_whitespace(chars);
+    match chars.next()? {
+        ',' => Some(false),
+        c if c == end => Some(true),
+        _ => None,
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars<'_>>) -> Option<String> {
+    chars.next_if_eq(&'"')?;
+    let mut s = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(s),
+            '\\' => s.push(match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'u' => parse_unicode_escape(chars)?,
+                c @ ('"' | '\\' | '/') => c,
+                _ => return None,
+            }),
+            c => s.push(c),
+        }
+    }
+}
+
+fn parse_unicode_escape(chars: &mut Peekable<Chars<'_>>) -> Option<char> {
+    fn hex(chars: &mut Peekable<Chars<'_>>) -> Option<u32> {
+        (0..4).try_fold(0, |acc, _| Some(acc * 16 + chars.next()?.to_digit(16)?))
+    }
+
+    let high = hex(chars)?;
+    if (0xd800..0xdc00).contains(&high) {
+        // surrogate pair
+        if chars.next()? != '\\' || chars.next()? != 'u' {
+            return None;
+        }
+        let low = hex(chars)?;
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + low.checked_sub(0xdc00)?)
+    } else {
+        char::from_u32(high)
+    }
+}
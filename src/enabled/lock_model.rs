@@ -0,0 +1,41 @@
+use crate::enabled::view::LockMode;
+
+/// Decides which locks different tasks can hold on the same scope at the same time, see
+/// [`Runner::lock_model`](crate::Runner::lock_model). Locks of the same task never conflict.
+///
+/// Custom modes are requested with [`ParcheckLock::Acquire`](crate::ParcheckLock::Acquire) and
+/// passed as [`LockMode::Custom`]:
+///
+/// ```ignore
+/// use parcheck::{LockMode, LockModel, SharedExclusive};
+///
+/// /// InnoDB-like gap locks.
+/// struct GapLocks;
+///
+/// impl LockModel for GapLocks {
+///     fn compatible(&self, held: LockMode, requested: LockMode) -> bool {
+///         match (held, requested) {
+///             // gap locks don't conflict with each other, but inserts into the gap wait for them
+///             (LockMode::Custom("gap"), LockMode::Custom(mode)) => mode == "gap",
+///             (LockMode::Custom("insert"), LockMode::Custom(_)) => true,
+///             _ => SharedExclusive.compatible(held, requested),
+///         }
+///     }
+/// }
+/// ```
+pub trait LockModel: Send + Sync {
+    /// Whether a task can acquire the scope in `requested` mode while another task holds it in
+    /// `held` mode.
+    fn compatible(&self, held: LockMode, requested: LockMode) -> bool;
+}
+
+/// Default model: shared locks are compatible with each other, every other combination (including
+/// custom modes) conflicts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SharedExclusive;
+
+impl LockModel for SharedExclusive {
+    fn compatible(&self, held: LockMode, requested: LockMode) -> bool {
+        held == LockMode::Shared && requested == LockMode::Shared
+    }
+}
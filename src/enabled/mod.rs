@@ -1,8 +1,37 @@
+pub(crate) mod backtrace;
+pub(crate) mod bench;
+pub(crate) mod breakpoint;
+pub(crate) mod checkpoint;
+pub(crate) mod comparison;
 pub(crate) mod controller;
+pub(crate) mod exactly_once;
+pub(crate) mod exploration;
+pub(crate) mod explored_tree;
+pub(crate) mod failure_class;
+pub(crate) mod history;
+pub(crate) mod idempotency;
+#[cfg(feature = "interactive")]
+pub(crate) mod interactive;
+pub(crate) mod json;
+pub(crate) mod lock_model;
+pub(crate) mod nextest;
 pub(crate) mod operation;
+#[cfg(feature = "otel")]
+pub(crate) mod otel;
+pub(crate) mod outbox;
+pub(crate) mod random;
+pub(crate) mod report;
+pub(crate) mod reporter;
 pub(crate) mod runner;
 pub(crate) mod schedule_tree;
+pub(crate) mod scope_id;
+pub(crate) mod strategy;
+pub(crate) mod stream;
+pub(crate) mod structure;
 pub(crate) mod task;
+pub(crate) mod trace;
+pub(crate) mod uncontrolled;
+pub(crate) mod view;
 
 #[macro_export]
 macro_rules! cfg_if {
@@ -20,20 +49,54 @@ macro_rules! task {
 
 #[macro_export]
 macro_rules! operation {
+    ($name:literal, timeout, { $fut:expr }) => {{
+        static METADATA: $crate::OperationMetadata =
+            $crate::OperationMetadata::new($name, file!(), line!());
+        $crate::private::timeout_operation(&METADATA, Vec::<$crate::ParcheckLock>::new(), $fut)
+    }};
+    ($name:literal, $locks:expr, timeout, { $fut:expr }) => {{
+        static METADATA: $crate::OperationMetadata =
+            $crate::OperationMetadata::new($name, file!(), line!());
+        $crate::private::timeout_operation(&METADATA, $locks, $fut)
+    }};
+    ($name:literal, serializable, { $fut:expr }) => {{
+        static METADATA: $crate::OperationMetadata =
+            $crate::OperationMetadata::new($name, file!(), line!());
+        $crate::private::serializable_operation(&METADATA, Vec::<$crate::ParcheckLock>::new(), $fut)
+    }};
+    ($name:literal, $locks:expr, serializable, { $fut:expr }) => {{
+        static METADATA: $crate::OperationMetadata =
+            $crate::OperationMetadata::new($name, file!(), line!());
+        $crate::private::serializable_operation(&METADATA, $locks, $fut)
+    }};
+    ($name:literal, unique = $key:expr, { $fut:expr }) => {{
+        static METADATA: $crate::OperationMetadata =
+            $crate::OperationMetadata::new($name, file!(), line!());
+        $crate::private::unique_operation(&METADATA, Vec::<$crate::ParcheckLock>::new(), $key, $fut)
+    }};
+    ($name:literal, $locks:expr, unique = $key:expr, { $fut:expr }) => {{
+        static METADATA: $crate::OperationMetadata =
+            $crate::OperationMetadata::new($name, file!(), line!());
+        $crate::private::unique_operation(&METADATA, $locks, $key, $fut)
+    }};
+    ($name:literal, cost = $cost:ident, { $fut:expr }) => {{
+        static METADATA: $crate::OperationMetadata =
+            $crate::OperationMetadata::new($name, file!(), line!()).with_cost($crate::Cost::$cost);
+        $crate::private::operation(&METADATA, Vec::<$crate::ParcheckLock>::new(), $fut)
+    }};
+    ($name:literal, $locks:expr, cost = $cost:ident, { $fut:expr }) => {{
+        static METADATA: $crate::OperationMetadata =
+            $crate::OperationMetadata::new($name, file!(), line!()).with_cost($crate::Cost::$cost);
+        $crate::private::operation(&METADATA, $locks, $fut)
+    }};
     ($name:literal, $locks:expr, { $fut:expr }) => {{
-        static METADATA: $crate::private::OperationMetadata = $crate::private::OperationMetadata {
-            name: $name,
-            file: file!(),
-            line: line!(),
-        };
+        static METADATA: $crate::OperationMetadata =
+            $crate::OperationMetadata::new($name, file!(), line!());
         $crate::private::operation(&METADATA, $locks, $fut)
     }};
     ($name:literal, {$fut:expr}) => {{
-        static METADATA: $crate::private::OperationMetadata = $crate::private::OperationMetadata {
-            name: $name,
-            file: file!(),
-            line: line!(),
-        };
-        $crate::private::operation(&METADATA, Vec::new(), $fut)
+        static METADATA: $crate::OperationMetadata =
+            $crate::OperationMetadata::new($name, file!(), line!());
+        $crate::private::operation(&METADATA, Vec::<$crate::ParcheckLock>::new(), $fut)
     }};
 }
@@ -0,0 +1,79 @@
+use std::{env, fs, path::PathBuf, thread};
+
+use crate::enabled::{json::escape, runner::fnv1a, task::TaskName, trace::Trace};
+
+/// Whether tests are executed by `cargo nextest`.
+pub(crate) fn detected() -> bool {
+    env::var_os("NEXTEST").is_some()
+}
+
+/// Single line describing a failed iteration, for tools that process output captured by nextest.
+pub(crate) fn failure_info(trace: &Trace) -> String {
+    let test = thread::current().name().unwrap_or_default().to_owned();
+    let attempt = env::var("NEXTEST_ATTEMPT").unwrap_or_else(|_| "1".to_owned());
+    format!(
+        r#"parcheck-failure: {{"test":"{}","attempt":{},"trace":"{}"}}"#,
+        escape(&test),
+        attempt.parse::<u32>().unwrap_or(1),
+        escape(&trace.to_string()),
+    )
+}
+
+/// Name of the current test (name of its thread) usable as a file name, `None` if the test
+/// thread is unnamed.
+pub(crate) fn current_test() -> Option<String> {
+    Some(
+        thread::current()
+            .name()?
+            .replace(|c: char| !c.is_alphanumeric(), "_"),
+    )
+}
+
+/// File with the failed schedule of a scenario of the current test, replayed when the test is
+/// retried.
+pub(crate) struct RetryFile {
+    /// Directory of the current test within the current nextest run.
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl RetryFile {
+    /// Files of the current test (identified by the name of its thread) within the current
+    /// nextest run, `None` if tests aren't executed by nextest (so schedules don't outlive a run)
+    /// or the test thread is unnamed.
+    pub(crate) fn for_current_test() -> Option<Self> {
+        if !detected() {
+            return None;
+        }
+        let run = env::var("NEXTEST_RUN_ID").ok()?;
+        let dir = env::temp_dir()
+            .join("parcheck-retry")
+            .join(run)
+            .join(current_test()?);
+        let path = dir.join("scenario.trace");
+        Some(Self { dir, path })
+    }
+
+    /// Switches to the file of the scenario with `initial_tasks`, so that runners of the same
+    /// test don't replay each other's schedules.
+    pub(crate) fn select_scenario(&mut self, initial_tasks: &[TaskName]) {
+        let names: Vec<&str> = initial_tasks.iter().map(|name| &*name.0).collect();
+        let scenario = fnv1a(names.join("\n").as_bytes());
+        self.path = self.dir.join(format!("{scenario:016x}.trace"));
+    }
+
+    pub(crate) fn load(&self) -> Option<Trace> {
+        fs::read_to_string(&self.path).ok()?.trim().parse().ok()
+    }
+
+    pub(crate) fn save(&self, trace: &Trace) {
+        if let Some(dir) = self.path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(&self.path, trace.to_string());
+    }
+
+    pub(crate) fn clear(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
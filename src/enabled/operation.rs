@@ -1,6 +1,8 @@
 use std::{
-    future::Future,
-    pin::Pin,
+    fmt::Display,
+    future::{poll_fn, Future},
+    pin::{pin, Pin},
+    ptr,
     task::{ready, Context, Poll},
 };
 
@@ -12,26 +14,188 @@ use tokio::sync::oneshot;
 use tracing::{instrument::Instrumented, Instrument};
 
 use crate::{
-    enabled::task::{self, OperationPermit, Task},
-    ParcheckLock,
+    enabled::{
+        backtrace,
+        scope_id::{self, Lock},
+        task::{self, OperationPermit, StepSpan, Task},
+        uncontrolled,
+    },
+    scope::OperationLock,
+    SerializationFailure, TimedOut, UniqueViolation,
 };
 
-#[doc(hidden)]
+/// Name and source location of an `operation!` call site.
 #[derive(Debug)]
 pub struct OperationMetadata {
-    pub name: &'static str,
-    pub file: &'static str,
-    pub line: u32,
+    pub(crate) name: &'static str,
+    pub(crate) file: &'static str,
+    pub(crate) line: u32,
+    pub(crate) cost: Cost,
+}
+
+/// Relative cost of executing an operation (e.g. real IO vs in-memory update), declared with
+/// `cost = High` in [`operation!`](crate::operation). Used by [`Runner::prefer_cheap_operations`]
+/// and [`Runner::max_expensive_preemptions`](crate::Runner::max_expensive_preemptions) to keep
+/// wall clock time of exploration manageable.
+///
+/// [`Runner::prefer_cheap_operations`]: crate::Runner::prefer_cheap_operations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Cost {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl OperationMetadata {
+    #[doc(hidden)]
+    #[must_use]
+    pub const fn new(name: &'static str, file: &'static str, line: u32) -> Self {
+        Self {
+            name,
+            file,
+            line,
+            cost: Cost::Normal,
+        }
+    }
+
+    #[doc(hidden)]
+    #[must_use]
+    pub const fn with_cost(self, cost: Cost) -> Self {
+        Self { cost, ..self }
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[must_use]
+    pub fn file(&self) -> &'static str {
+        self.file
+    }
+
+    #[must_use]
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    #[must_use]
+    pub fn cost(&self) -> Cost {
+        self.cost
+    }
+}
+
+/// Failure that can be injected instead of executing an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fault {
+    Timeout,
+    Crash,
+    UniqueViolation,
+    /// Retryable failure of a transaction commit, the task is expected to retry the transaction.
+    SerializationFailure,
+}
+
+/// Fault an operation can fail with.
+#[derive(Debug, Clone)]
+pub(crate) struct Fallible {
+    pub(crate) fault: Fault,
+    /// Fault can only be injected after another task executed an operation with the same key.
+    pub(crate) unique_key: Option<Box<str>>,
+}
+
+impl From<Fault> for Fallible {
+    fn from(fault: Fault) -> Self {
+        Self {
+            fault,
+            unique_key: None,
+        }
+    }
+}
+
+impl Fault {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Timeout => "timeout",
+            Self::Crash => "crash",
+            Self::UniqueViolation => "unique_violation",
+            Self::SerializationFailure => "serialization_failure",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "timeout" => Some(Self::Timeout),
+            "crash" => Some(Self::Crash),
+            "unique_violation" => Some(Self::UniqueViolation),
+            "serialization_failure" => Some(Self::SerializationFailure),
+            _ => None,
+        }
+    }
 }
 
 #[doc(hidden)]
-pub fn operation<F: Future>(
+pub fn operation<F: Future, L: OperationLock>(
     metadata: &'static OperationMetadata,
-    locks: Vec<ParcheckLock>,
+    locks: Vec<L>,
     f: F,
 ) -> OperationFuture<F> {
     OperationFuture::Initial {
-        data: Some((metadata, locks, f)),
+        data: Some((metadata, scope_id::erase(locks), None, f)),
+    }
+}
+
+#[doc(hidden)]
+pub fn timeout_operation<F: Future, L: OperationLock>(
+    metadata: &'static OperationMetadata,
+    locks: Vec<L>,
+    f: F,
+) -> TimeoutOperationFuture<F> {
+    TimeoutOperationFuture {
+        inner: OperationFuture::Initial {
+            data: Some((
+                metadata,
+                scope_id::erase(locks),
+                Some(Fault::Timeout.into()),
+                f,
+            )),
+        },
+    }
+}
+
+#[doc(hidden)]
+pub fn unique_operation<F: Future, L: OperationLock>(
+    metadata: &'static OperationMetadata,
+    locks: Vec<L>,
+    key: impl Display,
+    f: F,
+) -> UniqueOperationFuture<F> {
+    let fallible = Fallible {
+        fault: Fault::UniqueViolation,
+        unique_key: Some(key.to_string().into_boxed_str()),
+    };
+    UniqueOperationFuture {
+        inner: OperationFuture::Initial {
+            data: Some((metadata, scope_id::erase(locks), Some(fallible), f)),
+        },
+    }
+}
+
+#[doc(hidden)]
+pub fn serializable_operation<F: Future, L: OperationLock>(
+    metadata: &'static OperationMetadata,
+    locks: Vec<L>,
+    f: F,
+) -> SerializableOperationFuture<F> {
+    SerializableOperationFuture {
+        inner: OperationFuture::Initial {
+            data: Some((
+                metadata,
+                scope_id::erase(locks),
+                Some(Fault::SerializationFailure.into()),
+                f,
+            )),
+        },
     }
 }
 
@@ -40,14 +204,14 @@ pin_project! {
     #[project = OperationFutureProj]
     pub enum OperationFuture<F> {
         Initial {
-            data: Option<(&'static OperationMetadata, Vec<ParcheckLock>, F)>,
+            data: Option<(&'static OperationMetadata, Vec<Lock>, Option<Fallible>, F)>,
         },
         Uncontrolled {
             #[pin]
             fut: F,
         },
         WaitingForPermit {
-            data: Option<(&'static OperationMetadata, Task, F)>,
+            data: Option<(&'static OperationMetadata, Task, Option<Fault>, F)>,
             permit_rx: oneshot::Receiver<OperationPermit>,
         },
         Executing {
@@ -65,58 +229,191 @@ type InnerFuture<F> = Instrumented<F>;
 #[cfg(not(feature = "tracing"))]
 type InnerFuture<F> = F;
 
+/// Operation that fails with `fault` in schedules where it is injected.
+pub(crate) async fn faulty_operation<F: Future>(
+    metadata: &'static OperationMetadata,
+    locks: Vec<Lock>,
+    fault: Fault,
+    f: F,
+) -> Result<F::Output, Fault> {
+    let mut operation = pin!(OperationFuture::Initial {
+        data: Some((metadata, locks, Some(fault.into()), f)),
+    });
+    poll_fn(|cx| operation.as_mut().poll_operation(cx)).await
+}
+
+pin_project! {
+    #[doc(hidden)]
+    pub struct TimeoutOperationFuture<F> {
+        #[pin]
+        inner: OperationFuture<F>,
+    }
+}
+
+pin_project! {
+    #[doc(hidden)]
+    pub struct UniqueOperationFuture<F> {
+        #[pin]
+        inner: OperationFuture<F>,
+    }
+}
+
+pin_project! {
+    #[doc(hidden)]
+    pub struct SerializableOperationFuture<F> {
+        #[pin]
+        inner: OperationFuture<F>,
+    }
+}
+
 impl<F: Future> Future for OperationFuture<F> {
     type Output = F::Output;
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.poll_operation(cx).map(|result| match result {
+            Ok(value) => value,
+            Err(fault) => unreachable!("{fault:?} injected into infallible operation"),
+        })
+    }
+}
+
+impl<F: Future> Future for TimeoutOperationFuture<F> {
+    type Output = Result<F::Output, TimedOut>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project()
+            .inner
+            .poll_operation(cx)
+            .map(|result| result.map_err(|_| TimedOut))
+    }
+}
+
+impl<F: Future> Future for UniqueOperationFuture<F> {
+    type Output = Result<F::Output, UniqueViolation>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project()
+            .inner
+            .poll_operation(cx)
+            .map(|result| result.map_err(|_| UniqueViolation))
+    }
+}
+
+impl<F: Future> Future for SerializableOperationFuture<F> {
+    type Output = Result<F::Output, SerializationFailure>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.project()
+            .inner
+            .poll_operation(cx)
+            .map(|result| result.map_err(|_| SerializationFailure))
+    }
+}
+
+impl<F: Future> OperationFuture<F> {
+    fn request_permit(
+        task: Task,
+        metadata: &'static OperationMetadata,
+        locks: Vec<Lock>,
+        fault: Option<Fallible>,
+        fut: F,
+    ) -> Self {
+        let (permit_tx, permit_rx) = oneshot::channel();
+        let fault_kind = fault.as_ref().map(|fault| fault.fault);
+        task.send_event(task::TaskEvent::OperationPermitRequested {
+            metadata,
+            permit: Some(permit_tx),
+            locks,
+            backtrace: backtrace::capture(),
+            fault,
+        });
+        Self::WaitingForPermit {
+            permit_rx,
+            data: Some((metadata, task, fault_kind, fut)),
+        }
+    }
+
+    fn executing(
+        task: Task,
+        metadata: &'static OperationMetadata,
+        step_span: &StepSpan,
+        fut: F,
+    ) -> Self {
+        #[cfg(not(feature = "tracing"))]
+        let _ = (metadata, step_span);
+
+        Self::Executing {
+            task,
+            #[cfg(feature = "tracing")]
+            fut: fut.instrument({
+                let span = tracing::info_span!(
+                    "parcheck.operation",
+                    "parcheck.operation.name" = metadata.name,
+                    "parcheck.file" = metadata.file,
+                    "parcheck.line" = metadata.line
+                );
+                if let Some(step_span) = &step_span.span {
+                    span.follows_from(step_span);
+                }
+                span
+            }),
+            #[cfg(not(feature = "tracing"))]
+            fut,
+        }
+    }
+
+    fn poll_operation(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<F::Output, Fault>> {
+        // the future is pinned, so its address identifies the operation until it's dropped
+        let key = ptr::from_ref::<Self>(&self).addr();
         loop {
             let this = self.as_mut().project();
             let new_state = match this {
                 OperationFutureProj::Initial { data } => {
                     // Can't fail because `Initial` state is only observed once
-                    let (metadata, locks, fut) = data.take().unwrap();
-                    match task::current() {
-                        Some(task) => {
-                            let (permit_tx, permit_rx) = oneshot::channel();
+                    let (metadata, locks, fault, fut) = data.take().unwrap();
+                    if let Some(task) = task::current() {
+                        // fallible operations always wait, the controller decides whether to
+                        // inject the fault
+                        if fault.is_none() && task.start_fast(key, metadata) {
                             task.send_event(task::TaskEvent::OperationPermitRequested {
                                 metadata,
-                                permit: permit_tx,
+                                permit: None,
                                 locks,
+                                backtrace: backtrace::capture(),
+                                fault,
                             });
-                            // Can't fail because `Initial` state is only observed once
-                            Self::WaitingForPermit {
-                                permit_rx,
-                                data: Some((metadata, task, fut)),
-                            }
+                            Self::executing(task, metadata, &StepSpan::default(), fut)
+                        } else {
+                            Self::request_permit(task, metadata, locks, fault, fut)
                         }
-                        None => Self::Uncontrolled { fut },
+                    } else {
+                        uncontrolled::check(metadata);
+                        Self::Uncontrolled { fut }
                     }
                 }
                 OperationFutureProj::Uncontrolled { fut } => {
                     let value = ready!(fut.poll(cx));
                     self.set(Self::Done);
-                    return Poll::Ready(value);
+                    return Poll::Ready(Ok(value));
                 }
                 OperationFutureProj::WaitingForPermit { permit_rx, data } => {
                     let permit = ready!(permit_rx.poll_unpin(cx));
-                    let (metadata, task, fut) = data.take().unwrap();
-
-                    #[cfg(not(feature = "tracing"))]
-                    let _ = metadata;
-
-                    match permit {
-                        Ok(OperationPermit::Granted) | Err(_) => Self::Executing {
-                            task,
-                            #[cfg(feature = "tracing")]
-                            fut: fut.instrument(tracing::info_span!(
-                                "parcheck.operation",
-                                "parcheck.operation.name" = metadata.name,
-                                "parcheck.file" = metadata.file,
-                                "parcheck.line" = metadata.line
-                            )),
-                            #[cfg(not(feature = "tracing"))]
-                            fut,
-                        },
+                    let (metadata, task, fault, fut) = data.take().unwrap();
+
+                    task.operation_started(key, metadata);
+                    let step_span = match permit {
+                        Ok(OperationPermit::Granted {
+                            inject_fault: true, ..
+                        }) => {
+                            let fault = fault.expect("fault injected into infallible operation");
+                            task.operation_finished(key);
+                            self.set(Self::Done);
+                            return Poll::Ready(Err(fault));
+                        }
+                        Ok(OperationPermit::Granted { step_span, .. }) => step_span,
                         Ok(OperationPermit::OperationAlreadyInProgress { other }) => {
                             panic!(
                                 "operation '{}' already in progress for task '{}' (operation at {}:{})",
@@ -126,13 +423,16 @@ impl<F: Future> Future for OperationFuture<F> {
                                 other.line
                             )
                         }
-                    }
+                        Err(_) => StepSpan::default(),
+                    };
+
+                    Self::executing(task, metadata, &step_span, fut)
                 }
                 OperationFutureProj::Executing { task, fut } => {
                     let value = ready!(fut.poll(cx));
-                    task.send_event(task::TaskEvent::OperationFinished);
+                    task.operation_finished(key);
                     self.set(Self::Done);
-                    return Poll::Ready(value);
+                    return Poll::Ready(Ok(value));
                 }
                 OperationFutureProj::Done => panic!("future polled after done"),
             };
@@ -146,3 +446,21 @@ impl<F: Future> FusedFuture for OperationFuture<F> {
         matches!(self, Self::Done)
     }
 }
+
+impl<F: Future> FusedFuture for TimeoutOperationFuture<F> {
+    fn is_terminated(&self) -> bool {
+        self.inner.is_terminated()
+    }
+}
+
+impl<F: Future> FusedFuture for UniqueOperationFuture<F> {
+    fn is_terminated(&self) -> bool {
+        self.inner.is_terminated()
+    }
+}
+
+impl<F: Future> FusedFuture for SerializableOperationFuture<F> {
+    fn is_terminated(&self) -> bool {
+        self.inner.is_terminated()
+    }
+}
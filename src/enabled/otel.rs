@@ -0,0 +1,176 @@
+use std::{
+    fmt::Write as _,
+    fs::{File, OpenOptions},
+    io::Write as _,
+    path::Path,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use fastrand::Rng;
+
+use crate::enabled::{
+    json::escape,
+    trace::{Trace, TraceStep},
+};
+
+const SPAN_KIND_INTERNAL: u8 = 1;
+const STATUS_CODE_ERROR: u8 = 2;
+
+/// Writes explored iterations as OTLP/JSON `ExportTraceServiceRequest`s, one per line. JSON is
+/// written by hand, there is no dependency on OpenTelemetry crates.
+pub(crate) struct OtelExporter {
+    file: File,
+    rng: Rng,
+}
+
+impl OtelExporter {
+    pub(crate) fn create(path: &Path) -> Self {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap_or_else(|err| {
+                panic!("failed to open otel export file {}: {err}", path.display())
+            });
+
+        Self {
+            file,
+            rng: Rng::new(),
+        }
+    }
+
+    pub(crate) fn export(&mut self, iteration: u64, trace: &Trace, failure: Option<&str>) {
+        let trace_id = self.hex_id(16);
+        let root_id = self.hex_id(8);
+
+        let finished_at = SystemTime::now();
+        let started_at = trace.started_at.unwrap_or(finished_at);
+        // step timings are monotonic
+        let now = Instant::now();
+        let wall_clock = |instant: Instant| finished_at - now.saturating_duration_since(instant);
+
+        let mut spans = Vec::with_capacity(trace.steps.len() + 1);
+        spans.push(span(
+            &trace_id,
+            &root_id,
+            None,
+            "parcheck.iteration",
+            (started_at, finished_at),
+            &[
+                ("parcheck.iteration", Value::Int(iteration)),
+                ("parcheck.trace", Value::Str(&trace.to_string())),
+            ],
+            failure,
+        ));
+        for (index, step) in trace.steps.iter().enumerate() {
+            let span_id = self.hex_id(8);
+            let timing = step.timing.map_or((started_at, started_at), |timing| {
+                (
+                    wall_clock(timing.granted_at),
+                    wall_clock(timing.finished_at),
+                )
+            });
+            spans.push(span(
+                &trace_id,
+                &span_id,
+                Some(&root_id),
+                step.operation_name(),
+                timing,
+                &step_attributes(index, step),
+                None,
+            ));
+        }
+
+        let line = format!(
+            r#"{{"resourceSpans":[{{"resource":{{"attributes":[{{"key":"service.name","value":{{"stringValue":"parcheck"}}}}]}},"scopeSpans":[{{"scope":{{"name":"parcheck"}},"spans":[{}]}}]}}]}}"#,
+            spans.join(",")
+        );
+        writeln!(self.file, "{line}").expect("failed to write otel export file");
+    }
+
+    fn hex_id(&mut self, bytes: usize) -> String {
+        let mut id = String::with_capacity(bytes * 2);
+        for _ in 0..bytes {
+            write!(id, "{:02x}", self.rng.u8(..)).unwrap();
+        }
+        id
+    }
+}
+
+enum Value<'a> {
+    Str(&'a str),
+    Int(u64),
+}
+
+fn step_attributes(index: usize, step: &TraceStep) -> Vec<(&'static str, Value<'_>)> {
+    let mut attributes = vec![("parcheck.step", Value::Int(index as u64))];
+    if let Some(task_id) = step.task_id() {
+        attributes.push(("parcheck.task.id", Value::Int(task_id as u64)));
+    }
+    attributes.push(("parcheck.task.name", Value::Str(step.task_name())));
+    attributes.push(("parcheck.operation.name", Value::Str(step.operation_name())));
+    if let Some(fault) = step.injected_fault() {
+        attributes.push(("parcheck.fault", Value::Str(fault)));
+    }
+    if let Some(metadata) = step.metadata() {
+        attributes.push(("code.filepath", Value::Str(metadata.file())));
+        attributes.push(("code.lineno", Value::Int(metadata.line().into())));
+    }
+    attributes
+}
+
+fn span(
+    trace_id: &str,
+    span_id: &str,
+    parent_id: Option<&str>,
+    name: &str,
+    (start, end): (SystemTime, SystemTime),
+    attributes: &[(&str, Value<'_>)],
+    failure: Option<&str>,
+) -> String {
+    let mut json = format!(r#"{{"traceId":"{trace_id}","spanId":"{span_id}","#);
+    if let Some(parent_id) = parent_id {
+        write!(json, r#""parentSpanId":"{parent_id}","#).unwrap();
+    }
+    write!(
+        json,
+        r#""name":"{}","kind":{SPAN_KIND_INTERNAL},"startTimeUnixNano":"{}","endTimeUnixNano":"{}","attributes":["#,
+        escape(name),
+        unix_nanos(start),
+        unix_nanos(end),
+    )
+    .unwrap();
+    for (i, (key, value)) in attributes.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        match value {
+            Value::Str(value) => write!(
+                json,
+                r#"{{"key":"{key}","value":{{"stringValue":"{}"}}}}"#,
+                escape(value)
+            ),
+            Value::Int(value) => write!(
+                json,
+                r#"{{"key":"{key}","value":{{"intValue":"{value}"}}}}"#
+            ),
+        }
+        .unwrap();
+    }
+    json.push(']');
+    if let Some(message) = failure {
+        write!(
+            json,
+            r#","status":{{"code":{STATUS_CODE_ERROR},"message":"{}"}}"#,
+            escape(message)
+        )
+        .unwrap();
+    }
+    json.push('}');
+    json
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos())
+}
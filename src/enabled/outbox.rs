@@ -0,0 +1,108 @@
+use std::{future::Future, sync::Arc, sync::Mutex};
+
+use crate::{
+    enabled::{
+        operation::{operation, OperationMetadata},
+        report::Report,
+        runner::runner,
+    },
+    ParcheckLock,
+};
+
+static COMMIT: OperationMetadata = OperationMetadata::new("outbox.commit", file!(), line!());
+static PUBLISH: OperationMetadata = OperationMetadata::new("outbox.publish", file!(), line!());
+
+/// Records local commits and publishes of messages in a transactional-outbox (or saga) scenario
+/// and checks their invariants: a message is never published before it is committed, and every
+/// committed message is eventually published.
+#[derive(Debug, Default)]
+pub struct Outbox {
+    messages: Mutex<Vec<Message>>,
+}
+
+#[derive(Debug)]
+struct Message {
+    id: String,
+    committed: bool,
+    published: bool,
+}
+
+impl Outbox {
+    /// Executes `f` (local transaction that stores message `id` in the outbox) as operation
+    /// `outbox.commit`. The message is considered committed once `f` completes.
+    pub async fn commit<F: Future>(&self, id: &str, f: F) -> F::Output {
+        let output = operation(&COMMIT, Vec::<ParcheckLock>::new(), f).await;
+        self.message(id, |message| message.committed = true);
+        output
+    }
+
+    /// Executes `f` (publish of message `id`) as operation `outbox.publish`.
+    ///
+    /// # Panics
+    ///
+    /// If the message wasn't committed yet.
+    pub async fn publish<F: Future>(&self, id: &str, f: F) -> F::Output {
+        let output = operation(&PUBLISH, Vec::<ParcheckLock>::new(), f).await;
+        self.message(id, |message| {
+            assert!(
+                message.committed,
+                "message '{}' was published before it was committed",
+                message.id
+            );
+            message.published = true;
+        });
+        output
+    }
+
+    /// Checks that every committed message was published.
+    ///
+    /// # Panics
+    ///
+    /// If a committed message wasn't published.
+    pub fn assert_published(&self) {
+        for message in self.messages.lock().unwrap().iter() {
+            assert!(
+                !message.committed || message.published,
+                "message '{}' was committed but never published",
+                message.id
+            );
+        }
+    }
+
+    fn message(&self, id: &str, f: impl FnOnce(&mut Message)) {
+        let mut messages = self.messages.lock().unwrap();
+        let idx = if let Some(idx) = messages.iter().position(|message| message.id == id) {
+            idx
+        } else {
+            messages.push(Message {
+                id: id.into(),
+                committed: false,
+                published: false,
+            });
+            messages.len() - 1
+        };
+        f(&mut messages[idx]);
+    }
+}
+
+/// Explores schedules of the scenario (using [`runner`], so environment variables apply). `body`
+/// gets a fresh [`Outbox`] for every iteration, which is checked with [`Outbox::assert_published`]
+/// once the iteration completes.
+pub async fn check<I, B, Fut>(tasks: I, mut body: B) -> Report
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+    B: FnMut(Arc<Outbox>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    runner()
+        .run(tasks, || {
+            let outbox = Arc::new(Outbox::default());
+            let fut = body(Arc::clone(&outbox));
+            async move {
+                fut.await;
+                outbox.assert_published();
+            }
+        })
+        .await
+}
@@ -0,0 +1,125 @@
+use std::{
+    fmt,
+    ops::{Bound, RangeBounds},
+};
+
+/// Source of randomness used to explore schedules, see [`Runner::rng`](crate::Runner::rng).
+///
+/// Every iteration draws from its own stream obtained with [`RandomSource::split`], so that
+/// iterations don't depend on how many random values previous ones consumed.
+pub trait RandomSource: Send {
+    /// Next uniformly distributed 64-bit value.
+    fn next_u64(&mut self) -> u64;
+
+    /// Independent stream derived from this one (e.g. a split of a splittable generator, or a
+    /// generator seeded with a value from this one).
+    fn split(&mut self) -> Box<dyn RandomSource>;
+}
+
+/// Random number generator of a run: `fastrand` unless a custom [`RandomSource`] is supplied.
+pub(crate) struct Rng(Source);
+
+enum Source {
+    Fast(fastrand::Rng),
+    Custom(Box<dyn RandomSource>),
+}
+
+impl Rng {
+    pub(crate) fn new() -> Self {
+        Self(Source::Fast(fastrand::Rng::new()))
+    }
+
+    pub(crate) fn with_seed(seed: u64) -> Self {
+        Self(Source::Fast(fastrand::Rng::with_seed(seed)))
+    }
+
+    pub(crate) fn custom(source: Box<dyn RandomSource>) -> Self {
+        Self(Source::Custom(source))
+    }
+
+    /// Current state of the generator, from which [`Rng::with_seed`] continues the same sequence.
+    /// `None` for custom sources.
+    pub(crate) fn state(&self) -> Option<u64> {
+        match &self.0 {
+            Source::Fast(rng) => Some(rng.get_seed()),
+            Source::Custom(_) => None,
+        }
+    }
+
+    /// Independent generator for a single iteration.
+    pub(crate) fn fork(&mut self) -> Self {
+        Self(match &mut self.0 {
+            Source::Fast(rng) => Source::Fast(rng.fork()),
+            Source::Custom(source) => Source::Custom(source.split()),
+        })
+    }
+
+    pub(crate) fn usize(&mut self, range: impl RangeBounds<usize>) -> usize {
+        match &mut self.0 {
+            Source::Fast(rng) => rng.usize(range),
+            Source::Custom(source) => {
+                let (low, high) = bounds(range, |value| value as u64, usize::MAX as u64);
+                #[allow(clippy::cast_possible_truncation)]
+                let value = uniform(&mut **source, low, high) as usize;
+                value
+            }
+        }
+    }
+
+    pub(crate) fn u64(&mut self, range: impl RangeBounds<u64>) -> u64 {
+        match &mut self.0 {
+            Source::Fast(rng) => rng.u64(range),
+            Source::Custom(source) => {
+                let (low, high) = bounds(range, |value| value, u64::MAX);
+                uniform(&mut **source, low, high)
+            }
+        }
+    }
+
+    pub(crate) fn bool(&mut self) -> bool {
+        match &mut self.0 {
+            Source::Fast(rng) => rng.bool(),
+            Source::Custom(source) => source.next_u64() >> 63 == 1,
+        }
+    }
+}
+
+impl fmt::Debug for Rng {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Source::Fast(rng) => rng.fmt(f),
+            Source::Custom(_) => f.write_str("Rng(custom)"),
+        }
+    }
+}
+
+/// Inclusive bounds of a non-empty range of values up to `max`.
+fn bounds<T: Copy>(range: impl RangeBounds<T>, to_u64: impl Fn(T) -> u64, max: u64) -> (u64, u64) {
+    let low = match range.start_bound() {
+        Bound::Included(low) => to_u64(*low),
+        Bound::Excluded(low) => to_u64(*low) + 1,
+        Bound::Unbounded => 0,
+    };
+    let high = match range.end_bound() {
+        Bound::Included(high) => to_u64(*high),
+        Bound::Excluded(high) => to_u64(*high).checked_sub(1).expect("empty range"),
+        Bound::Unbounded => max,
+    };
+    assert!(low <= high, "empty range");
+    (low, high)
+}
+
+/// Uniformly distributed value in `low..=high` (Lemire's method).
+fn uniform(source: &mut dyn RandomSource, low: u64, high: u64) -> u64 {
+    let Some(span) = (high - low).checked_add(1) else {
+        return source.next_u64();
+    };
+    let threshold = span.wrapping_neg() % span;
+    loop {
+        let product = u128::from(source.next_u64()) * u128::from(span);
+        #[allow(clippy::cast_possible_truncation)]
+        if product as u64 >= threshold {
+            return low + (product >> 64) as u64;
+        }
+    }
+}
@@ -0,0 +1,759 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    time::Duration,
+};
+
+use crate::enabled::{
+    explored_tree::ExploredTree,
+    failure_class::{ClassifiedFailures, FailureClass},
+    structure::Structure,
+    trace::{Trace, TraceStep},
+};
+
+/// Number of slowest steps and schedules kept in a [`Report`].
+const SLOWEST: usize = 5;
+
+/// Smallest configuration that reproduces a failure, found by
+/// [`Runner::find_minimal_iterations`](crate::Runner::find_minimal_iterations). Displayed as
+/// environment variables to reproduce it with, followed by the failed schedule.
+#[derive(Debug, Clone)]
+pub struct Reproduction {
+    pub(crate) seed: u64,
+    pub(crate) iterations: u64,
+    pub(crate) trace: Trace,
+}
+
+impl Reproduction {
+    #[must_use]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    #[must_use]
+    pub fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// Schedule of the failed iteration.
+    #[must_use]
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+}
+
+impl fmt::Display for Reproduction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PARCHECK_SEED={} PARCHECK_MAX_ITERATIONS={} (failed schedule: {})",
+            self.seed, self.iterations, self.trace
+        )
+    }
+}
+
+/// Summary of a finished run.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub(crate) iterations: u64,
+    pub(crate) exhausted: bool,
+    pub(crate) detection: Option<DetectionEstimate>,
+    pub(crate) families: Vec<ScheduleFamily>,
+    pub(crate) scopes: BTreeMap<String, ScopeStats>,
+    pub(crate) structure: Option<Structure>,
+    pub(crate) schedule_space: Option<ScheduleSpace>,
+    pub(crate) schedule_tree: Option<ExploredTree>,
+    pub(crate) slowest_steps: Vec<SlowStep>,
+    pub(crate) slowest_schedules: Vec<SlowSchedule>,
+    pub(crate) expected_panic: Option<Trace>,
+    pub(crate) failure_classes: Vec<ClassifiedFailures>,
+}
+
+impl Report {
+    /// Number of executed iterations (schedules), including seeded and replayed ones.
+    #[must_use]
+    pub fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// Whether every schedule was explored.
+    #[must_use]
+    pub fn exhausted(&self) -> bool {
+        self.exhausted
+    }
+
+    /// Probability estimate of finding bugs with [`Strategy::Pct`](crate::Strategy::Pct). Not
+    /// available for other strategies.
+    #[must_use]
+    pub fn detection(&self) -> Option<&DetectionEstimate> {
+        self.detection.as_ref()
+    }
+
+    /// Explored schedules grouped by the order in which tasks took turns, in order of discovery.
+    #[must_use]
+    pub fn families(&self) -> &[ScheduleFamily] {
+        &self.families
+    }
+
+    /// Lock usage of every scope locked by an operation, ordered by scope name.
+    pub fn scopes(&self) -> impl Iterator<Item = (&str, &ScopeStats)> {
+        self.scopes
+            .iter()
+            .map(|(scope, stats)| (scope.as_str(), stats))
+    }
+
+    /// Lock usage of `scope`, `None` if it was never locked.
+    #[must_use]
+    pub fn scope(&self, scope: &str) -> Option<&ScopeStats> {
+        self.scopes.get(scope)
+    }
+
+    /// Operations executed by every task in the first successful iteration without injected
+    /// faults. Can be used to compute the size of the schedule space (see
+    /// [`Structure::count_schedules`]) or to detect changes of the scenario (see
+    /// [`Runner::expect_structure`](crate::Runner::expect_structure)).
+    #[must_use]
+    pub fn structure(&self) -> Option<&Structure> {
+        self.structure.as_ref()
+    }
+
+    /// Number of schedules before and after reductions, and where reductions pruned them. Not
+    /// available for [`Strategy::Pct`](crate::Strategy::Pct).
+    #[must_use]
+    pub fn schedule_space(&self) -> Option<&ScheduleSpace> {
+        self.schedule_space.as_ref()
+    }
+
+    /// Schedule tree at the end of the run, e.g. to check that a subtree was fully explored. Not
+    /// available for [`Strategy::Pct`](crate::Strategy::Pct).
+    #[must_use]
+    pub fn schedule_tree(&self) -> Option<&ExploredTree> {
+        self.schedule_tree.as_ref()
+    }
+
+    /// Schedule that panicked as expected, see
+    /// [`Runner::expect_panic_matching`](crate::Runner::expect_panic_matching).
+    #[must_use]
+    pub fn expected_panic(&self) -> Option<&Trace> {
+        self.expected_panic.as_ref()
+    }
+
+    /// Steps that took the longest from granting the permit until the operation finished, slowest
+    /// first (at most 5).
+    #[must_use]
+    pub fn slowest_steps(&self) -> &[SlowStep] {
+        &self.slowest_steps
+    }
+
+    /// Schedules whose steps took the longest in total, slowest first (at most 5).
+    #[must_use]
+    pub fn slowest_schedules(&self) -> &[SlowSchedule] {
+        &self.slowest_schedules
+    }
+
+    pub(crate) fn record_durations(&mut self, trace: &Trace) {
+        if let Some(duration) = trace.duration() {
+            keep_slowest(&mut self.slowest_schedules, duration, || SlowSchedule {
+                trace: trace.clone(),
+                duration,
+            });
+        }
+        for (index, step) in trace.steps().iter().enumerate() {
+            if let Some(duration) = step.duration() {
+                keep_slowest(&mut self.slowest_steps, duration, || SlowStep {
+                    trace: trace.clone(),
+                    index,
+                    duration,
+                });
+            }
+        }
+    }
+
+    pub(crate) fn record_scopes(&mut self, scopes: &HashMap<String, ScopeStats>) {
+        for (scope, stats) in scopes {
+            self.scopes.entry(scope.clone()).or_default().merge(stats);
+        }
+    }
+
+    /// Failed iterations grouped by [`Runner::classify_failure`](crate::Runner::classify_failure),
+    /// in order of discovery.
+    #[must_use]
+    pub fn failure_classes(&self) -> &[ClassifiedFailures] {
+        &self.failure_classes
+    }
+
+    pub(crate) fn record_failure(&mut self, class: &FailureClass, trace: &Trace) {
+        match self
+            .failure_classes
+            .iter_mut()
+            .find(|classified| classified.class == *class)
+        {
+            Some(classified) => classified.failures += 1,
+            None => self.failure_classes.push(ClassifiedFailures {
+                class: class.clone(),
+                failures: 1,
+                example: trace.clone(),
+            }),
+        }
+    }
+
+    pub(crate) fn record(&mut self, trace: &Trace, failed: bool) -> &ScheduleFamily {
+        let task_order = task_order(trace);
+        let index = if let Some(index) = self
+            .families
+            .iter()
+            .position(|family| family.task_order == task_order)
+        {
+            index
+        } else {
+            self.families.push(ScheduleFamily {
+                task_order,
+                iterations: 0,
+                failures: 0,
+                example: trace.clone(),
+            });
+            self.families.len() - 1
+        };
+
+        let family = &mut self.families[index];
+        family.iterations += 1;
+        if failed {
+            if family.failures == 0 {
+                family.example = trace.clone();
+            }
+            family.failures += 1;
+        }
+        family
+    }
+
+    /// Combines reports of runs that explored the same scenario.
+    pub fn merge(&mut self, other: &Report) {
+        self.iterations += other.iterations;
+        self.exhausted |= other.exhausted;
+        if self.detection.is_none() {
+            self.detection = other.detection;
+        }
+        if self.structure.is_none() {
+            self.structure.clone_from(&other.structure);
+        }
+        if self.schedule_space.is_none() {
+            self.schedule_space.clone_from(&other.schedule_space);
+        }
+        if self.schedule_tree.is_none() {
+            self.schedule_tree.clone_from(&other.schedule_tree);
+        }
+        if self.expected_panic.is_none() {
+            self.expected_panic.clone_from(&other.expected_panic);
+        }
+        for other in &other.families {
+            match self
+                .families
+                .iter_mut()
+                .find(|family| family.task_order == other.task_order)
+            {
+                Some(family) => {
+                    family.iterations += other.iterations;
+                    if family.failures == 0 {
+                        family.example = other.example.clone();
+                    }
+                    family.failures += other.failures;
+                }
+                None => self.families.push(other.clone()),
+            }
+        }
+        for (scope, stats) in &other.scopes {
+            self.scopes.entry(scope.clone()).or_default().merge(stats);
+        }
+        for other in &other.failure_classes {
+            match self
+                .failure_classes
+                .iter_mut()
+                .find(|classified| classified.class == other.class)
+            {
+                Some(classified) => classified.failures += other.failures,
+                None => self.failure_classes.push(other.clone()),
+            }
+        }
+        for step in &other.slowest_steps {
+            keep_slowest(&mut self.slowest_steps, step.duration, || step.clone());
+        }
+        for schedule in &other.slowest_schedules {
+            keep_slowest(&mut self.slowest_schedules, schedule.duration, || {
+                schedule.clone()
+            });
+        }
+    }
+}
+
+/// Step of a schedule that took long, see [`Report::slowest_steps`].
+#[derive(Debug, Clone)]
+pub struct SlowStep {
+    trace: Trace,
+    index: usize,
+    duration: Duration,
+}
+
+impl SlowStep {
+    #[must_use]
+    pub fn step(&self) -> &TraceStep {
+        &self.trace.steps()[self.index]
+    }
+
+    /// Index of the step in [`SlowStep::trace`].
+    #[must_use]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Schedule the step was executed in.
+    #[must_use]
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+}
+
+impl fmt::Display for SlowStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {} (step {} of {})",
+            self.duration,
+            self.step(),
+            self.index,
+            self.trace
+        )
+    }
+}
+
+/// Schedule that took long, see [`Report::slowest_schedules`].
+#[derive(Debug, Clone)]
+pub struct SlowSchedule {
+    trace: Trace,
+    duration: Duration,
+}
+
+impl SlowSchedule {
+    #[must_use]
+    pub fn trace(&self) -> &Trace {
+        &self.trace
+    }
+
+    /// Total time of the schedule's steps, see [`Trace::duration`].
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+impl fmt::Display for SlowSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {}", self.duration, self.trace)
+    }
+}
+
+trait Slow {
+    fn duration(&self) -> Duration;
+}
+
+impl Slow for SlowStep {
+    fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+impl Slow for SlowSchedule {
+    fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+/// Inserts an entry created with `new` into `slowest` (sorted from the slowest) if it's one of
+/// [`SLOWEST`] entries that took the longest.
+fn keep_slowest<T: Slow>(slowest: &mut Vec<T>, duration: Duration, new: impl FnOnce() -> T) {
+    if slowest.len() == SLOWEST
+        && slowest
+            .last()
+            .is_some_and(|last| last.duration() >= duration)
+    {
+        return;
+    }
+    let index = slowest.partition_point(|other| other.duration() >= duration);
+    slowest.insert(index, new());
+    slowest.truncate(SLOWEST);
+}
+
+/// Lock usage of a scope across all iterations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScopeStats {
+    pub(crate) max_shared_holders: usize,
+    pub(crate) exclusive_acquisitions: u64,
+}
+
+impl ScopeStats {
+    /// Maximum number of tasks that held a shared lock on the scope at the same time.
+    #[must_use]
+    pub fn max_shared_holders(&self) -> usize {
+        self.max_shared_holders
+    }
+
+    /// Number of times an exclusive lock on the scope was acquired (including upgrades of
+    /// shared locks).
+    #[must_use]
+    pub fn exclusive_acquisitions(&self) -> u64 {
+        self.exclusive_acquisitions
+    }
+
+    pub(crate) fn merge(&mut self, other: &ScopeStats) {
+        self.max_shared_holders = self.max_shared_holders.max(other.max_shared_holders);
+        self.exclusive_acquisitions += other.exclusive_acquisitions;
+    }
+}
+
+/// Schedule space of an exhaustive exploration compared to all interleavings of the scenario's
+/// operations. Reductions are only sound if operations declare their locks correctly, pruned
+/// alternatives show which of them were relied on.
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleSpace {
+    pub(crate) raw: Option<u128>,
+    pub(crate) reduced: u64,
+    pub(crate) pruned: Vec<PrunedAlternatives>,
+    pub(crate) unreachable: Vec<UnreachableBranches>,
+}
+
+impl ScheduleSpace {
+    /// Number of interleavings of operations without any reductions (see
+    /// [`Structure::count_schedules`]), `None` if no iteration succeeded without injected faults.
+    #[must_use]
+    pub fn raw_schedules(&self) -> Option<u128> {
+        self.raw
+    }
+
+    /// Number of schedules left after reductions (explored, or left unexplored because of
+    /// `max_iterations`).
+    #[must_use]
+    pub fn reduced_schedules(&self) -> u64 {
+        self.reduced
+    }
+
+    /// Alternatives skipped by reductions, grouped by reduction and operation, in order of
+    /// discovery.
+    #[must_use]
+    pub fn pruned(&self) -> &[PrunedAlternatives] {
+        &self.pruned
+    }
+
+    /// Number of alternatives skipped by `reduction`.
+    #[must_use]
+    pub fn pruned_by(&self, reduction: Reduction) -> u64 {
+        self.pruned
+            .iter()
+            .filter(|pruned| pruned.reduction == reduction)
+            .map(|pruned| pruned.alternatives)
+            .sum()
+    }
+
+    /// Branches of the schedule tree that couldn't be taken, grouped by reason, in order of
+    /// discovery.
+    #[must_use]
+    pub fn unreachable(&self) -> &[UnreachableBranches] {
+        &self.unreachable
+    }
+
+    /// Number of unreachable branches whose reason starts with `reason` (e.g. `"blocked by
+    /// locks"` for every scope).
+    #[must_use]
+    pub fn unreachable_by(&self, reason: &str) -> u64 {
+        self.unreachable
+            .iter()
+            .filter(|unreachable| unreachable.reason.starts_with(reason))
+            .map(|unreachable| unreachable.branches)
+            .sum()
+    }
+
+    /// Counts an unreachable branch, `example` is only called for the first branch of a reason.
+    pub(crate) fn record_unreachable(&mut self, reason: String, example: impl FnOnce() -> String) {
+        if let Some(unreachable) = self
+            .unreachable
+            .iter_mut()
+            .find(|unreachable| unreachable.reason == reason)
+        {
+            unreachable.branches += 1;
+        } else {
+            self.unreachable.push(UnreachableBranches {
+                reason,
+                branches: 1,
+                example: example(),
+            });
+        }
+    }
+
+    pub(crate) fn record_pruned(&mut self, reduction: Reduction, task: &str, operation: &str) {
+        if let Some(pruned) = self.pruned.iter_mut().find(|pruned| {
+            pruned.reduction == reduction && pruned.task == task && pruned.operation == operation
+        }) {
+            pruned.alternatives += 1;
+        } else {
+            self.pruned.push(PrunedAlternatives {
+                reduction,
+                task: task.into(),
+                operation: operation.into(),
+                alternatives: 1,
+            });
+        }
+    }
+}
+
+impl fmt::Display for ScheduleSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.raw {
+            Some(raw) => write!(f, "{} of {raw} schedules", self.reduced)?,
+            None => write!(f, "{} schedules", self.reduced)?,
+        }
+        for pruned in &self.pruned {
+            write!(f, "\n  {pruned}")?;
+        }
+        for unreachable in &self.unreachable {
+            write!(f, "\n  {unreachable}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reduction of the schedule space that skips some alternatives at a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reduction {
+    /// Task is blocked by locks held by other tasks (or queued behind them with
+    /// [`Runner::fair_locks`](crate::Runner::fair_locks)).
+    Locks,
+    /// [`Runner::max_expensive_preemptions`](crate::Runner::max_expensive_preemptions).
+    PreemptionBound,
+    /// Step is deeper than `depth` of [`Strategy::ShallowFirst`](crate::Strategy::ShallowFirst).
+    BranchingDepth,
+}
+
+impl fmt::Display for Reduction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Locks => "locks",
+            Self::PreemptionBound => "preemption bound",
+            Self::BranchingDepth => "branching depth",
+        })
+    }
+}
+
+/// Alternatives in which `task` would have executed `operation` next, skipped by a reduction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrunedAlternatives {
+    pub(crate) reduction: Reduction,
+    pub(crate) task: String,
+    pub(crate) operation: String,
+    pub(crate) alternatives: u64,
+}
+
+impl PrunedAlternatives {
+    #[must_use]
+    pub fn reduction(&self) -> Reduction {
+        self.reduction
+    }
+
+    #[must_use]
+    pub fn task(&self) -> &str {
+        &self.task
+    }
+
+    #[must_use]
+    pub fn operation(&self) -> &str {
+        &self.operation
+    }
+
+    /// Number of skipped alternatives, each of them a subtree of schedules.
+    #[must_use]
+    pub fn alternatives(&self) -> u64 {
+        self.alternatives
+    }
+}
+
+impl fmt::Display for PrunedAlternatives {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: task '{}' {} ({} alternatives)",
+            self.reduction, self.task, self.operation, self.alternatives
+        )
+    }
+}
+
+/// Branches of the schedule tree that couldn't be taken for the same reason, e.g. `blocked by
+/// locks on scope 'jobs'` or `task finished`. Unexpected reasons (e.g. a scope that shouldn't
+/// be contended) point at operations declaring wrong locks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnreachableBranches {
+    pub(crate) reason: String,
+    pub(crate) branches: u64,
+    pub(crate) example: String,
+}
+
+impl UnreachableBranches {
+    #[must_use]
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// Number of branches, each of them a subtree of schedules that were never explored.
+    #[must_use]
+    pub fn branches(&self) -> u64 {
+        self.branches
+    }
+
+    /// Steps leading to the first such branch (formatted as in [`Trace`]), the last of them is
+    /// the step that couldn't be taken.
+    #[must_use]
+    pub fn example(&self) -> &str {
+        &self.example
+    }
+}
+
+impl fmt::Display for UnreachableBranches {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} branches unreachable: {} (e.g. {})",
+            self.branches, self.reason, self.example
+        )
+    }
+}
+
+/// Schedules that share the sequence of context switches: tasks took turns in the same order,
+/// but possibly executed different number of operations on each turn.
+#[derive(Debug, Clone)]
+pub struct ScheduleFamily {
+    pub(crate) task_order: Vec<String>,
+    pub(crate) iterations: u64,
+    pub(crate) failures: u64,
+    pub(crate) example: Trace,
+}
+
+impl ScheduleFamily {
+    /// Names of tasks in the order they took turns.
+    #[must_use]
+    pub fn task_order(&self) -> &[String] {
+        &self.task_order
+    }
+
+    /// Number of iterations with a schedule from this family.
+    #[must_use]
+    pub fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// Number of failed iterations with a schedule from this family.
+    #[must_use]
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+
+    /// A schedule from this family. Failing one, if there were failures.
+    #[must_use]
+    pub fn example(&self) -> &Trace {
+        &self.example
+    }
+}
+
+impl fmt::Display for ScheduleFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} schedules",
+            self.task_order.join(" > "),
+            self.iterations
+        )?;
+        if self.failures > 0 {
+            write!(f, ", {} failed", self.failures)?;
+        }
+        f.write_str(")")
+    }
+}
+
+fn task_order(trace: &Trace) -> Vec<String> {
+    let mut order: Vec<String> = Vec::new();
+    for step in trace.steps() {
+        if order.last().map(String::as_str) != Some(step.task_name()) {
+            order.push(step.task_name().into());
+        }
+    }
+    order
+}
+
+/// Guarantee of [`Strategy::Pct`](crate::Strategy::Pct): a single iteration finds a bug that
+/// requires at most `depth` ordering constraints with probability of at least
+/// `1 / (tasks * steps^(depth - 1))`, where `tasks` and `steps` are the largest counts observed
+/// during the run.
+#[derive(Debug, Clone, Copy)]
+pub struct DetectionEstimate {
+    pub(crate) depth: usize,
+    pub(crate) tasks: usize,
+    pub(crate) steps: usize,
+    pub(crate) iterations: u64,
+}
+
+impl DetectionEstimate {
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    #[must_use]
+    pub fn tasks(&self) -> usize {
+        self.tasks
+    }
+
+    #[must_use]
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    #[must_use]
+    pub fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// Lower bound on probability that a single iteration finds a bug of `depth`.
+    #[must_use]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap
+    )]
+    pub fn per_iteration(&self) -> f64 {
+        let tasks = self.tasks.max(1) as f64;
+        let steps = self.steps.max(1) as f64;
+        1.0 / (tasks * steps.powi(self.depth as i32 - 1))
+    }
+
+    /// Lower bound on probability that at least one of the iterations found a bug of `depth`.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn probability(&self) -> f64 {
+        1.0 - (1.0 - self.per_iteration()).powf(self.iterations as f64)
+    }
+}
+
+impl fmt::Display for DetectionEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "with PCT depth {}, {} iterations, bugs requiring ≤{} ordering constraints are found with ≥{:.2}% probability ({} tasks, {} steps)",
+            self.depth,
+            self.iterations,
+            self.depth,
+            self.probability() * 100.0,
+            self.tasks,
+            self.steps,
+        )
+    }
+}
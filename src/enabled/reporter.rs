@@ -0,0 +1,48 @@
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+
+/// Destination of notes a runner prints about failed iterations (how to replay the failed
+/// schedule, its schedule family, backtraces of steps) and of its warnings.
+#[derive(Default)]
+pub enum Reporter {
+    /// Prints to stderr. This is the default.
+    #[default]
+    Stderr,
+    /// Discards notes and warnings.
+    Silent,
+    /// Appends every note as a line to the file at the path.
+    File(PathBuf),
+    /// Passes every note to the closure.
+    Custom(Box<dyn FnMut(&str)>),
+}
+
+impl Reporter {
+    /// Parses value of `PARCHECK_NOTES` environment variable: `stderr`, `silent` or path of a
+    /// file.
+    pub(crate) fn from_env_value(value: &str) -> Self {
+        match value {
+            "stderr" => Self::Stderr,
+            "silent" => Self::Silent,
+            path => Self::File(path.into()),
+        }
+    }
+
+    pub(crate) fn note(&mut self, note: &str) {
+        match self {
+            Self::Stderr => eprintln!("{note}"),
+            Self::Silent => {}
+            Self::File(path) => {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&*path)
+                    .unwrap_or_else(|err| {
+                        panic!("failed to open notes file {}: {err}", path.display())
+                    });
+                writeln!(file, "{note}").unwrap_or_else(|err| {
+                    panic!("failed to write notes file {}: {err}", path.display())
+                });
+            }
+            Self::Custom(report) => report(note),
+        }
+    }
+}
@@ -1,34 +1,139 @@
 use std::{
+    any::Any,
+    cell::RefCell,
+    collections::HashMap,
     env,
     error::Error,
     fmt,
     future::Future,
+    mem,
     panic::{self, AssertUnwindSafe},
-    str::FromStr,
+    path::PathBuf,
+    pin::pin,
+    rc::Rc,
+    sync::{Arc, Mutex, PoisonError},
+    thread,
     time::Duration,
 };
 
-use fastrand::Rng;
-use futures_util::{future::BoxFuture, join, FutureExt};
+use futures_util::{
+    future::{self, BoxFuture},
+    FutureExt,
+};
+use tokio::sync::oneshot;
 
+#[cfg(feature = "interactive")]
+use crate::enabled::interactive::{self, ChooseStep};
+#[cfg(feature = "otel")]
+use crate::enabled::otel::OtelExporter;
 use crate::enabled::{
-    controller::Controller,
-    schedule_tree::ScheduleTree,
-    task::{TaskId, TaskName},
+    breakpoint::{self, Breakpoint},
+    checkpoint::Checkpoint,
+    comparison::{ComparedSchedule, Comparison, Variant},
+    controller::{Controller, ControllerConfig, ControllerError, TaskState},
+    failure_class::{FailureAction, FailureClass},
+    history::FailureHistory,
+    lock_model::LockModel,
+    nextest::{self, RetryFile},
+    random::{RandomSource, Rng},
+    report::{Report, Reproduction, ScopeStats},
+    reporter::Reporter,
+    schedule_tree::{self, Choice, ScheduleTree},
+    strategy::{CostConfig, Pct, Saturation, Strategy},
+    stream::{EventSink, StepStream},
+    structure::{ScheduleEnumeration, Structure},
+    task::{Registrations, StepSpan, Task, TaskId, TaskName, TaskRegistration},
+    trace::{Cancellation, OperationName, StepCandidate, Trace, TraceStep},
+    view::ControllerView,
 };
 
 pub fn runner() -> Runner {
     Runner::from_env()
 }
 
+/// Runner for a single case of a parametrized test, e.g. returned by a fixture of `rstest` (or any
+/// other test parametrization crate):
+///
+/// ```ignore
+/// #[fixture]
+/// fn runner(#[default("default")] case: &str) -> parcheck::Runner {
+///     parcheck::case_runner(case).max_iterations(100)
+/// }
+///
+/// #[rstest]
+/// #[case::small(runner("small"))]
+/// #[case::large(runner("large"))]
+/// #[tokio::test]
+/// async fn transfers(#[case] runner: parcheck::Runner) { /* ... */ }
+/// ```
+///
+/// Same as [`runner`], except that `PARCHECK_REPLAY` and `PARCHECK_SEED` only apply to the case
+/// selected by `PARCHECK_CASE` (to every case if it's not set), and each case mixes its name into
+/// the seed, so that cases explore schedules independently of each other. Notes about failed
+/// iterations mention the case.
+pub fn case_runner(case: &str) -> Runner {
+    Runner::from_env_for_case(Some(case))
+}
+
 #[must_use]
 pub struct Runner {
     iteration_config: IterationConfig,
+    strategy: Strategy,
+    costs: CostConfig,
+    seed_schedules: Vec<Trace>,
+    expected_structure: Option<Structure>,
+    /// Source of randomness of every iteration, see [`Runner::seed`].
+    rng: Rng,
+    controller_config: ControllerConfig,
     on_panic: Option<PanicHandler>,
-    before_step: Option<BeforeStep>,
-    after_step: Option<AfterStep>,
+    reporter: Reporter,
+    hooks: Hooks,
+    /// Tasks that were reported to execute no operations.
+    idle_tasks: Vec<TaskName>,
+    /// Case of a parametrized test, see `case_runner`.
+    case: Option<Box<str>>,
+    /// Failed schedule of the test, see [`Runner::retry_same_schedule`].
+    retry: Option<RetryFile>,
+    /// Schedules that failed in previous runs, see [`Runner::failure_history`].
+    history: Option<FailureHistory>,
+    /// See [`Runner::schedule_space_warning`].
+    space_warning: u64,
+    /// See [`Runner::expect_panic_matching`].
+    expected_panic: Option<String>,
+    /// See [`Runner::classify_failure`].
+    classify: Option<ClassifyFailure>,
+    /// Whether failures of a class are retried, and how many times, see [`Runner::retry_on`].
+    retry_on: (RetryOn, u32),
+    /// Whether the last iteration failed and exploration continued, see
+    /// [`FailureAction::Continue`].
+    continued_failure: bool,
+    /// Traces of executed iterations, recorded by [`Runner::compare`].
+    traces: Option<Vec<Trace>>,
+    /// See [`Runner::checkpoint`].
+    checkpoint: Option<Checkpoint>,
+    /// Whether iterations probe a configuration for [`Runner::find_minimal_iterations`], whose
+    /// failures are captured instead of being reported and persisted.
+    searching: bool,
+    #[cfg(feature = "otel")]
+    otel_export: Option<PathBuf>,
+    #[cfg(feature = "otel")]
+    otel_exporter: Option<OtelExporter>,
+    #[cfg(feature = "interactive")]
+    interactive: Option<ChooseStep>,
+}
+
+#[derive(Default)]
+struct Hooks {
+    before_step: Option<StepHook>,
+    after_step: Option<StepHook>,
     before_iter: Option<BeforeIter>,
     after_iter: Option<AfterIter>,
+    on_operation: Vec<(String, OperationHook)>,
+    breakpoints: Vec<(Breakpoint, StepHook)>,
+    step_latency: Option<StepLatency>,
+    events: Option<EventSink>,
+    /// Whether every step of traces lists steps that could be chosen instead.
+    record_candidates: bool,
 }
 
 enum IterationConfig {
@@ -37,10 +142,52 @@ enum IterationConfig {
 }
 
 pub type PanicHandler = Box<dyn FnOnce(&Trace)>;
+pub type ClassifyFailure = Box<dyn FnMut(&(dyn Any + Send), &Trace) -> FailureClass>;
+pub type RetryOn = Box<dyn Fn(&FailureClass) -> bool>;
 pub type BeforeStep = Box<dyn FnMut() -> BoxFuture<'static, ()>>;
 pub type AfterStep = Box<dyn FnMut() -> BoxFuture<'static, ()>>;
+pub type StepHook = Box<dyn FnMut(&StepContext<'_>) -> BoxFuture<'static, ()>>;
 pub type BeforeIter = Box<dyn FnMut() -> BoxFuture<'static, ()>>;
 pub type AfterIter = Box<dyn FnMut() -> BoxFuture<'static, ()>>;
+pub type StepLatency = Box<dyn FnMut(&StepContext<'_>) -> Duration>;
+pub type OperationHook = Box<dyn FnMut(OperationEvent, &StepContext<'_>) -> BoxFuture<'static, ()>>;
+
+/// Point at which [`Runner::on_operation`] hook is called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationEvent {
+    /// Operation is about to be allowed to execute.
+    Granted,
+    /// Operation has finished executing.
+    Finished,
+}
+
+/// Step that is about to be executed (in [`Runner::before_step_with_context`]) or has just been
+/// executed (in [`Runner::after_step_with_context`]).
+pub struct StepContext<'a> {
+    index: usize,
+    step: &'a TraceStep,
+    controller: ControllerView<'a>,
+}
+
+impl StepContext<'_> {
+    /// Index of the step within current iteration.
+    #[must_use]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    #[must_use]
+    pub fn step(&self) -> &TraceStep {
+        self.step
+    }
+
+    /// State of the scheduler: before the operation is granted (in [`Runner::before_step`]) or
+    /// after it finished (in [`Runner::after_step`]).
+    #[must_use]
+    pub fn controller(&self) -> ControllerView<'_> {
+        self.controller
+    }
+}
 
 impl Default for Runner {
     fn default() -> Self {
@@ -48,310 +195,2003 @@ impl Default for Runner {
             iteration_config: IterationConfig::Iterate {
                 max_iterations: u64::MAX,
             },
+            strategy: Strategy::default(),
+            costs: CostConfig::default(),
+            seed_schedules: Vec::new(),
+            expected_structure: None,
+            rng: Rng::new(),
+            controller_config: ControllerConfig::default(),
             on_panic: None,
-            before_step: None,
-            after_step: None,
-            before_iter: None,
-            after_iter: None,
+            reporter: Reporter::default(),
+            hooks: Hooks::default(),
+            idle_tasks: Vec::new(),
+            case: None,
+            retry: None,
+            history: None,
+            space_warning: DEFAULT_SPACE_WARNING,
+            expected_panic: None,
+            classify: None,
+            retry_on: (
+                Box::new(|class| class.action() == FailureAction::Retry),
+                DEFAULT_FAILURE_RETRIES,
+            ),
+            continued_failure: false,
+            traces: None,
+            checkpoint: None,
+            searching: false,
+            #[cfg(feature = "otel")]
+            otel_export: None,
+            #[cfg(feature = "otel")]
+            otel_exporter: None,
+            #[cfg(feature = "interactive")]
+            interactive: None,
         }
     }
 }
 
 impl Runner {
     pub fn from_env() -> Self {
-        let mut runner = Self::default();
+        Self::from_env_for_case(None)
+    }
+
+    /// Preset for a fast feedback loop during development: covers every interleaving of the first
+    /// 3 steps within 100 iterations (see [`Strategy::ShallowFirst`]). Environment variables
+    /// (e.g. `PARCHECK_MAX_ITERATIONS`) override the preset like with [`runner`].
+    pub fn quick() -> Self {
+        Self::default()
+            .strategy(Strategy::ShallowFirst { depth: 3 })
+            .max_iterations(100)
+            .apply_env(None)
+    }
+
+    /// Preset for exhaustive checks of a scenario before relying on it: explores every schedule
+    /// (up to 100 000) and fails on tasks that aren't controlled by parcheck or execute
+    /// overlapping operations. Environment variables override the preset like with [`runner`].
+    pub fn thorough() -> Self {
+        Self::default()
+            .strategy(Strategy::Exhaustive)
+            .max_iterations(100_000)
+            .strict(true)
+            .detect_overlapping_operations(true)
+            .apply_env(None)
+    }
+
+    /// Preset for CI: explores until the schedule space appears saturated (at most 10 000
+    /// iterations, see [`Strategy::Adaptive`]), fails retries of a test on the same schedule (see
+    /// [`Runner::retry_same_schedule`]) and keeps a failure history of every test in the target
+    /// directory (see [`Runner::failure_history`]). Environment variables override the preset like
+    /// with [`runner`].
+    pub fn ci() -> Self {
+        let mut runner = Self::default()
+            .strategy(Strategy::Adaptive {
+                window: 100,
+                min_discovery_rate: 0.01,
+            })
+            .max_iterations(10_000)
+            .strict(true)
+            .retry_same_schedule(true);
+        runner.history = FailureHistory::for_current_test();
+        runner.apply_env(None)
+    }
 
-        if let Ok(trace) = env::var("PARCHECK_REPLAY") {
+    fn from_env_for_case(case: Option<&str>) -> Self {
+        Self {
+            case: case.map(Into::into),
+            ..Self::default()
+        }
+        .apply_env(case)
+    }
+
+    /// Applies settings of `PARCHECK_*` environment variables.
+    fn apply_env(mut self, case: Option<&str>) -> Self {
+        let selected = case
+            .is_none_or(|case| env::var("PARCHECK_CASE").map_or(true, |selected| selected == case));
+
+        if let Some(trace) = env::var("PARCHECK_REPLAY").ok().filter(|_| selected) {
             let trace = trace.parse().expect("can't parse PARCHECK_REPLAY");
-            runner.iteration_config = IterationConfig::Replay { trace };
+            self.iteration_config = IterationConfig::Replay { trace };
         } else if let Ok(max_iterations) = env::var("PARCHECK_MAX_ITERATIONS") {
-            runner.iteration_config = IterationConfig::Iterate {
+            self.iteration_config = IterationConfig::Iterate {
                 max_iterations: max_iterations
                     .parse()
                     .expect("failed to parse PARCHECK_MAX_ITERATIONS"),
             };
         }
 
-        runner
+        if let Ok(notes) = env::var("PARCHECK_NOTES") {
+            self.reporter = Reporter::from_env_value(&notes);
+        }
+
+        if env::var_os("PARCHECK_RETRY_SAME_SCHEDULE").is_some() {
+            self = self.retry_same_schedule(true);
+        }
+
+        if let Some(at) = env::var("PARCHECK_BREAK_AT").ok().filter(|_| selected) {
+            self = self.break_at(
+                Breakpoint::from_env_value(&at),
+                breakpoint::wait_for_debugger(),
+            );
+        }
+
+        if let Some(seed) = env::var("PARCHECK_SEED").ok().filter(|_| selected) {
+            let seed: u64 = seed.parse().expect("failed to parse PARCHECK_SEED");
+            self = self.seed(case.map_or(seed, |case| seed ^ fnv1a(case.as_bytes())));
+        }
+
+        #[cfg(feature = "otel")]
+        if let Ok(path) = env::var("PARCHECK_OTEL_FILE") {
+            self.otel_export = Some(path.into());
+        }
+
+        #[cfg(feature = "interactive")]
+        if env::var_os("PARCHECK_INTERACTIVE").is_some() {
+            self = self.interactive_stdin();
+        }
+
+        self
     }
 
+    /// Follows steps of the trace, then picks random steps once the trace runs out. Steps that
+    /// only name the task (`task.op`, e.g. written by hand from logs) are resolved to the first
+    /// task with that name waiting for the operation.
     pub fn replay(mut self, trace: Trace) -> Self {
         self.iteration_config = IterationConfig::Replay { trace };
         self
     }
 
+    /// Saves the schedule of a failed iteration and replays it instead of exploring the next time
+    /// the test runs, until it passes. Meant for retries of `cargo nextest run --retries`, so that
+    /// a retried test fails on the same schedule instead of flakily passing on another one.
+    /// Schedules are kept per nextest run, test (name of the thread the runner is created on) and
+    /// initial tasks of the scenario. Has no effect outside of nextest (without `NEXTEST` and
+    /// `NEXTEST_RUN_ID` environment variables). Can also be enabled with
+    /// `PARCHECK_RETRY_SAME_SCHEDULE` environment variable.
+    pub fn retry_same_schedule(mut self, retry: bool) -> Self {
+        self.retry = retry.then(RetryFile::for_current_test).flatten();
+        self
+    }
+
+    /// Records schedules of failed iterations in the file at `path` (kept across runs, e.g. next
+    /// to the test) and explores schedules closest to the ones that failed most often first. Runs
+    /// after a code change re-check historically fragile interleavings before spending the
+    /// iteration budget elsewhere. Has no effect with [`Strategy::Pct`].
+    pub fn failure_history(mut self, path: impl Into<PathBuf>) -> Self {
+        self.history = Some(FailureHistory::load(path.into()));
+        self
+    }
+
     pub fn max_iterations(mut self, max_iterations: u64) -> Self {
         self.iteration_config = IterationConfig::Iterate { max_iterations };
         self
     }
 
+    /// Fails the run if the [`Structure`] of the scenario (see [`Report::structure`]) differs
+    /// from `structure`, e.g. one captured by a previous run. Guards against changes of code under
+    /// test that silently change what the test explores.
+    pub fn expect_structure(mut self, structure: Structure) -> Self {
+        self.expected_structure = Some(structure);
+        self
+    }
+
+    /// Makes random decisions (which schedule to explore next, PCT priorities, etc.) reproducible:
+    /// given the same seed and a deterministic scenario, the same schedules are explored in the
+    /// same order. Can also be set with `PARCHECK_SEED` environment variable.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = Rng::with_seed(seed);
+        self
+    }
+
+    /// Draws random decisions from `source` instead of the built-in generator (replaces
+    /// [`Runner::seed`]). Every iteration uses its own [`RandomSource::split`] of the source.
+    pub fn rng(mut self, source: impl RandomSource + 'static) -> Self {
+        self.rng = Rng::custom(Box::new(source));
+        self
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Executes given schedules before exploring new ones. Each schedule is replayed the same way
+    /// as with [`Runner::replay`]. Ignored when replaying a single trace.
+    pub fn seed_schedules(mut self, schedules: impl IntoIterator<Item = Trace>) -> Self {
+        self.seed_schedules.extend(schedules);
+        self
+    }
+
+    /// Fails the iteration if task with given name takes longer than `deadline` to finish
+    /// (measured from the moment it started).
+    pub fn task_deadline(mut self, task: impl Into<String>, deadline: Duration) -> Self {
+        self.controller_config
+            .task_deadlines
+            .insert(TaskName(task.into().into()), deadline);
+        self
+    }
+
+    /// Continues new schedules with the cheapest operation available (see [`Cost`](crate::Cost)), so
+    /// expensive operations are executed as late as possible in the schedules explored first.
+    /// Useful with `max_iterations` when only a fraction of schedules can be explored. Doesn't
+    /// apply to [`Strategy::Pct`] and random schedules of [`Strategy::ShallowFirst`].
+    pub fn prefer_cheap_operations(mut self, prefer: bool) -> Self {
+        self.costs.prefer_cheap = prefer;
+        self
+    }
+
+    /// Doesn't explore schedules that preempt a task more than `max` times to execute an
+    /// operation declared with `cost = High` (see [`Cost`](crate::Cost)). A preemption is a step executed by
+    /// a task other than the one that executed the previous step, while that task could
+    /// continue. Doesn't apply to [`Strategy::Pct`] and random schedules of
+    /// [`Strategy::ShallowFirst`].
+    pub fn max_expensive_preemptions(mut self, max: usize) -> Self {
+        self.costs.max_expensive_preemptions = Some(max);
+        self
+    }
+
+    /// Models locks as fair: once a task is blocked by a lock held by another task, it acquires
+    /// the scope before tasks that were blocked later or that didn't have to wait yet (like
+    /// `tokio::sync::RwLock`). By default locks are unfair: when a scope is released, every
+    /// order in which waiting tasks acquire it is explored.
+    pub fn fair_locks(mut self, fair: bool) -> Self {
+        self.controller_config.fair_locks = fair;
+        self
+    }
+
+    /// Decides which lock modes conflict with each other, by default
+    /// [`SharedExclusive`](crate::SharedExclusive). Needed for locks acquired in custom modes with
+    /// [`ParcheckLock::Acquire`](crate::ParcheckLock::Acquire).
+    pub fn lock_model(mut self, model: impl LockModel + 'static) -> Self {
+        self.controller_config.lock_model = Some(Arc::new(model));
+        self
+    }
+
+    /// Fails the iteration if a task executes operations declared at different `operation!` call
+    /// sites under the same name. Such operations are indistinguishable in traces, so replaying
+    /// a trace may execute a different operation than the one that was recorded.
+    pub fn unique_op_names(mut self, unique: bool) -> Self {
+        self.controller_config.unique_op_names = unique;
+        self
+    }
+
+    /// Fails the iteration if a task finishes without executing any operations, instead of
+    /// printing a warning. Such a task isn't controlled by parcheck at all, usually because
+    /// `parcheck::operation!` annotations are missing or compiled out.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.controller_config.strict = strict;
+        self
+    }
+
+    /// Keeps locks of cancelled tasks (task futures dropped before completion) held until the end
+    /// of the iteration, like locks of a crashed process that are never released. By default they
+    /// are released when a task is cancelled.
+    pub fn leak_cancelled_locks(mut self, leak: bool) -> Self {
+        self.controller_config.leak_cancelled_locks = leak;
+        self
+    }
+
+    /// Fails the iteration if any two operations executed at the same time (wall-clock), also
+    /// within a single task, and reports both of them. Operations are granted one at a time, so
+    /// this only happens if a task handle is shared with spawned futures that execute operations
+    /// concurrently (e.g. with `tokio::join!` or `tokio::spawn` inside of a task scope).
+    pub fn detect_overlapping_operations(mut self, detect: bool) -> Self {
+        self.controller_config.detect_overlaps = detect;
+        self
+    }
+
+    /// Appends every iteration to the file at `path` as a line of JSON in the OTLP/JSON trace
+    /// format (one `ExportTraceServiceRequest` per line): steps are spans of an iteration span,
+    /// failed iterations have error status. Nothing is sent anywhere and no OpenTelemetry SDK is
+    /// used, the file can be read with `cargo parcheck report` or ingested by an OpenTelemetry
+    /// collector (e.g. its `otlpjsonfile` receiver). Can also be set with `PARCHECK_OTEL_FILE`
+    /// environment variable.
+    #[cfg(feature = "otel")]
+    pub fn otel_export(mut self, path: impl Into<PathBuf>) -> Self {
+        self.otel_export = Some(path.into());
+        self
+    }
+
+    /// Executes a single iteration where every step is chosen by `choose` among tasks that can
+    /// execute their operations. Takes precedence over replay and exploration settings.
+    #[cfg(feature = "interactive")]
+    pub fn interactive(mut self, choose: ChooseStep) -> Self {
+        self.interactive = Some(choose);
+        self
+    }
+
+    /// Like [`Runner::interactive`], but candidates are printed to stderr and the next step is
+    /// read from stdin. Can also be enabled with `PARCHECK_INTERACTIVE` environment variable.
+    #[cfg(feature = "interactive")]
+    pub fn interactive_stdin(self) -> Self {
+        self.interactive(Box::new(interactive::choose_from_stdin))
+    }
+
     pub fn on_panic(mut self, on_panic: PanicHandler) -> Self {
         self.on_panic = Some(on_panic);
         self
     }
 
-    pub fn before_step(mut self, before_step: BeforeStep) -> Self {
-        self.before_step = Some(before_step);
+    /// Where notes about failed iterations (e.g. how to replay the failed schedule) and warnings
+    /// are written, stderr by default. Can also be set with `PARCHECK_NOTES` environment variable
+    /// (`stderr`, `silent` or path of a file to append to).
+    pub fn reporter(mut self, reporter: Reporter) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    pub fn before_step(self, mut before_step: BeforeStep) -> Self {
+        self.before_step_with_context(Box::new(move |_| before_step()))
+    }
+
+    pub fn after_step(self, mut after_step: AfterStep) -> Self {
+        self.after_step_with_context(Box::new(move |_| after_step()))
+    }
+
+    /// Like [`Runner::before_step`], but `hook` is given the step that is about to be executed.
+    pub fn before_step_with_context(mut self, hook: StepHook) -> Self {
+        self.hooks.before_step = Some(hook);
+        self
+    }
+
+    /// Like [`Runner::after_step`], but `hook` is given the step that has just been executed.
+    pub fn after_step_with_context(mut self, hook: StepHook) -> Self {
+        self.hooks.after_step = Some(hook);
         self
     }
 
-    pub fn after_step(mut self, after_step: AfterStep) -> Self {
-        self.after_step = Some(after_step);
+    pub fn before_iter(mut self, before_iter: BeforeIter) -> Self {
+        self.hooks.before_iter = Some(before_iter);
         self
     }
 
-    pub fn before_iter(mut self, before_iter: BeforeStep) -> Self {
-        self.before_iter = Some(before_iter);
+    pub fn after_iter(mut self, after_iter: AfterIter) -> Self {
+        self.hooks.after_iter = Some(after_iter);
         self
     }
 
-    pub fn after_iter(mut self, after_iter: AfterStep) -> Self {
-        self.after_iter = Some(after_iter);
+    /// Pauses iterations before executing the step `at` (index of a step, e.g. of a replayed
+    /// trace, or name of an operation) until `hook` finishes, e.g. to inspect external state
+    /// (database rows) exactly at the racy moment. Can also be set with `PARCHECK_BREAK_AT`
+    /// environment variable, which pauses until a debugger is attached (see
+    /// [`wait_for_debugger`](crate::wait_for_debugger)).
+    pub fn break_at(mut self, at: impl Into<Breakpoint>, hook: StepHook) -> Self {
+        self.hooks.breakpoints.push((at.into(), hook));
         self
     }
 
-    pub async fn run<'a, I, F, Fut>(self, initial_tasks: I, mut f: F)
+    /// Calls `hook` when an operation with given name is granted and when it finishes.
+    pub fn on_operation(mut self, name: impl Into<String>, hook: OperationHook) -> Self {
+        self.hooks.on_operation.push((name.into(), hook));
+        self
+    }
+
+    /// Records steps that could be chosen at every step of traces (see
+    /// [`TraceStep::candidates`](crate::TraceStep::candidates)), so that it can be analyzed how
+    /// constrained each decision was.
+    pub fn record_candidates(mut self, record: bool) -> Self {
+        self.hooks.record_candidates = record;
+        self
+    }
+
+    /// Inverts the outcome of the run: it passes as soon as a schedule panics with a message
+    /// containing `pattern` (the schedule is reported and returned by
+    /// [`Report::expected_panic`]), and fails if no explored schedule does. Other panics still
+    /// fail the run. Demonstrates a known race before it's fixed. Methods that return the state
+    /// of the scenario (e.g. [`Runner::run_with_state`]) have no state to return after the panic,
+    /// so they panic as well.
+    pub fn expect_panic_matching(mut self, pattern: impl Into<String>) -> Self {
+        self.expected_panic = Some(pattern.into());
+        self
+    }
+
+    /// Classifies failed iterations by their panic payload (a `String` with the message for
+    /// errors detected by parcheck itself, a [`ScenarioError`] for errors returned by
+    /// [`Runner::try_run`] scenarios) and schedule. Failures are grouped by class in
+    /// [`Report::failure_classes`], notes about them name the class, and the class's
+    /// [`FailureAction`] decides whether the run fails, continues exploring or retries the
+    /// schedule (e.g. `connection refused` of a test database). Only failures of
+    /// [`FailureAction::Fail`] are recorded for [`Runner::retry_same_schedule`] and
+    /// [`Runner::failure_history`]. Scenarios with state ([`Runner::run_with_state`] and
+    /// [`Runner::run_with_resource`]) lose it in the failed iteration, so their failures always
+    /// fail the run.
+    pub fn classify_failure(
+        mut self,
+        classify: impl FnMut(&(dyn Any + Send), &Trace) -> FailureClass + 'static,
+    ) -> Self {
+        self.classify = Some(Box::new(classify));
+        self
+    }
+
+    /// Re-runs the same schedule, up to `max_retries` times, after failures whose class (see
+    /// [`Runner::classify_failure`]) satisfies `retry`, e.g. transient errors of a database in a
+    /// container. The failure is only reported if the schedule still fails after the last retry.
+    /// Defaults to 3 retries of classes with [`FailureAction::Retry`].
+    pub fn retry_on(
+        mut self,
+        retry: impl Fn(&FailureClass) -> bool + 'static,
+        max_retries: u32,
+    ) -> Self {
+        self.retry_on = (Box::new(retry), max_retries);
+        self
+    }
+
+    /// Warns (see [`Runner::reporter`]) if the schedule space, estimated after the first
+    /// iterations, exceeds `threshold` schedules and more schedules than `max_iterations`, naming
+    /// the operations that branch the most. Defaults to 1 000 000, `u64::MAX` disables the
+    /// warning.
+    pub fn schedule_space_warning(mut self, threshold: u64) -> Self {
+        self.space_warning = threshold;
+        self
+    }
+
+    /// Saves the exploration state (explored schedules, random number generator and number of
+    /// iterations) to the file at `path` every `interval`, so that a run interrupted or stopped by
+    /// `max_iterations` (e.g. a CI job with a time limit) is resumed by the next run exactly where
+    /// it stopped. `max_iterations` then limits iterations of all resumed runs together. The file
+    /// is ignored if the test's initial tasks changed, and removed once every schedule is explored.
+    /// Alternatives skipped by reductions and unreachable branches aren't saved, so reports of
+    /// resumed runs count only those of the last run.
+    pub fn checkpoint(mut self, path: impl Into<PathBuf>, interval: Duration) -> Self {
+        self.checkpoint = Some(Checkpoint::new(path.into(), interval));
+        self
+    }
+
+    /// Delays each operation by the returned duration after its permit is granted.
+    pub fn step_latency(mut self, step_latency: StepLatency) -> Self {
+        self.hooks.step_latency = Some(step_latency);
+        self
+    }
+
+    pub async fn run<'a, I, F, Fut>(mut self, initial_tasks: I, mut f: F) -> Report
     where
         I: IntoIterator,
-        I::Item: Into<String>,
+        I::Item: AsRef<str>,
         F: FnMut() -> Fut,
         Fut: Future<Output = ()>,
     {
-        self.run_with_state(initial_tasks, (), |()| f()).await;
+        let (_, report) = self
+            .explore(initial_tasks, (), |()| f(), Stateless(CurrentRuntime))
+            .await;
+        report
     }
 
-    #[allow(clippy::too_many_lines)] // TODO: refactor
-    pub async fn run_with_state<'a, T, I, F, Fut>(
-        mut self,
+    /// Like [`Runner::run`], but the scenario returns a `Result`, so that it can use `?`.
+    ///
+    /// # Errors
+    ///
+    /// An iteration that returns an error fails like one that panics (its schedule is reported),
+    /// and the error is returned. The iteration unwinds with a [`ScenarioError`] payload, so
+    /// [`Runner::classify_failure`] can inspect the error and [`Runner::expect_panic_matching`]
+    /// matches its message.
+    pub async fn try_run<I, F, Fut, E>(
+        self,
         initial_tasks: I,
-        mut state: T,
         mut f: F,
-    ) -> T
+    ) -> Result<Report, Box<dyn Error + Send + Sync>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let result = AssertUnwindSafe(self.run(initial_tasks, || {
+            let fut = f();
+            async { fut.await.unwrap_or_else(|error| fail_with(error)) }
+        }))
+        .catch_unwind()
+        .await;
+        unwind_error(result)
+    }
+
+    /// Like [`Runner::run_with_state`], but the scenario returns a `Result`.
+    ///
+    /// # Errors
+    ///
+    /// Error of the first failed iteration, see [`Runner::try_run`].
+    pub async fn try_run_with_state<T, I, F, Fut, E>(
+        self,
+        initial_tasks: I,
+        state: T,
+        mut f: F,
+    ) -> Result<T, Box<dyn Error + Send + Sync>>
     where
         I: IntoIterator,
-        I::Item: Into<String>,
+        I::Item: AsRef<str>,
+        F: FnMut(T) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let result = AssertUnwindSafe(self.run_with_state(initial_tasks, state, |state| {
+            let fut = f(state);
+            async { fut.await.unwrap_or_else(|error| fail_with(error)) }
+        }))
+        .catch_unwind()
+        .await;
+        unwind_error(result)
+    }
+
+    /// Like [`Runner::run`], but tasks aren't looked up by name: every iteration passes handles
+    /// of `initial_tasks` (in the same order) into `f`, and futures are bound to them with
+    /// [`task_with`](crate::task_with). Several tasks can have the same name.
+    pub async fn run_with_handles<I, F, Fut>(mut self, initial_tasks: I, mut f: F) -> Report
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        F: FnMut(Vec<TaskRegistration>) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let registrations = Registrations::default();
+        self.controller_config.registrations = Some(Arc::clone(&registrations));
+        let (_, report) = self
+            .explore(
+                initial_tasks,
+                (),
+                |()| f(mem::take(&mut *registrations.lock().unwrap())),
+                Stateless(CurrentRuntime),
+            )
+            .await;
+        report
+    }
+
+    /// Like [`Runner::run`], but returns a stream of [`StepEvent`](crate::StepEvent)s, so that
+    /// execution can be driven and observed by an external consumer. Iterations execute on the
+    /// runtime the stream is polled from.
+    pub fn stream<'a, I, F, Fut>(mut self, initial_tasks: I, f: F) -> StepStream<'a>
+    where
+        I: IntoIterator + 'a,
+        I::Item: AsRef<str>,
+        F: FnMut() -> Fut + 'a,
+        Fut: Future<Output = ()> + 'a,
+    {
+        let events = EventSink::default();
+        self.hooks.events = Some(events.clone());
+        StepStream::new(self.run(initial_tasks, f), events)
+    }
+
+    /// Like [`Runner::run`], but each iteration is executed on a dedicated current-thread tokio
+    /// runtime (on a separate thread), so background tasks and timers leaked by one iteration are
+    /// dropped together with its runtime and can't affect subsequent iterations.
+    ///
+    /// Hooks and the controller stay on the calling runtime, only the scenario itself is moved.
+    pub async fn run_isolated<I, F, Fut>(mut self, initial_tasks: I, f: F) -> Report
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = ()>,
+    {
+        let f = Arc::new(Mutex::new(f));
+        let (_, report) = self
+            .explore(
+                initial_tasks,
+                (),
+                |()| run_on_new_runtime(Arc::clone(&f)),
+                Stateless(CurrentRuntime),
+            )
+            .await;
+        report
+    }
+
+    /// Like [`Runner::run`], but every iteration starts by calling `setup` to create a resource
+    /// (e.g. a database transaction). The resource is passed into `f`, and whatever `f` returns
+    /// is handed to `teardown` at the end of the iteration. If the iteration panics, the resource
+    /// is dropped instead.
+    pub async fn run_with_resource<I, R, S, SFut, F, Fut, D, DFut>(
+        mut self,
+        initial_tasks: I,
+        setup: S,
+        f: F,
+        teardown: D,
+    ) -> Report
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        S: FnMut() -> SFut,
+        SFut: Future<Output = R>,
+        F: FnMut(R) -> Fut,
+        Fut: Future<Output = R>,
+        D: FnMut(R) -> DFut,
+        DFut: Future<Output = ()>,
+    {
+        let (_, report) = self
+            .explore(
+                initial_tasks,
+                (setup, f, teardown),
+                |(mut setup, mut f, mut teardown)| async move {
+                    let resource = setup().await;
+                    let resource = f(resource).await;
+                    teardown(resource).await;
+                    (setup, f, teardown)
+                },
+                CurrentRuntime,
+            )
+            .await;
+        report
+    }
+
+    pub async fn run_with_state<'a, T, I, F, Fut>(mut self, initial_tasks: I, state: T, f: F) -> T
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
         F: FnMut(T) -> Fut,
         Fut: Future<Output = T>,
     {
-        // TODO: add to config
-        const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+        let (state, _) = self.explore(initial_tasks, state, f, CurrentRuntime).await;
+        state.expect(NO_STATE_AFTER_EXPECTED_PANIC)
+    }
 
+    /// Explores schedules of the scenario with [`Variant::Before`] of `f`, then replays every
+    /// explored schedule with [`Variant::After`] (as far as it can be followed, then with random
+    /// steps) and compares outcomes of the variants per schedule. Shows whether a change, e.g. a
+    /// fix behind a feature flag, changes behavior only in the intended schedules.
+    ///
+    /// Failures of the scenario are outcomes and don't fail the run (apart from their panic
+    /// messages). [`Runner::on_panic`] handler and [`Runner::expect_panic_matching`] are ignored.
+    pub async fn compare<I, F, Fut>(mut self, initial_tasks: I, mut f: F) -> Comparison
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        F: FnMut(Variant) -> Fut,
+        Fut: Future<Output = ()>,
+    {
         let initial_tasks: Vec<TaskName> = initial_tasks
             .into_iter()
-            .map(|name| TaskName(name.into()))
+            .map(|name| TaskName(name.as_ref().into()))
             .collect();
+        self.on_panic = None;
+        self.expected_panic = None;
 
-        let max_iterations = match self.iteration_config {
-            IterationConfig::Replay { trace } => {
-                let mut controller = Controller::register(&initial_tasks);
+        self.traces = Some(Vec::new());
+        let (before, _) = self
+            .explore(
+                initial_tasks.iter().map(|name| &*name.0),
+                Vec::new(),
+                |mut outcomes| {
+                    let before = outcome(f(Variant::Before));
+                    async move {
+                        outcomes.push(before.await);
+                        outcomes
+                    }
+                },
+                CurrentRuntime,
+            )
+            .await;
+        let before = before.unwrap_or_default();
+        let before_traces = self.traces.replace(Vec::new()).unwrap_or_default();
 
-                let control = async {
-                    let mut steps_from_trace = trace.steps.into_iter();
-                    let mut rng = Rng::new();
+        let mut report = Report::default();
+        let mut after = Vec::with_capacity(before.len());
+        for trace in &before_traces {
+            let mut rng = self.rng.fork();
+            after.push(
+                self.iteration(
+                    &CurrentRuntime,
+                    &initial_tasks,
+                    None,
+                    &mut |_| outcome(f(Variant::After)),
+                    follow_picker(trace.clone(), &mut rng),
+                    &mut report,
+                )
+                .await,
+            );
+        }
+        let after_traces = self.traces.take().unwrap_or_default();
 
-                    if let Some(before_iter) = &mut self.before_iter {
-                        before_iter().await;
-                    }
+        let schedules = before_traces
+            .into_iter()
+            .zip(after_traces)
+            .zip(before.into_iter().zip(after))
+            .map(
+                |((before_trace, after_trace), (before, after))| ComparedSchedule {
+                    before_trace,
+                    after_trace,
+                    before,
+                    after,
+                },
+            )
+            .collect();
+        Comparison { schedules }
+    }
 
-                    loop {
-                        let _tasks = controller.ready(WAIT_TIMEOUT).await;
-                        if let Some(before_step) = &mut self.before_step {
-                            before_step().await;
-                        }
-
-                        let task_id = steps_from_trace
-                            .next()
-                            .map(|(task_id, _, _)| {
-                                // TODO: check task name, op name
-                                task_id
-                            })
-                            .or_else(|| {
-                                let candidates = controller
-                                    .tasks()
-                                    .iter()
-                                    .filter_map(|(task, state)| {
-                                        state.can_execute().then_some(task.id())
-                                    })
-                                    .collect::<Vec<TaskId>>();
-
-                                if candidates.is_empty() {
-                                    return None;
-                                }
-
-                                Some(candidates[rng.usize(..candidates.len())])
-                            });
-
-                        let Some(task_id) = task_id else {
-                            break;
-                        };
-
-                        controller.step_forward(task_id).await;
-                        if let Some(after_step) = &mut self.after_step {
-                            after_step().await;
-                        }
-                    }
+    /// Searches for the smallest `max_iterations` that reproduces a failure of this configuration
+    /// with one of the `seeds` (see [`Runner::seed`]). For every seed, the number of iterations is
+    /// doubled (up to `max_iterations`) until the scenario fails, and then narrowed down with a
+    /// binary search. Returns `None` if the scenario doesn't fail within `max_iterations` with any
+    /// of the seeds. [`Runner::max_iterations`] is ignored.
+    ///
+    /// Failures found during the search are not reported (apart from their panic messages) and
+    /// every failure fails its probe. [`Runner::on_panic`] handler, [`Runner::seed_schedules`],
+    /// [`Runner::expect_panic_matching`], [`Runner::checkpoint`], [`Runner::failure_history`],
+    /// [`Runner::retry_same_schedule`], [`Runner::classify_failure`] and [`Runner::retry_on`] are
+    /// ignored.
+    pub async fn find_minimal_iterations<I, F, Fut>(
+        mut self,
+        seeds: impl IntoIterator<Item = u64>,
+        max_iterations: u64,
+        initial_tasks: I,
+        mut f: F,
+    ) -> Option<Reproduction>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let IterationConfig::Iterate { .. } = self.iteration_config else {
+            panic!("can't search for minimal iterations of a replayed trace");
+        };
+        let initial_tasks: Vec<Arc<str>> = initial_tasks
+            .into_iter()
+            .map(|name| name.as_ref().into())
+            .collect();
+        self.seed_schedules.clear();
+        self.expected_panic = None;
+        self.checkpoint = None;
+        self.history = None;
+        self.retry = None;
+        self.classify = None;
+        self.retry_on = (Box::new(|_| false), 0);
+        self.searching = true;
 
-                    controller.assert_finished();
-                    drop(controller);
+        let mut found: Option<Reproduction> = None;
+        for seed in seeds {
+            // only looking for configurations smaller than the one already found
+            let limit = found
+                .as_ref()
+                .map_or(max_iterations, |found| found.iterations - 1);
 
-                    if let Some(after_iter) = &mut self.after_iter {
-                        after_iter().await;
-                    }
-                };
+            let mut passed = 0;
+            let mut failed = None;
+            while failed.is_none() && passed < limit {
+                let iterations = passed.saturating_mul(2).clamp(1, limit);
+                match self
+                    .reproduce(seed, iterations, &initial_tasks, &mut f)
+                    .await
+                {
+                    Some(trace) => failed = Some((iterations, trace)),
+                    None => passed = iterations,
+                }
+            }
+            let Some((mut iterations, mut trace)) = failed else {
+                continue;
+            };
+
+            while iterations - passed > 1 {
+                let middle = passed + (iterations - passed) / 2;
+                match self.reproduce(seed, middle, &initial_tasks, &mut f).await {
+                    Some(failure) => (iterations, trace) = (middle, failure),
+                    None => passed = middle,
+                }
+            }
 
-                // TODO: handle panics
-                (state, ()) = join!(f(state), control);
-                return state;
+            found = Some(Reproduction {
+                seed,
+                iterations,
+                trace,
+            });
+            if iterations == 1 {
+                break;
+            }
+        }
+        found
+    }
+
+    /// Executes a single iteration (tasks execute operations in order of their ids) to capture
+    /// its [`Structure`], and lists up to `limit` schedules of it without executing them. Useful
+    /// to gauge the number of schedules before running an exhaustive exploration. Use
+    /// [`Structure`] directly to enumerate schedules of a model without executing the scenario.
+    pub async fn enumerate_schedules<I, F, Fut>(
+        mut self,
+        limit: usize,
+        initial_tasks: I,
+        mut f: F,
+    ) -> ScheduleEnumeration
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let initial_tasks: Vec<TaskName> = initial_tasks
+            .into_iter()
+            .map(|name| TaskName(name.as_ref().into()))
+            .collect();
+        let Outcome { result, trace, .. } = CurrentRuntime
+            .execute(
+                &mut self.hooks,
+                &self.controller_config,
+                &initial_tasks,
+                (),
+                &mut |()| f(),
+                |tasks: &[(Task, TaskState)]| {
+                    tasks
+                        .iter()
+                        .find(|(_, state)| state.can_execute())
+                        .map(|(task, _)| Choice {
+                            task_id: task.id(),
+                            inject_fault: false,
+                        })
+                },
+            )
+            .await;
+        unwrap_iteration(
+            result,
+            &trace,
+            self.case.as_deref(),
+            &mut self.on_panic,
+            &mut self.reporter,
+        );
+
+        let structure = Structure::from_trace(&trace);
+        ScheduleEnumeration {
+            schedules: structure.schedules().take(limit).collect(),
+            structure,
+        }
+    }
+
+    /// Runs `iterations` with the given seed and returns trace of the failed iteration.
+    async fn reproduce<F, Fut>(
+        &mut self,
+        seed: u64,
+        iterations: u64,
+        initial_tasks: &[Arc<str>],
+        f: &mut F,
+    ) -> Option<Trace>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let failure = Rc::new(RefCell::new(None));
+        self.rng = Rng::with_seed(seed);
+        self.iteration_config = IterationConfig::Iterate {
+            max_iterations: iterations,
+        };
+        self.on_panic = Some(Box::new({
+            let failure = Rc::clone(&failure);
+            move |trace| *failure.borrow_mut() = Some(trace.clone())
+        }));
+
+        let _ = AssertUnwindSafe(self.explore(initial_tasks, (), |()| f(), CurrentRuntime))
+            .catch_unwind()
+            .await;
+        self.on_panic = None;
+        failure.take()
+    }
+
+    /// Explores schedules, `None` instead of the state if a schedule panicked as expected (see
+    /// [`Runner::expect_panic_matching`]).
+    async fn explore<T, I, F, E>(
+        &mut self,
+        initial_tasks: I,
+        state: T,
+        f: F,
+        executor: E,
+    ) -> (Option<T>, Report)
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        E: Executor<T, F>,
+    {
+        let Some(pattern) = self.expected_panic.clone() else {
+            let (state, report) = self
+                .explore_schedules(initial_tasks, state, f, executor)
+                .await;
+            return (Some(state), report);
+        };
+        match AssertUnwindSafe(self.explore_schedules(initial_tasks, state, f, executor))
+            .catch_unwind()
+            .await
+        {
+            Ok(_) => panic!("no explored schedule panicked with a message matching {pattern:?}"),
+            Err(payload) => match payload.downcast::<ExpectedPanic>() {
+                Ok(expected) => (None, expected.0),
+                Err(payload) => panic::resume_unwind(payload),
+            },
+        }
+    }
+
+    async fn explore_schedules<T, I, F, E>(
+        &mut self,
+        initial_tasks: I,
+        mut state: T,
+        mut f: F,
+        executor: E,
+    ) -> (T, Report)
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        E: Executor<T, F>,
+    {
+        let mut report = Report::default();
+        let initial_tasks: Vec<TaskName> = initial_tasks
+            .into_iter()
+            .map(|name| TaskName(name.as_ref().into()))
+            .collect();
+
+        #[cfg(feature = "otel")]
+        if let Some(path) = &self.otel_export {
+            self.otel_exporter = Some(OtelExporter::create(path));
+        }
+        // with fast permits an operation may execute before its step is picked, so operation
+        // spans can't follow from step spans
+        self.controller_config.fast_permits =
+            !cfg!(feature = "tracing") && !self.hooks.observe_granted_steps();
+        #[cfg(feature = "otel")]
+        if self.otel_exporter.is_some() {
+            self.controller_config.fast_permits = false;
+        }
+        // every step is executed once it's chosen, even if it's the only candidate
+        #[cfg(feature = "interactive")]
+        if self.interactive.is_some() {
+            self.controller_config.fast_permits = false;
+        }
+
+        #[cfg(feature = "interactive")]
+        if let Some(mut choose) = self.interactive.take() {
+            let pick = interactive::picker(&mut choose);
+            let state = self
+                .iteration(&executor, &initial_tasks, state, &mut f, pick, &mut report)
+                .await;
+            return (state, report);
+        }
+
+        if let Some(retry) = &mut self.retry {
+            retry.select_scenario(&initial_tasks);
+        }
+        self.replay_retried_schedule();
+        let max_iterations = match &self.iteration_config {
+            IterationConfig::Replay { trace } => {
+                let trace = trace.clone();
+                let mut rng = self.rng.fork();
+                let state = self
+                    .iteration(
+                        &executor,
+                        &initial_tasks,
+                        state,
+                        &mut f,
+                        replay_picker(trace, &mut rng),
+                        &mut report,
+                    )
+                    .await;
+                if let Some(retry) = &self.retry {
+                    retry.clear();
+                }
+                return (state, report);
             }
             IterationConfig::Iterate {
                 max_iterations: max_iter,
-            } => max_iter,
+            } => *max_iter,
         };
 
-        let mut schedule_tree = ScheduleTree::new(&initial_tasks);
+        for seed in std::mem::take(&mut self.seed_schedules) {
+            let mut rng = self.rng.fork();
+            state = self
+                .iteration(
+                    &executor,
+                    &initial_tasks,
+                    state,
+                    &mut f,
+                    replay_picker(seed, &mut rng),
+                    &mut report,
+                )
+                .await;
+        }
+
+        if let Strategy::Pct { depth } = self.strategy {
+            let max_iterations = random_iterations(max_iterations);
+            let mut pct = Pct::new(depth);
+            for _ in 0..max_iterations {
+                let mut schedule = pct.schedule(self.rng.fork());
+                state = self
+                    .iteration(
+                        &executor,
+                        &initial_tasks,
+                        state,
+                        &mut f,
+                        |tasks: &[(Task, TaskState)]| schedule.pick(tasks),
+                        &mut report,
+                    )
+                    .await;
+                pct.record(&schedule);
+            }
+            report.detection = Some(pct.estimate(max_iterations));
+            return (state, report);
+        }
+
+        let state = self
+            .explore_tree(
+                &executor,
+                &initial_tasks,
+                state,
+                &mut f,
+                max_iterations,
+                &mut report,
+            )
+            .await;
+        (state, report)
+    }
+
+    /// Explores schedules of the [`ScheduleTree`] according to the strategy, then (with
+    /// [`Strategy::ShallowFirst`]) spends the rest of iterations on random schedules.
+    async fn explore_tree<T, F, E>(
+        &mut self,
+        executor: &E,
+        initial_tasks: &[TaskName],
+        mut state: T,
+        f: &mut F,
+        max_iterations: u64,
+        report: &mut Report,
+    ) -> T
+    where
+        E: Executor<T, F>,
+    {
+        let mut schedule_tree = ScheduleTree::new(initial_tasks, self.costs);
         let mut iter = 0;
+        let mut checkpoint = self.checkpoint.take();
+        if let Some(restored) = checkpoint
+            .as_ref()
+            .and_then(|checkpoint| checkpoint.load(initial_tasks, self.costs))
+        {
+            self.reporter.note(&format!(
+                "note: resuming exploration from checkpoint {} after {} iterations",
+                checkpoint.as_ref().unwrap().path().display(),
+                restored.iterations
+            ));
+            schedule_tree = restored.tree;
+            iter = restored.iterations;
+            if let Some(rng) = restored.rng {
+                self.rng = rng;
+            }
+        }
+        if let Some(history) = &self.history {
+            schedule_tree.prefer_failed(history.failed().to_vec());
+        }
+        if let Strategy::ShallowFirst { depth } = self.strategy {
+            schedule_tree.limit_branching_depth(depth);
+        }
+        let mut saturation = Saturation::new(self.strategy);
 
         while schedule_tree.has_unfinished_paths() && iter < max_iterations {
-            let mut controller = Controller::register(&initial_tasks);
-            let mut trace = Trace::new();
+            let discovered = schedule_tree.discovered();
+            let mut rng = self.rng.fork();
+            let mut cursor = schedule_tree.pick_unfinished_path(&mut rng).unwrap();
 
-            let control = async {
-                let mut rng = Rng::new();
-                let mut cursor = schedule_tree.pick_unfinished_path(&mut rng).unwrap();
+            state = self
+                .iteration(
+                    executor,
+                    initial_tasks,
+                    state,
+                    f,
+                    |tasks: &[(Task, TaskState)]| cursor.visit_and_pick(tasks, &mut rng),
+                    report,
+                )
+                .await;
+            if mem::take(&mut self.continued_failure) {
+                cursor.abandon();
+            }
 
-                if let Some(before_iter) = &mut self.before_iter {
-                    before_iter().await;
-                }
+            iter += 1;
+            if let Some(checkpoint) = &mut checkpoint {
+                checkpoint.save_periodically(&schedule_tree, iter, &self.rng);
+            }
+            if iter == ESTIMATE_ITERATIONS && schedule_tree.has_unfinished_paths() {
+                self.warn_schedule_space(&schedule_tree, max_iterations);
+            }
+            if saturation.record(schedule_tree.discovered() - discovered) {
+                break;
+            }
+        }
 
-                loop {
-                    let tasks = controller.ready(WAIT_TIMEOUT).await;
-                    let Some(task_id) = cursor.visit_and_pick(tasks, &mut rng) else {
-                        break;
-                    };
+        if let Some(checkpoint) = &mut checkpoint {
+            if schedule_tree.has_unfinished_paths() && iter >= max_iterations {
+                checkpoint.save(&schedule_tree, iter, &self.rng);
+            } else {
+                checkpoint.remove();
+            }
+        }
+        self.checkpoint = checkpoint;
 
-                    let (task_name, op_name) = controller
-                        .tasks()
-                        .iter()
-                        .find_map(|(task, state)| {
-                            if task.id() == task_id {
-                                let op_metadata = state
-                                    .executable_op()
-                                    .expect("task with chosen task_id isn't executable");
-                                Some((task.name().clone(), OperationName(op_metadata.name.into())))
-                            } else {
-                                None
-                            }
-                        })
-                        .expect("can't find task name & op name for chosen task");
+        if let Strategy::ShallowFirst { .. } = self.strategy {
+            for _ in iter..random_iterations(max_iterations) {
+                let mut rng = self.rng.fork();
+                state = self
+                    .iteration(
+                        executor,
+                        initial_tasks,
+                        state,
+                        f,
+                        |tasks: &[(Task, TaskState)]| random_choice(tasks, &mut rng),
+                        report,
+                    )
+                    .await;
+            }
+        }
 
-                    trace.steps.push((task_id, task_name, op_name));
-                    if let Some(before_step) = &mut self.before_step {
-                        before_step().await;
-                    }
-                    controller.step_forward(task_id).await;
-                    if let Some(after_step) = &mut self.after_step {
-                        after_step().await;
-                    }
-                }
+        report.exhausted = !schedule_tree.has_unfinished_paths() && !schedule_tree.truncated();
+        let raw = report.structure.as_ref().map(Structure::count_schedules);
+        report.schedule_tree = Some(schedule_tree.explored());
+        report.schedule_space = Some(schedule_tree.into_schedule_space(raw));
+        state
+    }
+
+    fn warn_schedule_space(&mut self, schedule_tree: &ScheduleTree, max_iterations: u64) {
+        let Some(estimate) = schedule_tree.estimate() else {
+            return;
+        };
+        #[allow(clippy::cast_precision_loss)]
+        let limit = self.space_warning.max(max_iterations) as f64;
+        if estimate.schedules <= limit || self.space_warning == u64::MAX {
+            return;
+        }
+        let contributors = estimate
+            .contributors
+            .iter()
+            .take(3)
+            .map(|(task, operation, count)| format!("task '{task}' {operation} ({count} steps)"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.reporter.note(&format!(
+            "warning: schedule space is estimated at {:.0} schedules (average branching factor {:.1}) after {ESTIMATE_ITERATIONS} iterations, most alternatives come from: {contributors} (consider fewer tasks or operations, locks that order them, `Runner::max_iterations` or a bounded strategy)",
+            estimate.schedules, estimate.branching
+        ));
+    }
+
+    /// Warns once about every task of a successful iteration that executed no operations.
+    fn warn_idle_tasks(&mut self, idle_tasks: Vec<TaskName>) {
+        for task in idle_tasks {
+            if !self.idle_tasks.contains(&task) {
+                self.reporter.note(&format!(
+                    "warning: task '{}' finished without executing any operations, so it's not tested (are `parcheck::operation!` annotations missing or disabled?)",
+                    task.0
+                ));
+                self.idle_tasks.push(task);
+            }
+        }
+    }
+
+    /// Records the structure of the first successful iteration without injected faults, and
+    /// checks it against [`Runner::expect_structure`].
+    fn record_structure(&self, trace: &Trace, report: &mut Report) {
+        if trace.steps.iter().any(|step| step.fault.is_some()) {
+            return;
+        }
+        let structure = Structure::from_trace(trace);
+        if let Some(expected) = &self.expected_structure {
+            assert!(
+                structure == *expected,
+                "structure of the scenario changed\nexpected:\n{expected}actual:\n{structure}"
+            );
+        }
+        report.structure = Some(structure);
+    }
+
+    /// State to continue with after an iteration failed with `class`, and whether exploration
+    /// continues or the schedule is retried (see [`Runner::retry_on`]). `None` if the run fails,
+    /// because of the class, too many retries or a state that can't be recovered.
+    fn recover<T, F, E>(
+        &mut self,
+        class: &FailureClass,
+        trace: &Trace,
+        retries: u32,
+        executor: &E,
+    ) -> Option<(T, FailureAction)>
+    where
+        E: Executor<T, F>,
+    {
+        let (retry, max_retries) = &self.retry_on;
+        let action = if retry(class) {
+            (retries < *max_retries).then_some(FailureAction::Retry)
+        } else {
+            (class.action() == FailureAction::Continue).then_some(FailureAction::Continue)
+        };
+        let recovered = action.and_then(|action| Some((executor.recover_state()?, action)));
+        let note = match recovered.as_ref().map(|(_, action)| action) {
+            None => format!("note: failure classified as '{class}'"),
+            Some(FailureAction::Continue) => {
+                self.continued_failure = true;
+                format!("note: failure classified as '{class}', exploration continues: {trace}")
+            }
+            Some(_) => {
+                format!(
+                    "note: failure classified as '{class}', retrying the same schedule: {trace}"
+                )
+            }
+        };
+        self.reporter.note(&note);
+        recovered
+    }
+
+    /// Switches to replaying the schedule that failed in a previous run of the test, see
+    /// [`Runner::retry_same_schedule`].
+    fn replay_retried_schedule(&mut self) {
+        if let Some(trace) = self.retry.as_ref().and_then(RetryFile::load) {
+            self.iteration_config = IterationConfig::Replay { trace };
+        }
+    }
+
+    async fn iteration<T, F, E>(
+        &mut self,
+        executor: &E,
+        initial_tasks: &[TaskName],
+        state: T,
+        f: &mut F,
+        pick: impl FnMut(&[(Task, TaskState)]) -> Option<Choice>,
+        report: &mut Report,
+    ) -> T
+    where
+        E: Executor<T, F>,
+    {
+        self.attempt(
+            executor,
+            initial_tasks,
+            state,
+            f,
+            pick,
+            report,
+            (Vec::new(), 0),
+        )
+        .await
+    }
+
+    /// Executes an iteration that first takes the steps of `retried` failed attempts (see
+    /// [`Runner::retry_on`]), then the steps picked by `pick`. A retry thus executes the same
+    /// schedule, and `pick` (e.g. following a path of the schedule tree) continues where the
+    /// failed attempt stopped.
+    #[allow(clippy::too_many_arguments)]
+    async fn attempt<T, F, E>(
+        &mut self,
+        executor: &E,
+        initial_tasks: &[TaskName],
+        state: T,
+        f: &mut F,
+        mut pick: impl FnMut(&[(Task, TaskState)]) -> Option<Choice>,
+        report: &mut Report,
+        (retried_steps, retries): (Vec<Choice>, u32),
+    ) -> T
+    where
+        E: Executor<T, F>,
+    {
+        let mut taken = Vec::with_capacity(retried_steps.len());
+        let mut replayed = retried_steps.into_iter();
+        let Outcome {
+            result,
+            trace,
+            scopes,
+            idle_tasks,
+        } = executor
+            .execute(
+                &mut self.hooks,
+                &self.controller_config,
+                initial_tasks,
+                state,
+                f,
+                |tasks: &[(Task, TaskState)]| {
+                    let choice = replayed.next().or_else(|| pick(tasks));
+                    taken.extend(choice);
+                    choice
+                },
+            )
+            .await;
+
+        let class = match (&result, &mut self.classify) {
+            (Err(failure), Some(classify)) => Some(failure.classify(classify, &trace)),
+            _ => None,
+        };
 
-                controller.assert_finished();
-                drop(controller);
+        #[cfg(feature = "otel")]
+        if let Some(exporter) = &mut self.otel_exporter {
+            let failure = result.as_ref().err().map(|failure| match &class {
+                Some(class) => format!("[{class}] {}", failure.message()),
+                None => failure.message(),
+            });
+            exporter.export(report.iterations, &trace, failure.as_deref());
+        }
+
+        if result.is_ok() {
+            self.warn_idle_tasks(idle_tasks);
+        }
+
+        if let Some(events) = &self.hooks.events {
+            events.iteration_finished(result.is_err());
+        }
+
+        if report.structure.is_none() && result.is_ok() {
+            self.record_structure(&trace, report);
+        }
 
-                if let Some(after_iter) = &mut self.after_iter {
-                    after_iter().await;
+        if let Some(traces) = &mut self.traces {
+            traces.push(trace.clone());
+        }
+        report.iterations += 1;
+        report.record_scopes(&scopes);
+        report.record_durations(&trace);
+        if let Some(class) = &class {
+            report.record_failure(class, &trace);
+        }
+        let family = report.record(&trace, result.is_err());
+        if let (Err(Failure::Panic(payload)), Some(pattern)) = (&result, &self.expected_panic) {
+            if panic_message(&**payload).contains(pattern.as_str()) {
+                self.reporter
+                    .note(&format!("note: schedule panicked as expected: {trace}"));
+                report.expected_panic = Some(trace);
+                panic::resume_unwind(Box::new(ExpectedPanic(report.clone())));
+            }
+        }
+        if let Some(class) = &class {
+            if let Some((state, action)) = self.recover(class, &trace, retries, executor) {
+                if action == FailureAction::Continue {
+                    return state;
                 }
-            };
+                let retry = self.attempt(
+                    executor,
+                    initial_tasks,
+                    state,
+                    f,
+                    pick,
+                    report,
+                    (taken, retries + 1),
+                );
+                return Box::pin(retry).await;
+            }
+        }
+        if result.is_err() && !self.searching {
+            self.reporter
+                .note(&format!("note: failed schedule belongs to family {family}"));
+            if let Some(retry) = &self.retry {
+                retry.save(&trace);
+            }
+            if let Some(history) = &mut self.history {
+                history.record(&trace);
+            }
+            if nextest::detected() {
+                self.reporter.note(&nextest::failure_info(&trace));
+            }
+        }
+        unwrap_iteration(
+            result,
+            &trace,
+            self.case.as_deref(),
+            &mut self.on_panic,
+            &mut self.reporter,
+        )
+    }
+}
 
-            let result = AssertUnwindSafe(async {
-                (state, ()) = join!(f(state), control);
-                state
-            })
-            .catch_unwind()
+/// Runs the same scenario under several named configurations, one after another.
+///
+/// `PARCHECK_MATRIX` environment variable (comma separated names) selects which configurations
+/// to run, all of them are run if it's not set.
+#[must_use]
+pub struct RunnerMatrix {
+    runners: Vec<(String, Runner)>,
+}
+
+impl Runner {
+    pub fn matrix<N: Into<String>>(configs: impl IntoIterator<Item = (N, Runner)>) -> RunnerMatrix {
+        let mut runners: Vec<(String, Runner)> = configs
+            .into_iter()
+            .map(|(name, runner)| (name.into(), runner))
+            .collect();
+
+        if let Ok(selected) = env::var("PARCHECK_MATRIX") {
+            let selected: Vec<&str> = selected.split(',').map(str::trim).collect();
+            for name in &selected {
+                assert!(
+                    runners.iter().any(|(config, _)| config == name),
+                    "unknown configuration in PARCHECK_MATRIX: '{name}'"
+                );
+            }
+            runners.retain(|(name, _)| selected.contains(&name.as_str()));
+        }
+
+        RunnerMatrix { runners }
+    }
+}
+
+impl RunnerMatrix {
+    pub async fn run<I, F, Fut>(self, initial_tasks: I, mut f: F) -> Report
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let (_, report) = self
+            .explore(initial_tasks, (), |()| f(), Stateless(CurrentRuntime))
             .await;
+        report
+    }
 
-            state = match result {
-                Ok(v) => v,
-                Err(error) => {
-                    if let Some(on_panic) = self.on_panic {
-                        on_panic(&trace);
-                    } else {
-                        let env_value = trace.to_string();
-                        eprintln!(
-                            "note: use `PARCHECK_REPLAY={env_value:?}` to replay the same schedule"
-                        );
-                    }
-                    panic::resume_unwind(error);
+    pub async fn run_with_state<T, I, F, Fut>(self, initial_tasks: I, state: T, f: F) -> T
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        F: FnMut(T) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let (state, _) = self.explore(initial_tasks, state, f, CurrentRuntime).await;
+        state.expect(NO_STATE_AFTER_EXPECTED_PANIC)
+    }
+
+    async fn explore<T, I, F, E>(
+        self,
+        initial_tasks: I,
+        mut state: T,
+        mut f: F,
+        executor: E,
+    ) -> (Option<T>, Report)
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        E: for<'f> Executor<T, &'f mut F> + Copy,
+    {
+        let initial_tasks: Vec<Arc<str>> = initial_tasks
+            .into_iter()
+            .map(|name| name.as_ref().into())
+            .collect();
+
+        let mut report = Report::default();
+        for (_, mut runner) in self.runners {
+            let (config_state, config_report) = runner
+                .explore(&initial_tasks, state, &mut f, executor)
+                .await;
+            report.merge(&config_report);
+            let Some(config_state) = config_state else {
+                return (None, report);
+            };
+            state = config_state;
+        }
+        (Some(state), report)
+    }
+}
+
+/// Random schedules never run out, so an unlimited `max_iterations` falls back to
+/// [`DEFAULT_RANDOM_ITERATIONS`].
+fn random_iterations(max_iterations: u64) -> u64 {
+    if max_iterations == u64::MAX {
+        DEFAULT_RANDOM_ITERATIONS
+    } else {
+        max_iterations
+    }
+}
+
+const NO_STATE_AFTER_EXPECTED_PANIC: &str =
+    "a schedule panicked as expected, so there's no state of the scenario to return";
+
+/// Number of times a schedule is retried after failures classified with [`FailureAction::Retry`],
+/// see [`Runner::retry_on`].
+const DEFAULT_FAILURE_RETRIES: u32 = 3;
+
+/// Number of iterations of strategies that pick schedules randomly when `max_iterations` isn't
+/// set, see [`Strategy::Pct`] and [`Strategy::ShallowFirst`].
+const DEFAULT_RANDOM_ITERATIONS: u64 = 1000;
+
+/// Schedule space size above which a warning is printed, see [`Runner::schedule_space_warning`].
+const DEFAULT_SPACE_WARNING: u64 = 1_000_000;
+
+/// Number of iterations after which the schedule space is estimated.
+const ESTIMATE_ITERATIONS: u64 = 10;
+
+/// How long the controller waits for tasks to request an operation or finish.
+// TODO: add to config
+pub(crate) const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl Hooks {
+    /// Whether any hook runs between granting a step and executing its operation.
+    fn observe_granted_steps(&self) -> bool {
+        self.before_step.is_some()
+            || !self.on_operation.is_empty()
+            || !self.breakpoints.is_empty()
+            || self.step_latency.is_some()
+            || self.events.is_some()
+    }
+
+    async fn control(
+        &mut self,
+        controller: &mut Controller,
+        trace: &mut Trace,
+        mut pick: impl FnMut(&[(Task, TaskState)]) -> Option<Choice>,
+    ) -> Result<(), ControllerError> {
+        if let Some(before_iter) = &mut self.before_iter {
+            before_iter().await;
+        }
+        if let Some(events) = &self.events {
+            events.iteration_started();
+        }
+
+        let mut prev_step_span = StepSpan::default();
+        loop {
+            controller.ready(WAIT_TIMEOUT).await?;
+            for task_id in controller.take_cancelled() {
+                trace.cancellations.push(Cancellation {
+                    step: trace.steps.len(),
+                    task_id,
+                    task_name: controller.tasks()[task_id.0].0.name().clone(),
+                });
+            }
+            let tasks = controller.tasks();
+            let candidates = if self.record_candidates {
+                StepCandidate::collect(tasks)
+            } else {
+                Vec::new()
+            };
+            let Some(Choice {
+                task_id,
+                inject_fault,
+            }) = pick(tasks)
+            else {
+                break;
+            };
+
+            let (task, state) = controller
+                .tasks()
+                .iter()
+                .find(|(task, _)| task.id() == task_id)
+                .ok_or(ControllerError::NotExecutable { task_id })?;
+            let op_metadata = state
+                .executable_op()
+                .ok_or(ControllerError::NotExecutable { task_id })?;
+            let step = TraceStep {
+                task_id: Some(task_id),
+                task_name: task.name().clone(),
+                op_name: OperationName::Static(op_metadata.name()),
+                metadata: Some(op_metadata),
+                backtrace: state.backtrace().cloned(),
+                timing: None,
+                fault: if inject_fault {
+                    state.injectable_fault()
+                } else {
+                    None
+                },
+                candidates,
+            };
+
+            if let Some(events) = &self.events {
+                events
+                    .step(&step, controller.tasks()[task_id.0].1.locks())
+                    .await;
+            }
+
+            let index = trace.steps.len();
+            trace.steps.push(step);
+            let ctx = StepContext {
+                index,
+                step: &trace.steps[index],
+                controller: ControllerView::new(controller),
+            };
+            if let Some(before_step) = &mut self.before_step {
+                before_step(&ctx).await;
+            }
+            for (at, hook) in &mut self.breakpoints {
+                if at.matches(&ctx) {
+                    hook(&ctx).await;
                 }
+            }
+            self.on_operation(OperationEvent::Granted, &ctx).await;
+            let latency = self
+                .step_latency
+                .as_mut()
+                .map_or(Duration::ZERO, |step_latency| step_latency(&ctx));
+            let step_span = step_span(&ctx, &prev_step_span);
+            let timing = controller
+                .step_forward(task_id, latency, step_span.clone(), inject_fault)
+                .await?;
+            prev_step_span = step_span;
+            let ctx = StepContext {
+                index,
+                step: &trace.steps[index],
+                controller: ControllerView::new(controller),
             };
+            self.on_operation(OperationEvent::Finished, &ctx).await;
+            if let Some(after_step) = &mut self.after_step {
+                after_step(&ctx).await;
+            }
+            trace.steps[index].timing = timing;
+        }
 
-            iter += 1;
+        controller.check_finished()?;
+
+        if let Some(after_iter) = &mut self.after_iter {
+            after_iter().await;
         }
-        state
+        Ok(())
+    }
+
+    async fn on_operation(&mut self, event: OperationEvent, ctx: &StepContext<'_>) {
+        for (name, hook) in &mut self.on_operation {
+            if name == ctx.step.operation_name() {
+                hook(event, ctx).await;
+            }
+        }
+    }
+}
+
+/// Creates a span for the scheduling decision, linked to the span of previous decision.
+#[cfg(feature = "tracing")]
+fn step_span(ctx: &StepContext<'_>, prev: &StepSpan) -> StepSpan {
+    let span = tracing::info_span!(
+        "parcheck.step",
+        "parcheck.step.index" = ctx.index,
+        "parcheck.task.id" = ctx.step.task_id(),
+        "parcheck.task.name" = ctx.step.task_name(),
+        "parcheck.operation.name" = ctx.step.operation_name(),
+    );
+    if let Some(prev) = &prev.span {
+        span.follows_from(prev);
     }
+    tracing::info!(parent: &span, "parcheck: scheduled operation");
+    StepSpan { span: Some(span) }
 }
 
-pub struct Trace {
-    steps: Vec<(TaskId, TaskName, OperationName)>,
+#[cfg(not(feature = "tracing"))]
+fn step_span(_ctx: &StepContext<'_>, _prev: &StepSpan) -> StepSpan {
+    StepSpan::default()
 }
 
-struct OperationName(String);
+/// Result of a single iteration.
+struct Outcome<T> {
+    result: Result<T, Failure>,
+    trace: Trace,
+    scopes: HashMap<String, ScopeStats>,
+    idle_tasks: Vec<TaskName>,
+}
+
+/// Executes a single iteration.
+trait Executor<T, F> {
+    async fn execute(
+        &self,
+        hooks: &mut Hooks,
+        config: &ControllerConfig,
+        initial_tasks: &[TaskName],
+        state: T,
+        f: &mut F,
+        pick: impl FnMut(&[(Task, TaskState)]) -> Option<Choice>,
+    ) -> Outcome<T>;
 
-impl Trace {
-    fn new() -> Self {
-        Self { steps: Vec::new() }
+    /// State for the next iteration after one failed (and consumed the state), `None` if it
+    /// can't be recreated.
+    fn recover_state(&self) -> Option<T> {
+        None
     }
 }
 
-impl fmt::Display for Trace {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.steps.is_empty() {
-            return Ok(());
+/// Executes iterations on the runtime `Runner` is called from.
+#[derive(Clone, Copy)]
+struct CurrentRuntime;
+
+/// Executes iterations of a scenario without state with another executor, so that exploration
+/// can continue after a failed iteration.
+#[derive(Clone, Copy)]
+struct Stateless<E>(E);
+
+impl<T, F, Fut> Executor<T, F> for CurrentRuntime
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = T>,
+{
+    async fn execute(
+        &self,
+        hooks: &mut Hooks,
+        config: &ControllerConfig,
+        initial_tasks: &[TaskName],
+        state: T,
+        f: &mut F,
+        pick: impl FnMut(&[(Task, TaskState)]) -> Option<Choice>,
+    ) -> Outcome<T> {
+        run_iteration(hooks, config, initial_tasks, state, f, pick).await
+    }
+}
+
+impl<F, E: Executor<(), F>> Executor<(), F> for Stateless<E> {
+    async fn execute(
+        &self,
+        hooks: &mut Hooks,
+        config: &ControllerConfig,
+        initial_tasks: &[TaskName],
+        state: (),
+        f: &mut F,
+        pick: impl FnMut(&[(Task, TaskState)]) -> Option<Choice>,
+    ) -> Outcome<()> {
+        self.0
+            .execute(hooks, config, initial_tasks, state, f, pick)
+            .await
+    }
+
+    fn recover_state(&self) -> Option<()> {
+        Some(())
+    }
+}
+
+/// Runs an iteration of the scenario on a new current-thread runtime (on a separate thread) and
+/// waits for it without blocking the calling runtime. The runtime is dropped before the result is
+/// sent back, together with everything the iteration leaked. If the iteration is aborted (the
+/// returned future is dropped), the scenario is dropped as well.
+async fn run_on_new_runtime<F, Fut>(f: Arc<Mutex<F>>)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()>,
+{
+    let (mut result_tx, result_rx) = oneshot::channel();
+    thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to build iteration runtime");
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let scenario = f.lock().unwrap_or_else(PoisonError::into_inner)();
+            runtime.block_on(async {
+                future::select(pin!(scenario), pin!(result_tx.closed())).await;
+            });
+        }));
+        drop(runtime);
+        let _ = result_tx.send(result);
+    });
+
+    let result = result_rx
+        .await
+        .expect("iteration thread exited without sending a result");
+    if let Err(payload) = result {
+        panic::resume_unwind(payload);
+    }
+}
+
+async fn run_iteration<T, F, Fut>(
+    hooks: &mut Hooks,
+    config: &ControllerConfig,
+    initial_tasks: &[TaskName],
+    state: T,
+    f: &mut F,
+    pick: impl FnMut(&[(Task, TaskState)]) -> Option<Choice>,
+) -> Outcome<T>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = T>,
+{
+    let mut trace = Trace::new();
+    let mut controller = Controller::register(initial_tasks, config);
+    let result = AssertUnwindSafe(async {
+        let (state, ()) = future::try_join(
+            f(state).map(Ok),
+            hooks.control(&mut controller, &mut trace, pick),
+        )
+        .await?;
+        controller.check_no_leaks()?;
+        if config.strict {
+            controller.check_executed_operations()?;
         }
+        Ok(state)
+    })
+    .catch_unwind()
+    .await
+    .map_err(Failure::Panic)
+    .and_then(|result| result.map_err(Failure::Controller));
+
+    Outcome {
+        result,
+        trace,
+        scopes: controller.scope_stats(),
+        idle_tasks: controller.idle_tasks(),
+    }
+}
 
-        let (task_id, task_name, op_name) = &self.steps[0];
-        write!(f, "{}:{}.{}", task_id.0, task_name.0, op_name.0)?;
-        for (task_id, task_name, op_name) in &self.steps[1..] {
-            write!(f, " > {}:{}.{}", task_id.0, task_name.0, op_name.0)?;
+/// Why an iteration failed.
+enum Failure {
+    /// Scenario (or a hook) panicked.
+    Panic(Box<dyn Any + Send>),
+    /// Controller detected misuse of parcheck.
+    Controller(ControllerError),
+}
+
+impl Failure {
+    #[cfg(feature = "otel")]
+    fn message(&self) -> String {
+        match self {
+            Self::Panic(payload) => panic_message(&**payload),
+            Self::Controller(error) => error.to_string(),
+        }
+    }
+
+    fn classify(&self, classify: &mut ClassifyFailure, trace: &Trace) -> FailureClass {
+        match self {
+            Self::Panic(payload) => classify(&**payload, trace),
+            Self::Controller(error) => classify(&error.to_string(), trace),
         }
-        Ok(())
     }
 }
 
-impl FromStr for Trace {
-    type Err = ParseTraceError;
+fn unwrap_iteration<T>(
+    result: Result<T, Failure>,
+    trace: &Trace,
+    case: Option<&str>,
+    on_panic: &mut Option<PanicHandler>,
+    reporter: &mut Reporter,
+) -> T {
+    match result {
+        Ok(state) => state,
+        Err(error) => {
+            if let Some(on_panic) = on_panic.take() {
+                on_panic(trace);
+            } else {
+                let env_value = trace.to_string();
+                let case = case.map_or_else(String::new, |case| format!("PARCHECK_CASE={case:?} "));
+                reporter.note(&format!(
+                    "note: use `{case}PARCHECK_REPLAY={env_value:?}` to replay the same schedule"
+                ));
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let steps = s
-            .split(" > ")
-            .map(|step| {
-                let (task_id, names) = step.split_once(':').ok_or(ParseTraceError)?;
-                let (task_name, op_name) = names.split_once('.').ok_or(ParseTraceError)?;
+                #[cfg(feature = "backtrace")]
+                for step in trace.steps() {
+                    if let Some(backtrace) = step.backtrace() {
+                        reporter.note(&format!("note: step {step:?} requested at:\n{backtrace}"));
+                    }
+                }
+            }
+            match error {
+                Failure::Panic(payload) => panic::resume_unwind(payload),
+                Failure::Controller(error) => panic!("{error}"),
+            }
+        }
+    }
+}
 
-                let task_id = TaskId(task_id.parse().map_err(|_| ParseTraceError)?);
-                Ok((
+/// Follows steps of the trace, then picks random executable tasks once the trace runs out.
+fn replay_picker(
+    trace: Trace,
+    rng: &mut Rng,
+) -> impl FnMut(&[(Task, TaskState)]) -> Option<Choice> + '_ {
+    let mut steps_from_trace = trace.steps.into_iter();
+    move |tasks| {
+        steps_from_trace
+            .next()
+            .map(|step| {
+                // TODO: check task name, op name
+                let task_id = step.task_id.unwrap_or_else(|| resolve_step(&step, tasks));
+                Choice {
                     task_id,
-                    TaskName(task_name.into()),
-                    OperationName(op_name.into()),
-                ))
+                    inject_fault: step.fault.is_some(),
+                }
             })
-            .collect::<Result<Vec<_>, ParseTraceError>>()?;
+            .or_else(|| random_choice(tasks, rng))
+    }
+}
 
-        Ok(Self { steps })
+/// Follows steps of the trace as long as the same task can execute the same operation (and inject
+/// the same fault), then picks random executable tasks.
+fn follow_picker(
+    trace: Trace,
+    rng: &mut Rng,
+) -> impl FnMut(&[(Task, TaskState)]) -> Option<Choice> + '_ {
+    let mut steps_from_trace = Some(trace.steps.into_iter());
+    move |tasks| {
+        let step = steps_from_trace.as_mut().and_then(Iterator::next);
+        let followed = step.and_then(|step| {
+            tasks
+                .iter()
+                .find(|(task, state)| {
+                    step.task_id.is_none_or(|id| id == task.id())
+                        && task.name() == &step.task_name
+                        && state
+                            .executable_op()
+                            .is_some_and(|metadata| metadata.name() == step.operation_name())
+                        && (step.fault.is_none() || state.injectable_fault().is_some())
+                })
+                .map(|(task, _)| Choice {
+                    task_id: task.id(),
+                    inject_fault: step.fault.is_some(),
+                })
+        });
+        if followed.is_none() {
+            steps_from_trace = None;
+        }
+        followed.or_else(|| random_choice(tasks, rng))
     }
 }
 
-pub struct ParseTraceError;
+/// Report of a run that stopped at a schedule that panicked as expected, unwound from the
+/// iteration, see [`Runner::expect_panic_matching`].
+struct ExpectedPanic(Report);
+
+/// Error returned by a scenario of [`Runner::try_run`], the panic payload of its failed
+/// iteration (e.g. as seen by [`Runner::classify_failure`]).
+pub struct ScenarioError(Box<dyn Error + Send + Sync>);
 
-impl fmt::Debug for ParseTraceError {
+impl ScenarioError {
+    /// Error returned by the scenario, can be downcast to its type.
+    #[must_use]
+    pub fn error(&self) -> &(dyn Error + Send + Sync + 'static) {
+        &*self.0
+    }
+
+    #[must_use]
+    pub fn into_error(self) -> Box<dyn Error + Send + Sync> {
+        self.0
+    }
+}
+
+impl fmt::Debug for ScenarioError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("invalid trace")
+        fmt::Debug::fmt(&self.0, f)
     }
 }
 
-impl fmt::Display for ParseTraceError {
+impl fmt::Display for ScenarioError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("invalid trace")
+        fmt::Display::fmt(&self.0, f)
     }
 }
 
-impl Error for ParseTraceError {}
+/// Fails the iteration with the error of the scenario. Unwinds without calling the panic hook,
+/// so that no panic message is printed.
+fn fail_with<T>(error: impl Into<Box<dyn Error + Send + Sync>>) -> T {
+    panic::resume_unwind(Box::new(ScenarioError(error.into())))
+}
+
+/// Returns the error of the scenario that unwound, resumes other panics.
+fn unwind_error<T>(
+    result: Result<T, Box<dyn Any + Send>>,
+) -> Result<T, Box<dyn Error + Send + Sync>> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(payload) => match payload.downcast::<ScenarioError>() {
+            Ok(error) => Err(error.into_error()),
+            Err(payload) => panic::resume_unwind(payload),
+        },
+    }
+}
+
+/// Panic message of the scenario, `None` if it completed.
+async fn outcome(fut: impl Future<Output = ()>) -> Option<String> {
+    AssertUnwindSafe(fut)
+        .catch_unwind()
+        .await
+        .err()
+        .map(|payload| panic_message(&*payload))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else if let Some(error) = payload.downcast_ref::<ScenarioError>() {
+        error.to_string()
+    } else {
+        "panic".to_owned()
+    }
+}
+
+/// Random executable task (or injected fault), `None` if no task can execute.
+fn random_choice(tasks: &[(Task, TaskState)], rng: &mut Rng) -> Option<Choice> {
+    let candidates = schedule_tree::choices(tasks)
+        .filter_map(|(choice, state)| state.can_execute().then_some(choice))
+        .collect::<Vec<Choice>>();
+
+    if candidates.is_empty() {
+        return None;
+    }
+
+    Some(candidates[rng.usize(..candidates.len())])
+}
+
+/// Task of a replayed step that only names the task: the first one with that name waiting for
+/// the operation.
+fn resolve_step(step: &TraceStep, tasks: &[(Task, TaskState)]) -> TaskId {
+    tasks
+        .iter()
+        .find(|(task, state)| {
+            task.name() == &step.task_name
+                && state
+                    .executable_op()
+                    .is_some_and(|metadata| metadata.name() == step.operation_name())
+        })
+        .map_or_else(
+            || panic!("no task can execute replayed step '{step}'"),
+            |(task, _)| task.id(),
+        )
+}
+
+/// 64-bit FNV-1a hash, stable across platforms and Rust versions (unlike `DefaultHasher`).
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -1,25 +1,42 @@
-use fastrand::Rng;
-use std::ops::Range;
+use std::{collections::HashMap, fmt::Write as _, ops::Range};
 
 use crate::enabled::{
     controller::TaskState,
+    explored_tree::{ExploredNode, ExploredStep, ExploredTree, NodeStatus},
+    history,
+    operation::Cost,
+    random::Rng,
+    report::{Reduction, ScheduleSpace},
+    strategy::CostConfig,
     task::{Task, TaskId, TaskName},
+    trace::Trace,
 };
 
 pub(crate) struct ScheduleTree {
+    tasks: Vec<TaskName>,
     nodes: Vec<Node>,
-    roots: usize,
     unvisited_leafs: Vec<Path>,
+    discovered: u64,
+    costs: CostConfig,
+    /// Alternatives are only explored for steps before this depth.
+    branching_depth: Option<usize>,
+    /// Whether alternatives were skipped because of `branching_depth`.
+    truncated: bool,
+    /// Alternatives skipped by reductions.
+    space: ScheduleSpace,
+    /// Schedules that failed in previous runs, paths close to them are explored first.
+    failed: Vec<(Trace, u64)>,
 }
 
 #[derive(Debug, Copy, Clone)]
 pub(crate) struct NodeId(usize);
 
+const ROOT: NodeId = NodeId(0);
+
 struct Node {
-    // TODO: spawn tasks
-    #[allow(dead_code)]
-    task_name: TaskName,
     state: NodeState,
+    /// Step leading to the node, `None` for the root.
+    step: Option<ExploredStep>,
 }
 
 enum NodeState {
@@ -28,48 +45,446 @@ enum NodeState {
     Unreachable { reason: &'static str },
 }
 
-struct Path(Vec<TaskId>);
+/// Projected size of the schedule space, see [`ScheduleTree::estimate`].
+#[derive(Debug, Default)]
+pub(crate) struct SpaceEstimate {
+    pub(crate) schedules: f64,
+    /// Average number of choices at steps that have alternatives.
+    pub(crate) branching: f64,
+    /// Operations of tasks (task name, operation name) by the number of steps at which they are
+    /// an alternative, most frequent first.
+    pub(crate) contributors: Vec<(String, &'static str, u32)>,
+}
+
+/// Indexes of chosen children (see [`choices`]) starting from the root.
+struct Path {
+    steps: Vec<usize>,
+    /// [`ScheduleTree::fragility`] of the path, `None` until it's needed.
+    fragility: Option<u64>,
+}
+
+impl Path {
+    fn new(steps: Vec<usize>) -> Self {
+        Self {
+            steps,
+            fragility: None,
+        }
+    }
+
+    fn push(&mut self, idx: usize) {
+        self.steps.push(idx);
+        self.fragility = None;
+    }
+}
+
+/// Decision made at a single step: which task executes its operation and whether operation's
+/// fault is injected instead.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Choice {
+    pub(crate) task_id: TaskId,
+    pub(crate) inject_fault: bool,
+}
 
 impl ScheduleTree {
-    pub(crate) fn new(roots: &[TaskName]) -> Self {
-        let nodes = roots
-            .iter()
-            .map(|task_name| Node {
-                task_name: task_name.clone(),
-                state: NodeState::Unvisited,
-            })
-            .collect::<Vec<Node>>();
+    pub(crate) fn new(roots: &[TaskName], costs: CostConfig) -> Self {
+        let unvisited_leafs = if roots.is_empty() {
+            Vec::new()
+        } else {
+            vec![Path::new(Vec::new())]
+        };
 
         Self {
-            roots: nodes.len(),
-            unvisited_leafs: (0..nodes.len())
-                .map(|idx| Path(vec![TaskId(idx)]))
-                .collect(),
-            nodes,
+            tasks: roots.to_vec(),
+            nodes: vec![Node {
+                state: NodeState::Unvisited,
+                step: None,
+            }],
+            discovered: unvisited_leafs.len() as u64,
+            unvisited_leafs,
+            costs,
+            branching_depth: None,
+            truncated: false,
+            space: ScheduleSpace::default(),
+            failed: Vec::new(),
+        }
+    }
+
+    /// Explores paths that share the longest prefixes with (frequently) `failed` schedules first.
+    pub(crate) fn prefer_failed(&mut self, failed: Vec<(Trace, u64)>) {
+        self.failed = failed;
+        for leaf in &mut self.unvisited_leafs {
+            leaf.fragility = None;
         }
     }
 
+    /// Only explores alternatives of the first `depth` steps of schedules, later steps are picked
+    /// randomly.
+    pub(crate) fn limit_branching_depth(&mut self, depth: usize) {
+        self.branching_depth = Some(depth);
+    }
+
+    /// Whether some schedules were left unexplored because of [`limit_branching_depth`].
+    ///
+    /// [`limit_branching_depth`]: ScheduleTree::limit_branching_depth
+    pub(crate) fn truncated(&self) -> bool {
+        self.truncated
+    }
+
     pub(crate) fn has_unfinished_paths(&self) -> bool {
         !self.unvisited_leafs.is_empty()
     }
 
+    /// Number of distinct schedules discovered so far (explored or not).
+    pub(crate) fn discovered(&self) -> u64 {
+        self.discovered
+    }
+
+    /// Discovered schedules and alternatives skipped by reductions, compared to `raw` schedules
+    /// of the scenario.
+    pub(crate) fn into_schedule_space(self, raw: Option<u128>) -> ScheduleSpace {
+        ScheduleSpace {
+            raw,
+            reduced: self.discovered,
+            ..self.space
+        }
+    }
+
+    /// Read-only copy of the tree.
+    pub(crate) fn explored(&self) -> ExploredTree {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let (status, children) = match &node.state {
+                    NodeState::Unvisited => (NodeStatus::Unvisited, 0..0),
+                    NodeState::Visited { children } => (NodeStatus::Visited, children.clone()),
+                    NodeState::Unreachable { reason } => (NodeStatus::Unreachable { reason }, 0..0),
+                };
+                ExploredNode {
+                    step: node.step,
+                    status,
+                    children,
+                }
+            })
+            .collect();
+        ExploredTree {
+            tasks: self.tasks.clone(),
+            nodes,
+        }
+    }
+
     pub(crate) fn pick_unfinished_path(&mut self, rng: &mut Rng) -> Option<PathCursor<'_>> {
         if self.unvisited_leafs.is_empty() {
             return None;
         }
 
-        let path = rng.usize(..self.unvisited_leafs.len());
+        let path = if self.failed.is_empty() {
+            rng.usize(..self.unvisited_leafs.len())
+        } else {
+            let fragility = (0..self.unvisited_leafs.len())
+                .map(|leaf| self.leaf_fragility(leaf))
+                .collect::<Vec<_>>();
+            let max = fragility.iter().copied().max().unwrap_or_default();
+            let fragile = (0..fragility.len())
+                .filter(|idx| fragility[*idx] == max)
+                .collect::<Vec<_>>();
+            fragile[rng.usize(..fragile.len())]
+        };
 
         Some(PathCursor {
             tree: self,
             state: CursorState::Path {
-                at: None,
+                at: ROOT,
                 path,
                 depth: 0,
+                last: None,
+                expensive_preemptions: 0,
             },
         })
     }
 
+    /// Adds nodes for every choice at the current step, recording alternatives skipped by
+    /// reductions.
+    fn add_children(
+        &mut self,
+        tasks: &[(Task, TaskState)],
+        last: Option<TaskId>,
+        expensive_preemptions: usize,
+        path: usize,
+    ) -> Range<usize> {
+        let costs = self.costs;
+        let mut pruned = Vec::new();
+        let children = self.add_nodes(choices(tasks).map(|(choice, task_state)| {
+            let state = match task_state_to_node_state(task_state) {
+                NodeState::Unvisited
+                    if costs.max_expensive_preemptions.is_some_and(|max| {
+                        expensive_preemptions >= max
+                            && is_expensive_preemption(last, tasks, choice, task_state)
+                    }) =>
+                {
+                    pruned.push((Reduction::PreemptionBound, choice));
+                    NodeState::Unreachable {
+                        reason: PREEMPTION_BOUND,
+                    }
+                }
+                node_state @ NodeState::Unreachable {
+                    reason: BLOCKED_BY_LOCKS,
+                } => {
+                    pruned.push((Reduction::Locks, choice));
+                    node_state
+                }
+                node_state => node_state,
+            };
+            let step = ExploredStep {
+                task: choice.task_id.0,
+                operation: operation_name(task_state),
+                inject_fault: choice.inject_fault,
+            };
+            Node {
+                state,
+                step: Some(step),
+            }
+        }));
+        for (reduction, choice) in pruned {
+            self.record_pruned(reduction, tasks, choice);
+        }
+        let (nodes, roots) = (&self.nodes, &self.tasks);
+        let prefix = &self.unvisited_leafs[path].steps;
+        for (idx, (_, task_state)) in children.clone().zip(choices(tasks)) {
+            if let NodeState::Unreachable { reason } = nodes[idx].state {
+                self.space
+                    .record_unreachable(unreachable_reason(reason, task_state), || {
+                        describe_path(nodes, roots, prefix, NodeId(idx))
+                    });
+            }
+        }
+        children
+    }
+
+    fn record_pruned(&mut self, reduction: Reduction, tasks: &[(Task, TaskState)], choice: Choice) {
+        let (task, state) = &tasks[choice.task_id.0];
+        let operation = operation_name(state).unwrap_or_default();
+        self.space
+            .record_pruned(reduction, &task.name().0, operation);
+    }
+
+    /// Unvisited children (indexes of `unvisited`) of the node at `path` whose paths are closest
+    /// to failed schedules, all of them without failure history.
+    fn most_fragile(&self, path: &[usize], unvisited: &[usize]) -> Vec<usize> {
+        if self.failed.is_empty() {
+            return unvisited.to_vec();
+        }
+        let mut child_path = path.to_vec();
+        let fragility = unvisited
+            .iter()
+            .map(|idx| {
+                child_path.push(*idx);
+                let fragility = self.fragility(&child_path);
+                child_path.pop();
+                fragility
+            })
+            .collect::<Vec<_>>();
+        let max = fragility.iter().copied().max().unwrap_or_default();
+        unvisited
+            .iter()
+            .zip(fragility)
+            .filter(|(_, fragility)| *fragility == max)
+            .map(|(idx, _)| *idx)
+            .collect()
+    }
+
+    /// Fragility of the path of an unvisited leaf, computed once per path.
+    fn leaf_fragility(&mut self, leaf: usize) -> u64 {
+        if let Some(fragility) = self.unvisited_leafs[leaf].fragility {
+            return fragility;
+        }
+        let fragility = self.fragility(&self.unvisited_leafs[leaf].steps);
+        self.unvisited_leafs[leaf].fragility = Some(fragility);
+        fragility
+    }
+
+    /// See [`history::fragility`].
+    fn fragility(&self, path: &[usize]) -> u64 {
+        let mut at = ROOT;
+        let mut steps = Vec::with_capacity(path.len());
+        for idx in path {
+            let NodeState::Visited { children } = &self.nodes[at.0].state else {
+                break;
+            };
+            at = NodeId(children.start + idx);
+            let step = self.nodes[at.0].step.expect("child without a step");
+            steps.push((&*self.tasks[step.task].0, step.operation, step.inject_fault));
+        }
+        history::fragility(&self.failed, &steps)
+    }
+
+    /// Estimates the size of the schedule space from the explored schedules: every schedule
+    /// estimates it as the product of the number of choices at each of its steps (Knuth's
+    /// estimator), averaged over explored schedules. `None` if no schedule was explored yet.
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn estimate(&self) -> Option<SpaceEstimate> {
+        let mut estimate = SpaceEstimate::default();
+        let mut schedules = 0;
+        let mut branching_nodes = 0;
+        let mut stack = vec![(ROOT, 1.0)];
+        while let Some((at, product)) = stack.pop() {
+            let NodeState::Visited { children } = &self.nodes[at.0].state else {
+                continue;
+            };
+            let reachable = children
+                .clone()
+                .filter(|idx| !matches!(self.nodes[*idx].state, NodeState::Unreachable { .. }))
+                .collect::<Vec<_>>();
+            if reachable.is_empty() {
+                estimate.schedules += product;
+                schedules += 1;
+                continue;
+            }
+            if reachable.len() > 1 {
+                estimate.branching += reachable.len() as f64;
+                branching_nodes += 1;
+                for idx in &reachable {
+                    let step = self.nodes[*idx].step.expect("child without a step");
+                    let name = self.tasks[step.task].0.to_string();
+                    let operation = step.operation.unwrap_or_default();
+                    match estimate
+                        .contributors
+                        .iter_mut()
+                        .find(|(task, op, _)| *task == name && *op == operation)
+                    {
+                        Some((_, _, count)) => *count += 1,
+                        None => estimate.contributors.push((name, operation, 1)),
+                    }
+                }
+            }
+            let product = product * reachable.len() as f64;
+            stack.extend(reachable.into_iter().map(|idx| (NodeId(idx), product)));
+        }
+        if schedules == 0 {
+            return None;
+        }
+        estimate.schedules /= f64::from(schedules);
+        if branching_nodes > 0 {
+            estimate.branching /= f64::from(branching_nodes);
+        }
+        estimate
+            .contributors
+            .sort_by_key(|(_, _, count)| std::cmp::Reverse(*count));
+        Some(estimate)
+    }
+
+    /// Writes the tree as lines of a checkpoint (see [`Checkpoint`](super::checkpoint::Checkpoint)):
+    /// tasks, every node (state, children, step) and every unvisited leaf. Alternatives pruned so
+    /// far and failed schedules aren't included.
+    pub(crate) fn write_checkpoint(&self, out: &mut String) {
+        writeln!(out, "discovered {}", self.discovered).unwrap();
+        writeln!(out, "truncated {}", u8::from(self.truncated)).unwrap();
+        for task in &self.tasks {
+            writeln!(out, "task {}", task.0).unwrap();
+        }
+        for node in &self.nodes {
+            let (state, children) = match &node.state {
+                NodeState::Unvisited => ("u".to_owned(), 0..0),
+                NodeState::Visited { children } => ("v".to_owned(), children.clone()),
+                NodeState::Unreachable { reason } => {
+                    let idx = UNREACHABLE_REASONS.iter().position(|known| known == reason);
+                    (format!("x{}", idx.unwrap()), 0..0)
+                }
+            };
+            let step = node.step.map_or_else(String::new, |step| {
+                format!(
+                    " {} {} {}",
+                    step.task,
+                    u8::from(step.inject_fault),
+                    step.operation.unwrap_or_default()
+                )
+            });
+            writeln!(
+                out,
+                "node {state} {} {}{step}",
+                children.start, children.end
+            )
+            .unwrap();
+        }
+        for leaf in &self.unvisited_leafs {
+            let path = leaf
+                .steps
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            writeln!(out, "leaf {}", path.join(" ")).unwrap();
+        }
+    }
+
+    /// Tree written by [`ScheduleTree::write_checkpoint`], `None` if the lines can't be parsed or
+    /// the tree has different tasks.
+    pub(crate) fn read_checkpoint<'a>(
+        lines: impl Iterator<Item = &'a str>,
+        roots: &[TaskName],
+        costs: CostConfig,
+    ) -> Option<Self> {
+        let mut tree = Self::new(roots, costs);
+        tree.nodes.clear();
+        tree.unvisited_leafs.clear();
+        let mut tasks = Vec::new();
+        // operation names of nodes are `&'static str` (names of operation metadata), names read
+        // from the checkpoint are leaked once per name
+        let mut operations = HashMap::<&str, &'static str>::new();
+        for line in lines {
+            let (kind, value) = line.split_once(' ').unwrap_or((line, ""));
+            match kind {
+                "discovered" => tree.discovered = value.parse().ok()?,
+                "truncated" => tree.truncated = value == "1",
+                "task" => tasks.push(value),
+                "node" => {
+                    let mut fields = value.splitn(6, ' ');
+                    let state = fields.next()?;
+                    let children = fields.next()?.parse().ok()?..fields.next()?.parse().ok()?;
+                    let state = match state {
+                        "u" => NodeState::Unvisited,
+                        "v" => NodeState::Visited { children },
+                        _ => NodeState::Unreachable {
+                            reason: UNREACHABLE_REASONS
+                                .get(state.strip_prefix('x')?.parse::<usize>().ok()?)?,
+                        },
+                    };
+                    let step = match fields.next() {
+                        Some(task) => {
+                            let inject_fault = fields.next()? == "1";
+                            let operation = fields.next()?;
+                            let operation = (!operation.is_empty()).then(|| {
+                                *operations
+                                    .entry(operation)
+                                    .or_insert_with(|| Box::leak(operation.into()))
+                            });
+                            Some(ExploredStep {
+                                task: task.parse().ok()?,
+                                operation,
+                                inject_fault,
+                            })
+                        }
+                        None => None,
+                    };
+                    tree.nodes.push(Node { state, step });
+                }
+                "leaf" => tree.unvisited_leafs.push(Path::new(
+                    value
+                        .split(' ')
+                        .filter(|idx| !idx.is_empty())
+                        .map(|idx| idx.parse().ok())
+                        .collect::<Option<_>>()?,
+                )),
+                _ => return None,
+            }
+        }
+        let same_tasks = tasks.len() == roots.len()
+            && tasks
+                .iter()
+                .zip(roots)
+                .all(|(task, root)| **task == *root.0);
+        (same_tasks && !tree.nodes.is_empty()).then_some(tree)
+    }
+
     fn add_nodes(&mut self, nodes: impl IntoIterator<Item = Node>) -> Range<usize> {
         let start = self.nodes.len();
         self.nodes.extend(nodes);
@@ -84,107 +499,265 @@ pub(crate) struct PathCursor<'a> {
 
 enum CursorState {
     Path {
-        at: Option<NodeId>,
+        at: NodeId,
         path: usize,
         depth: usize,
+        /// Task that executed the previous step.
+        last: Option<TaskId>,
+        expensive_preemptions: usize,
     },
     Finished,
 }
 
-impl<'a> PathCursor<'a> {
+impl PathCursor<'_> {
+    /// Gives up the rest of the path after its iteration failed and exploration continued (see
+    /// [`FailureAction::Continue`](crate::FailureAction::Continue)), so that the failing schedule
+    /// isn't picked again. The end of the path is marked as unreachable.
+    pub(crate) fn abandon(self) {
+        let CursorState::Path { path, .. } = self.state else {
+            return;
+        };
+        let path = self.tree.unvisited_leafs.swap_remove(path);
+        let mut at = ROOT;
+        for idx in path.steps {
+            let NodeState::Visited { children } = &self.tree.nodes[at.0].state else {
+                break;
+            };
+            at = NodeId(children.start + idx);
+        }
+        let node = &mut self.tree.nodes[at.0];
+        if let NodeState::Unvisited = node.state {
+            node.state = NodeState::Unreachable {
+                reason: ITERATION_FAILED,
+            };
+        }
+    }
+
     pub(crate) fn visit_and_pick(
         &mut self,
         tasks: &[(Task, TaskState)],
         rng: &mut Rng,
-    ) -> Option<TaskId> {
-        let CursorState::Path { at, path, depth } = &mut self.state else {
+    ) -> Option<Choice> {
+        let CursorState::Path {
+            at,
+            path,
+            depth,
+            last,
+            expensive_preemptions,
+        } = &mut self.state
+        else {
             panic!("visit() called in wrong state");
         };
 
-        if let Some(node_id) = at {
-            match &self.tree.nodes[node_id.0].state {
-                NodeState::Visited { .. } => {
-                    // TODO: compare `tasks` and children
-                }
-                NodeState::Unreachable { reason } => {
-                    panic!("visited node marked as unreachable ({reason})");
-                }
-                NodeState::Unvisited => {
-                    let children = self.tree.add_nodes(tasks_to_nodes(tasks));
-                    self.tree.nodes[node_id.0].state = NodeState::Visited {
-                        children: children.clone(),
-                    };
-
-                    assert_eq!(*depth, self.tree.unvisited_leafs[*path].0.len());
-
-                    let unvisited = tasks
-                        .iter()
-                        .filter_map(|(task, state)| state.can_execute().then_some(task.id()));
+        match &self.tree.nodes[at.0].state {
+            NodeState::Visited { .. } => {
+                // TODO: compare `tasks` and children
+            }
+            NodeState::Unreachable { reason } => {
+                panic!("visited node marked as unreachable ({reason})");
+            }
+            NodeState::Unvisited => {
+                let costs = self.tree.costs;
+                let children = self
+                    .tree
+                    .add_children(tasks, *last, *expensive_preemptions, *path);
+                let unvisited = children
+                    .clone()
+                    .filter(|idx| matches!(self.tree.nodes[*idx].state, NodeState::Unvisited))
+                    .map(|idx| idx - children.start)
+                    .collect::<Vec<usize>>();
+                self.tree.nodes[at.0].state = NodeState::Visited { children };
 
-                    let num_unvisited = unvisited.clone().count();
-                    if num_unvisited == 0 {
-                        self.tree.unvisited_leafs.swap_remove(*path);
-                        self.state = CursorState::Finished;
-                        return None;
-                    }
+                assert_eq!(*depth, self.tree.unvisited_leafs[*path].steps.len());
 
-                    let next_task_id = unvisited.clone().nth(rng.usize(..num_unvisited)).unwrap();
-                    for child_task_id in unvisited.filter(|id| *id != next_task_id) {
-                        let mut path = Path(self.tree.unvisited_leafs[*path].0.clone());
-                        path.0.push(child_task_id);
+                if unvisited.is_empty() {
+                    self.tree.unvisited_leafs.swap_remove(*path);
+                    self.state = CursorState::Finished;
+                    return None;
+                }
 
-                        self.tree.unvisited_leafs.push(path);
+                let fragile = self
+                    .tree
+                    .most_fragile(&self.tree.unvisited_leafs[*path].steps, &unvisited);
+                let next = if costs.prefer_cheap {
+                    let cost = |idx: &usize| {
+                        choices(tasks)
+                            .nth(*idx)
+                            .and_then(|(_, state)| state.executable_op())
+                            .map_or(Cost::Normal, |op| op.cost())
+                    };
+                    let cheapest = fragile.iter().map(cost).min().unwrap();
+                    let cheap = fragile
+                        .iter()
+                        .filter(|idx| cost(idx) == cheapest)
+                        .copied()
+                        .collect::<Vec<_>>();
+                    cheap[rng.usize(..cheap.len())]
+                } else {
+                    fragile[rng.usize(..fragile.len())]
+                };
+                let branching = self.tree.branching_depth.is_none_or(|max| *depth < max);
+                self.tree.truncated |= !branching && unvisited.len() > 1;
+                if !branching {
+                    for idx in unvisited.iter().filter(|idx| **idx != next) {
+                        let (choice, _) = choices(tasks).nth(*idx).unwrap();
+                        self.tree
+                            .record_pruned(Reduction::BranchingDepth, tasks, choice);
                     }
-
-                    self.tree.unvisited_leafs[*path].0.push(next_task_id);
                 }
-            }
-        } else {
-            assert_eq!(tasks.len(), self.tree.roots);
+                for child in unvisited
+                    .into_iter()
+                    .filter(|idx| branching && *idx != next)
+                {
+                    let mut path = Path::new(self.tree.unvisited_leafs[*path].steps.clone());
+                    path.push(child);
 
-            for (i, (_, task_state)) in tasks.iter().enumerate() {
-                let state = &mut self.tree.nodes[i].state;
-                if matches!(state, NodeState::Unvisited) {
-                    *state = task_state_to_node_state(task_state);
+                    self.tree.unvisited_leafs.push(path);
+                    self.tree.discovered += 1;
                 }
-            }
 
-            let root_idx = self.tree.unvisited_leafs[*path].0[0];
-            if matches!(
-                self.tree.nodes[root_idx.0].state,
-                NodeState::Unreachable { .. }
-            ) {
-                self.tree.unvisited_leafs.swap_remove(*path);
-                self.state = CursorState::Finished;
-                return None;
+                self.tree.unvisited_leafs[*path].push(next);
             }
-        };
+        }
 
         let path = &self.tree.unvisited_leafs[*path];
-        if *depth < path.0.len() {
-            let task_id = path.0[*depth];
+        if *depth < path.steps.len() {
+            let idx = path.steps[*depth];
             *depth += 1;
-            match at {
-                Some(node_id) => {
-                    let NodeState::Visited { children } = &self.tree.nodes[node_id.0].state else {
-                        panic!("created path through unvisited nodes");
-                    };
-                    *node_id = NodeId(children.start + task_id.0);
-                }
-                None => *at = Some(NodeId(task_id.0)),
+            let NodeState::Visited { children } = &self.tree.nodes[at.0].state else {
+                panic!("created path through unvisited nodes");
+            };
+            *at = NodeId(children.start + idx);
+            let (choice, state) = choices(tasks)
+                .nth(idx)
+                .expect("tasks don't match visited node");
+            if is_expensive_preemption(*last, tasks, choice, state) {
+                *expensive_preemptions += 1;
             }
-            Some(task_id)
+            *last = Some(choice.task_id);
+            Some(choice)
         } else {
             None
         }
     }
 }
 
-fn tasks_to_nodes(tasks: &[(Task, TaskState)]) -> impl Iterator<Item = Node> + '_ {
-    tasks.iter().map(|(task, state)| Node {
-        task_name: task.name().clone(),
-        state: task_state_to_node_state(state),
-    })
+/// Possible decisions at a step: every task in order of their ids, followed by fault injection
+/// for tasks waiting to start a fallible operation.
+pub(crate) fn choices(
+    tasks: &[(Task, TaskState)],
+) -> impl Iterator<Item = (Choice, &TaskState)> + '_ {
+    let executions = tasks.iter().map(|(task, state)| {
+        (
+            Choice {
+                task_id: task.id(),
+                inject_fault: false,
+            },
+            state,
+        )
+    });
+    let faults = tasks
+        .iter()
+        .filter(|(_, state)| state.injectable_fault().is_some())
+        .map(|(task, state)| {
+            (
+                Choice {
+                    task_id: task.id(),
+                    inject_fault: true,
+                },
+                state,
+            )
+        });
+    executions.chain(faults)
+}
+
+/// Whether `choice` switches away from the task that executed the previous step (and can
+/// continue) to an expensive operation.
+fn is_expensive_preemption(
+    last: Option<TaskId>,
+    tasks: &[(Task, TaskState)],
+    choice: Choice,
+    state: &TaskState,
+) -> bool {
+    let Some(last) = last else {
+        return false;
+    };
+    choice.task_id != last
+        && state
+            .executable_op()
+            .is_some_and(|op| op.cost() == Cost::High)
+        && tasks
+            .iter()
+            .any(|(task, state)| task.id() == last && state.can_execute())
+}
+
+fn operation_name(task_state: &TaskState) -> Option<&'static str> {
+    match task_state {
+        TaskState::WaitingToStartOperation { metadata, .. } => Some(metadata.name()),
+        _ => None,
+    }
+}
+
+const BLOCKED_BY_LOCKS: &str = "blocked by locks";
+const PREEMPTION_BOUND: &str = "expensive preemption bound";
+const TASK_FINISHED: &str = "task finished";
+const ITERATION_FAILED: &str = "iteration failed";
+
+/// Reasons of unreachable nodes, by their index in checkpoints.
+const UNREACHABLE_REASONS: [&str; 4] = [
+    BLOCKED_BY_LOCKS,
+    PREEMPTION_BOUND,
+    TASK_FINISHED,
+    ITERATION_FAILED,
+];
+
+/// Steps leading to the node `last` (a child of the node at `prefix`, given as indexes of
+/// children from the root), formatted as in [`Trace`].
+fn describe_path(nodes: &[Node], tasks: &[TaskName], prefix: &[usize], last: NodeId) -> String {
+    let mut path = Vec::with_capacity(prefix.len() + 1);
+    let mut at = ROOT;
+    for idx in prefix {
+        let NodeState::Visited { children } = &nodes[at.0].state else {
+            break;
+        };
+        at = NodeId(children.start + idx);
+        path.push(at);
+    }
+    path.push(last);
+
+    let mut steps = Vec::with_capacity(path.len());
+    for at in path {
+        let step = nodes[at.0].step.expect("child without a step");
+        let task = tasks.get(step.task).map_or("?", |task| &task.0);
+        let mut desc = format!("{}:{task}", step.task);
+        if let Some(operation) = step.operation {
+            write!(desc, ".{operation}").unwrap();
+        }
+        if step.inject_fault {
+            desc.push_str("!fault");
+        }
+        steps.push(desc);
+    }
+    steps.join(" > ")
+}
+
+/// Reason of an unreachable node, with the contended scopes of a blocked task.
+fn unreachable_reason(reason: &'static str, task_state: &TaskState) -> String {
+    let TaskState::WaitingToStartOperation { blocked_locks, .. } = task_state else {
+        return reason.to_owned();
+    };
+    if reason != BLOCKED_BY_LOCKS || blocked_locks.is_empty() {
+        return reason.to_owned();
+    }
+    let mut scopes = Vec::<String>::new();
+    for lock in blocked_locks {
+        let scope = format!("'{}'", lock.scope());
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    let noun = if scopes.len() == 1 { "scope" } else { "scopes" };
+    format!("{reason} on {noun} {}", scopes.join(", "))
 }
 
 fn task_state_to_node_state(task_state: &TaskState) -> NodeState {
@@ -197,10 +770,10 @@ fn task_state_to_node_state(task_state: &TaskState) -> NodeState {
             NodeState::Unvisited
         }
         TaskState::WaitingToStartOperation { .. } => NodeState::Unreachable {
-            reason: "blocked by locks",
+            reason: BLOCKED_BY_LOCKS,
         },
         TaskState::Finished => NodeState::Unreachable {
-            reason: "task finished",
+            reason: TASK_FINISHED,
         },
     }
 }
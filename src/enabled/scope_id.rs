@@ -0,0 +1,116 @@
+use std::{
+    any::{Any, TypeId},
+    fmt,
+    hash::{Hash, Hasher},
+    sync::{Arc, OnceLock},
+};
+
+use crate::{
+    scope::{OperationLock, ScopeKey, TypedLock},
+    ParcheckLock,
+};
+
+/// Lock with a type-erased scope, as tracked by the controller.
+pub(crate) type Lock = TypedLock<ScopeId>;
+
+/// Scope key of any [`ScopeKey`] type. Scopes are equal if their keys have the same type and are
+/// equal (`&str` keys are stored as `String` keys), and are displayed with their name (see
+/// [`ScopeKey`]).
+#[derive(Clone)]
+pub(crate) struct ScopeId(Arc<dyn Key>);
+
+trait Key: Send + Sync {
+    fn key(&self) -> &dyn Any;
+    fn eq_key(&self, other: &dyn Any) -> bool;
+    fn hash_key(&self, state: &mut dyn Hasher);
+    fn name(&self) -> &str;
+}
+
+struct Named<K> {
+    key: K,
+    name: OnceLock<String>,
+}
+
+impl<K: ScopeKey> Key for Named<K> {
+    fn key(&self) -> &dyn Any {
+        &self.key
+    }
+
+    fn eq_key(&self, other: &dyn Any) -> bool {
+        other.downcast_ref::<K>() == Some(&self.key)
+    }
+
+    fn hash_key(&self, mut state: &mut dyn Hasher) {
+        TypeId::of::<K>().hash(&mut state);
+        self.key.hash(&mut state);
+    }
+
+    fn name(&self) -> &str {
+        let key: &dyn Any = &self.key;
+        if let Some(name) = key.downcast_ref::<String>() {
+            return name;
+        }
+        self.name.get_or_init(|| format!("{:?}", self.key))
+    }
+}
+
+impl ScopeId {
+    pub(crate) fn new<K: ScopeKey>(key: K) -> Self {
+        // same scope as the `String` key (or the `ParcheckLock` scope) with the same text
+        if let Some(key) = (&key as &dyn Any).downcast_ref::<&'static str>() {
+            return Self::new((*key).to_owned());
+        }
+        Self(Arc::new(Named {
+            key,
+            name: OnceLock::new(),
+        }))
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        self.0.name()
+    }
+}
+
+impl PartialEq for ScopeId {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.eq_key(other.0.key())
+    }
+}
+
+impl Eq for ScopeId {}
+
+impl Hash for ScopeId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash_key(state);
+    }
+}
+
+impl fmt::Debug for ScopeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.name(), f)
+    }
+}
+
+impl fmt::Display for ScopeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+pub(crate) fn erase<L: OperationLock>(locks: Vec<L>) -> Vec<Lock> {
+    locks
+        .into_iter()
+        .map(|lock| lock.into_typed().map_scope(ScopeId::new))
+        .collect()
+}
+
+/// Lock with the scope replaced by its name.
+pub(crate) fn named(lock: &Lock) -> ParcheckLock {
+    let scope = lock.scope().name().to_owned();
+    match lock {
+        TypedLock::AcquireShared { .. } => ParcheckLock::AcquireShared { scope },
+        TypedLock::AcquireExclusive { .. } => ParcheckLock::AcquireExclusive { scope },
+        TypedLock::Acquire { mode, .. } => ParcheckLock::Acquire { scope, mode },
+        TypedLock::Release { .. } => ParcheckLock::Release { scope },
+    }
+}
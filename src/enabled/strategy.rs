@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+
+use crate::enabled::{
+    controller::TaskState,
+    random::Rng,
+    report::DetectionEstimate,
+    schedule_tree::Choice,
+    task::{Task, TaskId},
+};
+
+/// How schedules are picked and when exploration stops. `max_iterations` is a hard cap
+/// regardless of the strategy.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Strategy {
+    /// Explores schedules until all of them are executed.
+    #[default]
+    Exhaustive,
+    /// Explores schedules until the schedule space appears saturated: stops when fewer than
+    /// `min_discovery_rate` new schedules per iteration were discovered during the last `window`
+    /// iterations.
+    Adaptive {
+        window: u64,
+        min_discovery_rate: f64,
+    },
+    /// Probabilistic concurrency testing: every iteration gives tasks random priorities and
+    /// lowers priority of the running task at `depth - 1` random steps. Runs `max_iterations`
+    /// schedules (1000 if it isn't set, repetitions are possible) and reports probability of finding bugs that require
+    /// at most `depth` ordering constraints, see [`Report::detection`](crate::Report::detection).
+    /// Faults of fallible operations are injected with probability of 1/2.
+    Pct { depth: usize },
+    /// Explores every schedule that differs in the first `depth` steps (later steps are picked
+    /// randomly), then spends the rest of `max_iterations` (1000 if it isn't set) on random
+    /// schedules. Guarantees coverage of shallow interleavings within a fixed budget, while still
+    /// sampling deeper ones. Iterations of the first phase also count towards `max_iterations`.
+    ShallowFirst { depth: usize },
+}
+
+/// Preferences of [`Strategy::Exhaustive`] and [`Strategy::Adaptive`] based on declared
+/// [`Cost`](crate::Cost) of operations.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct CostConfig {
+    /// New schedules continue with the cheapest operation available.
+    pub(crate) prefer_cheap: bool,
+    /// Schedules that switch away from an executable task to an expensive operation more often
+    /// are not explored.
+    pub(crate) max_expensive_preemptions: Option<usize>,
+}
+
+/// Tracks the rate of schedule discovery for [`Strategy::Adaptive`].
+pub(crate) struct Saturation {
+    strategy: Strategy,
+    discoveries: VecDeque<u64>,
+}
+
+impl Saturation {
+    pub(crate) fn new(strategy: Strategy) -> Self {
+        Self {
+            strategy,
+            discoveries: VecDeque::new(),
+        }
+    }
+
+    /// Records number of schedules discovered by the last iteration and returns whether
+    /// exploration should stop.
+    pub(crate) fn record(&mut self, discovered: u64) -> bool {
+        let Strategy::Adaptive {
+            window,
+            min_discovery_rate,
+        } = self.strategy
+        else {
+            return false;
+        };
+
+        self.discoveries.push_back(discovered);
+        if (self.discoveries.len() as u64) < window {
+            return false;
+        }
+        if self.discoveries.len() as u64 > window {
+            self.discoveries.pop_front();
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let rate = self.discoveries.iter().sum::<u64>() as f64 / window as f64;
+        rate < min_discovery_rate
+    }
+}
+
+/// Tracks task & step counts observed by [`Strategy::Pct`] iterations.
+pub(crate) struct Pct {
+    depth: usize,
+    tasks: usize,
+    steps: usize,
+}
+
+impl Pct {
+    pub(crate) fn new(depth: usize) -> Self {
+        assert!(depth > 0, "PCT depth must be at least 1");
+        Self {
+            depth,
+            tasks: 0,
+            steps: 0,
+        }
+    }
+
+    /// Starts a new iteration. Priority change points are picked based on the longest iteration
+    /// observed so far.
+    pub(crate) fn schedule(&self, mut rng: Rng) -> PctSchedule {
+        let change_points = (1..self.depth).map(|_| rng.usize(..=self.steps)).collect();
+        PctSchedule {
+            rng,
+            depth: self.depth,
+            priorities: Vec::new(),
+            change_points,
+            step: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, schedule: &PctSchedule) {
+        self.tasks = self.tasks.max(schedule.priorities.len());
+        self.steps = self.steps.max(schedule.step);
+    }
+
+    pub(crate) fn estimate(&self, iterations: u64) -> DetectionEstimate {
+        DetectionEstimate {
+            depth: self.depth,
+            tasks: self.tasks,
+            steps: self.steps,
+            iterations,
+        }
+    }
+}
+
+/// Picks tasks for a single [`Strategy::Pct`] iteration.
+pub(crate) struct PctSchedule {
+    rng: Rng,
+    depth: usize,
+    priorities: Vec<(TaskId, u64)>,
+    change_points: Vec<usize>,
+    step: usize,
+}
+
+impl PctSchedule {
+    pub(crate) fn pick(&mut self, tasks: &[(Task, TaskState)]) -> Option<Choice> {
+        for (task, _) in tasks {
+            if !self.priorities.iter().any(|(id, _)| *id == task.id()) {
+                // Initial priorities are above any priority assigned at a change point.
+                let priority = self.depth as u64 + self.rng.u64(..u64::MAX / 2);
+                self.priorities.push((task.id(), priority));
+            }
+        }
+
+        let mut task_id = highest_priority(&self.priorities, tasks)?;
+        for (i, change_point) in self.change_points.iter().enumerate() {
+            if *change_point == self.step {
+                if let Some((_, priority)) =
+                    self.priorities.iter_mut().find(|(id, _)| *id == task_id)
+                {
+                    *priority = i as u64;
+                }
+                task_id = highest_priority(&self.priorities, tasks)?;
+            }
+        }
+
+        self.step += 1;
+        let inject_fault = tasks
+            .iter()
+            .any(|(task, state)| task.id() == task_id && state.injectable_fault().is_some())
+            && self.rng.bool();
+        Some(Choice {
+            task_id,
+            inject_fault,
+        })
+    }
+}
+
+fn highest_priority(priorities: &[(TaskId, u64)], tasks: &[(Task, TaskState)]) -> Option<TaskId> {
+    tasks
+        .iter()
+        .filter(|(_, state)| state.can_execute())
+        .filter_map(|(task, _)| priorities.iter().find(|(id, _)| *id == task.id()).copied())
+        .max_by_key(|(_, priority)| *priority)
+        .map(|(id, _)| id)
+}
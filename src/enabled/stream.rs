@@ -0,0 +1,135 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures_util::Stream;
+
+use crate::{
+    enabled::{
+        report::Report,
+        scope_id::{self, Lock},
+        trace::TraceStep,
+    },
+    ParcheckLock,
+};
+
+/// Event of a run explored with [`Runner::stream`](crate::Runner::stream).
+#[derive(Debug, Clone)]
+pub enum StepEvent {
+    /// Iteration (numbered from 0) has started.
+    IterationStarted { iteration: u64 },
+    /// Scheduler chose the next step, operation is about to execute with given locks.
+    Step {
+        iteration: u64,
+        step: TraceStep,
+        locks: Vec<ParcheckLock>,
+    },
+    /// Iteration has finished, stream panics right after a failed one.
+    IterationFinished { iteration: u64, failed: bool },
+}
+
+/// Stream of [`StepEvent`]s returned by [`Runner::stream`](crate::Runner::stream). Iterations
+/// are executed only while the stream is polled, and execution pauses after every step until the
+/// event is consumed.
+///
+/// # Panics
+///
+/// Polling panics if an iteration fails (same as [`Runner::run`](crate::Runner::run)).
+#[must_use = "streams do nothing unless polled"]
+pub struct StepStream<'a> {
+    run: Pin<Box<dyn Future<Output = Report> + 'a>>,
+    events: EventSink,
+    report: Option<Report>,
+}
+
+/// Collects events of a run for [`StepStream`].
+#[derive(Clone, Default)]
+pub(crate) struct EventSink {
+    inner: Arc<Mutex<Events>>,
+}
+
+#[derive(Default)]
+struct Events {
+    queue: VecDeque<StepEvent>,
+    iteration: Option<u64>,
+}
+
+impl<'a> StepStream<'a> {
+    pub(crate) fn new(run: impl Future<Output = Report> + 'a, events: EventSink) -> Self {
+        Self {
+            run: Box::pin(run),
+            events,
+            report: None,
+        }
+    }
+
+    /// Report of the run, available once the stream is exhausted.
+    #[must_use]
+    pub fn report(&self) -> Option<&Report> {
+        self.report.as_ref()
+    }
+}
+
+impl Stream for StepStream<'_> {
+    type Item = StepEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(event) = self.events.pop() {
+            return Poll::Ready(Some(event));
+        }
+        if self.report.is_some() {
+            return Poll::Ready(None);
+        }
+
+        if let Poll::Ready(report) = self.run.as_mut().poll(cx) {
+            self.report = Some(report);
+        }
+        match self.events.pop() {
+            Some(event) => Poll::Ready(Some(event)),
+            None if self.report.is_some() => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl EventSink {
+    pub(crate) fn iteration_started(&self) {
+        let mut events = self.inner.lock().unwrap();
+        let iteration = events.iteration.map_or(0, |iteration| iteration + 1);
+        events.iteration = Some(iteration);
+        events
+            .queue
+            .push_back(StepEvent::IterationStarted { iteration });
+    }
+
+    /// Records the step and yields, so that the stream returns the event before the operation
+    /// is executed.
+    pub(crate) async fn step(&self, step: &TraceStep, locks: &[Lock]) {
+        {
+            let mut events = self.inner.lock().unwrap();
+            let iteration = events.iteration.unwrap_or(0);
+            events.queue.push_back(StepEvent::Step {
+                iteration,
+                step: step.clone(),
+                locks: locks.iter().map(scope_id::named).collect(),
+            });
+        }
+        tokio::task::yield_now().await;
+    }
+
+    pub(crate) fn iteration_finished(&self, failed: bool) {
+        let mut events = self.inner.lock().unwrap();
+        let iteration = events.iteration.unwrap_or(0);
+        events
+            .queue
+            .push_back(StepEvent::IterationFinished { iteration, failed });
+    }
+
+    fn pop(&self) -> Option<StepEvent> {
+        self.inner.lock().unwrap().queue.pop_front()
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt;
+
+use crate::enabled::{
+    task::{TaskId, TaskName},
+    trace::{Names, OperationName, Trace, TraceStep},
+};
+
+/// Sequence of operations every task executes, regardless of the schedule. Captured from an
+/// iteration (see [`Runner::enumerate_schedules`](crate::Runner::enumerate_schedules)) or built
+/// manually to model a scenario.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Structure {
+    tasks: Vec<TaskStructure>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TaskStructure {
+    id: usize,
+    name: String,
+    operations: Vec<String>,
+}
+
+impl Structure {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a task that executes `operations` in order. Tasks get ids in order they are added.
+    #[must_use]
+    pub fn task<O>(mut self, name: impl Into<String>, operations: O) -> Self
+    where
+        O: IntoIterator,
+        O::Item: Into<String>,
+    {
+        self.tasks.push(TaskStructure {
+            id: self.tasks.len(),
+            name: name.into(),
+            operations: operations.into_iter().map(Into::into).collect(),
+        });
+        self
+    }
+
+    pub(crate) fn from_trace(trace: &Trace) -> Self {
+        let mut tasks: Vec<TaskStructure> = Vec::new();
+        for step in trace.steps() {
+            let id = step.task_id().expect("executed steps have task ids");
+            let position = if let Some(position) = tasks.iter().position(|task| task.id == id) {
+                position
+            } else {
+                tasks.push(TaskStructure {
+                    id,
+                    name: step.task_name().to_owned(),
+                    operations: Vec::new(),
+                });
+                tasks.len() - 1
+            };
+            tasks[position]
+                .operations
+                .push(step.operation_name().to_owned());
+        }
+        tasks.sort_by_key(|task| task.id);
+        Self { tasks }
+    }
+
+    /// Names of tasks and operations they execute, in order of task ids.
+    pub fn tasks(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.tasks
+            .iter()
+            .map(|task| (task.name.as_str(), task.operations.as_slice()))
+    }
+
+    /// Number of distinct interleavings of the tasks' operations (saturates at `u128::MAX`).
+    /// Locks and injected faults are not taken into account, so this is an upper bound for
+    /// scenarios with locks and a lower bound for scenarios with fallible operations.
+    #[must_use]
+    pub fn count_schedules(&self) -> u128 {
+        // product of binomial coefficients C(placed + n, n)
+        let mut count: u128 = 1;
+        let mut placed: u128 = 0;
+        for task in &self.tasks {
+            let mut interleavings: u128 = 1;
+            for j in 1..=task.operations.len() as u128 {
+                let Some(product) = interleavings.checked_mul(placed + j) else {
+                    return u128::MAX;
+                };
+                interleavings = product / j;
+            }
+            let Some(product) = count.checked_mul(interleavings) else {
+                return u128::MAX;
+            };
+            count = product;
+            placed += task.operations.len() as u128;
+        }
+        count
+    }
+
+    /// Lazily enumerates interleavings of the tasks' operations (see
+    /// [`Structure::count_schedules`]) as traces that can be replayed.
+    pub fn schedules(&self) -> impl Iterator<Item = Trace> + '_ {
+        // steps of all schedules share the names
+        let mut interned = Names::default();
+        let names: Vec<(TaskName, Vec<OperationName>)> = self
+            .tasks
+            .iter()
+            .map(|task| {
+                let operations = task
+                    .operations
+                    .iter()
+                    .map(|operation| interned.operation(operation))
+                    .collect();
+                (interned.task(&task.name), operations)
+            })
+            .collect();
+
+        // interleaving is a sequence of task indexes, enumerated in lexicographic order
+        let mut next: Option<Vec<usize>> = Some(
+            self.tasks
+                .iter()
+                .enumerate()
+                .flat_map(|(index, task)| std::iter::repeat_n(index, task.operations.len()))
+                .collect(),
+        );
+
+        std::iter::from_fn(move || {
+            let interleaving = next.take()?;
+            next = next_permutation(&interleaving);
+
+            let mut executed = vec![0; self.tasks.len()];
+            let steps = interleaving
+                .into_iter()
+                .map(|index| {
+                    let (task_name, operations) = &names[index];
+                    let op_name = operations[executed[index]].clone();
+                    executed[index] += 1;
+                    TraceStep {
+                        task_id: Some(TaskId(self.tasks[index].id)),
+                        task_name: task_name.clone(),
+                        op_name,
+                        metadata: None,
+                        backtrace: None,
+                        timing: None,
+                        fault: None,
+                        candidates: Vec::new(),
+                    }
+                })
+                .collect();
+            Some(Trace {
+                steps,
+                started_at: None,
+                cancellations: Vec::new(),
+            })
+        })
+    }
+}
+
+/// Next permutation of a multiset in lexicographic order.
+fn next_permutation(current: &[usize]) -> Option<Vec<usize>> {
+    let i = current.windows(2).rposition(|pair| pair[0] < pair[1])?;
+    let j = current.iter().rposition(|value| *value > current[i])?;
+    let mut next = current.to_vec();
+    next.swap(i, j);
+    next[i + 1..].reverse();
+    Some(next)
+}
+
+impl fmt::Display for Structure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for task in &self.tasks {
+            writeln!(
+                f,
+                "{}:{}: {}",
+                task.id,
+                task.name,
+                task.operations.join(" > ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of [`Runner::enumerate_schedules`](crate::Runner::enumerate_schedules).
+#[derive(Debug, Clone)]
+pub struct ScheduleEnumeration {
+    pub(crate) structure: Structure,
+    pub(crate) schedules: Vec<Trace>,
+}
+
+impl ScheduleEnumeration {
+    #[must_use]
+    pub fn structure(&self) -> &Structure {
+        &self.structure
+    }
+
+    /// Total number of schedules, see [`Structure::count_schedules`].
+    #[must_use]
+    pub fn count(&self) -> u128 {
+        self.structure.count_schedules()
+    }
+
+    /// Schedules listed up to the limit.
+    #[must_use]
+    pub fn schedules(&self) -> &[Trace] {
+        &self.schedules
+    }
+}
@@ -1,9 +1,14 @@
 use std::{
     fmt,
     future::Future,
+    hash::{Hash, Hasher},
     pin::Pin,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
     task::{ready, Context, Poll},
+    time::Instant,
 };
 
 use pin_project_lite::pin_project;
@@ -15,23 +20,60 @@ use tokio::{
 #[cfg(feature = "tracing")]
 use tracing::{instrument::Instrumented, Instrument};
 
-use crate::{enabled::operation::OperationMetadata, ParcheckLock};
+use crate::enabled::{
+    operation::{Fallible, OperationMetadata},
+    scope_id::Lock,
+};
 
 pub fn task<F: Future>(name: &str, f: F) -> ParcheckTaskFuture<F> {
     ParcheckTaskFuture::Initial {
-        data: Some((name, f)),
+        data: Some((TaskBinding::Name(name), f)),
     }
 }
 
+/// Binds `f` to a task passed by [`Runner::run_with_handles`](crate::Runner::run_with_handles).
+/// Unlike [`task!`](crate::task!), the task isn't looked up by name.
+pub fn task_with<F: Future>(
+    registration: TaskRegistration,
+    f: F,
+) -> ParcheckTaskFuture<'static, F> {
+    ParcheckTaskFuture::Initial {
+        data: Some((TaskBinding::Registration(registration.task), f)),
+    }
+}
+
+/// Handle of a task expected by [`Runner::run_with_handles`](crate::Runner::run_with_handles),
+/// see [`task_with`].
+#[derive(Debug)]
+pub struct TaskRegistration {
+    task: Task,
+}
+
+impl TaskRegistration {
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.task.name().0
+    }
+}
+
+/// Registrations of the current iteration, handed over from the controller to the scenario.
+pub(crate) type Registrations = Arc<Mutex<Vec<TaskRegistration>>>;
+
+pub(crate) enum TaskBinding<'a> {
+    Name(&'a str),
+    Registration(Task),
+}
+
 pin_project! {
     #[doc(hidden)]
     #[project = ParcheckTaskFutureProj]
     pub enum ParcheckTaskFuture<'a, F> {
         Initial {
-            data: Option<(&'a str, F)>,
+            data: Option<(TaskBinding<'a>, F)>,
         },
         Controlled {
             task: Task,
+            completed: bool,
 
             #[pin]
             fut: InnerFuture<TaskLocalFuture<Task, F>>,
@@ -46,8 +88,12 @@ pin_project! {
     impl<'a, F> PinnedDrop for ParcheckTaskFuture<'a, F> {
         fn drop(this: Pin<&mut Self>) {
             let proj = this.project();
-            if let ParcheckTaskFutureProj::Controlled { task, .. } = proj {
-                task.send_event(TaskEvent::TaskFinished);
+            if let ParcheckTaskFutureProj::Controlled { task, completed, .. } = proj {
+                task.send_event(if *completed {
+                    TaskEvent::TaskFinished
+                } else {
+                    TaskEvent::TaskCancelled
+                });
             }
         }
     }
@@ -66,9 +112,13 @@ impl<'a, F: Future> Future for ParcheckTaskFuture<'a, F> {
             let this = self.as_mut().project();
             let new_state = match this {
                 ParcheckTaskFutureProj::Initial { data } => {
-                    let (name, fut) = data.take().unwrap();
+                    let (binding, fut) = data.take().unwrap();
+                    let task = match binding {
+                        TaskBinding::Name(name) => Task::pop_expected_task(name),
+                        TaskBinding::Registration(task) => Some(task),
+                    };
 
-                    if let Some(task) = Task::pop_expected_task(name) {
+                    if let Some(task) = task {
                         task.send_event(TaskEvent::TaskStarted);
 
                         let fut = TASK.scope(task.clone(), fut);
@@ -77,19 +127,21 @@ impl<'a, F: Future> Future for ParcheckTaskFuture<'a, F> {
                             fut: fut.instrument(tracing::info_span!(
                                 "parcheck.task",
                                 "parcheck.task.id" = task.id().0,
-                                "parcheck.task.name" = name,
+                                "parcheck.task.name" = &*task.name().0,
                             )),
                             #[cfg(not(feature = "tracing"))]
                             fut,
                             task,
+                            completed: false,
                         }
                     } else {
                         Self::Uncontrolled { fut }
                     }
                 }
-                ParcheckTaskFutureProj::Controlled { task, fut } => {
+                ParcheckTaskFutureProj::Controlled { fut, completed, .. } => {
                     let value = ready!(fut.poll(cx));
-                    task.send_event(TaskEvent::TaskFinished);
+                    *completed = true;
+                    // `TaskFinished` is sent on drop of `Controlled` state
                     self.set(Self::Done);
                     return Poll::Ready(value);
                 }
@@ -113,11 +165,27 @@ tokio::task_local! {
     static TASK: Task;
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) struct TaskId(pub(crate) usize);
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub(crate) struct TaskName(pub(crate) String);
+/// Name of a task. Steps of a parsed trace share the name (see `trace::Names`), so comparing
+/// them usually only compares pointers.
+#[derive(Debug, Clone)]
+pub(crate) struct TaskName(pub(crate) Arc<str>);
+
+impl PartialEq for TaskName {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.0 == other.0
+    }
+}
+
+impl Eq for TaskName {}
+
+impl Hash for TaskName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
 
 #[derive(Clone)]
 pub(crate) struct Task {
@@ -128,23 +196,74 @@ pub(crate) enum TaskEvent {
     TaskStarted,
     OperationPermitRequested {
         metadata: &'static OperationMetadata,
-        permit: oneshot::Sender<OperationPermit>,
-        locks: Vec<ParcheckLock>,
+        /// `None` if the operation already started with a fast permit.
+        permit: Option<oneshot::Sender<OperationPermit>>,
+        locks: Vec<Lock>,
+        backtrace: Option<Arc<str>>,
+        fault: Option<Fallible>,
+    },
+    /// Operation executed between `started_at` and `finished_at` (wall-clock).
+    OperationFinished {
+        metadata: &'static OperationMetadata,
+        started_at: Instant,
+        finished_at: Instant,
     },
-    OperationFinished,
     TaskFinished,
+    /// Task future was dropped before it completed.
+    TaskCancelled,
+}
+
+/// Event stamped with its position among events of the task. Events of a task sent from different
+/// threads can arrive out of order, the controller processes them in order of `seq`.
+pub(crate) struct SequencedEvent {
+    pub(crate) task_id: TaskId,
+    pub(crate) seq: u64,
+    pub(crate) event: TaskEvent,
+}
+
+impl fmt::Debug for TaskEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TaskStarted => f.write_str("TaskStarted"),
+            Self::OperationPermitRequested { metadata, .. } => {
+                write!(f, "OperationPermitRequested for {metadata}")
+            }
+            Self::OperationFinished { .. } => f.write_str("OperationFinished"),
+            Self::TaskFinished => f.write_str("TaskFinished"),
+            Self::TaskCancelled => f.write_str("TaskCancelled"),
+        }
+    }
+}
+
+/// Span of a scheduling decision, operation span is linked to it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StepSpan {
+    #[cfg(feature = "tracing")]
+    pub(crate) span: Option<tracing::Span>,
 }
 
 #[derive(Debug)]
 pub(crate) enum OperationPermit {
-    Granted,
-    OperationAlreadyInProgress { other: &'static OperationMetadata },
+    Granted {
+        step_span: StepSpan,
+        inject_fault: bool,
+    },
+    OperationAlreadyInProgress {
+        other: &'static OperationMetadata,
+    },
 }
 
 struct TaskInner {
     id: TaskId,
     name: TaskName,
-    events: mpsc::UnboundedSender<(TaskId, TaskEvent)>,
+    events: mpsc::UnboundedSender<SequencedEvent>,
+    next_seq: AtomicU64,
+    /// Operations currently executed by the task (normally at most one), keyed by addresses of
+    /// their (pinned) futures, with the time they started at (wall-clock).
+    executing: Mutex<Vec<(usize, &'static OperationMetadata, Instant)>>,
+    /// Set by the controller once no other task can be scheduled instead of this one, see
+    /// [`Task::start_fast`].
+    fast_permits: AtomicBool,
 }
 
 impl fmt::Debug for Task {
@@ -159,21 +278,88 @@ impl fmt::Debug for Task {
 static EXPECTED_TASKS: Mutex<Vec<Task>> = Mutex::new(Vec::new());
 
 impl Task {
+    /// Creates a task expected in this iteration. It's either found by name when started with
+    /// `task!`, or passed to the scenario with `registrations`.
     pub(crate) fn register(
         id: TaskId,
         name: TaskName,
-        events: mpsc::UnboundedSender<(TaskId, TaskEvent)>,
+        events: mpsc::UnboundedSender<SequencedEvent>,
+        registrations: Option<&Registrations>,
     ) -> Self {
         let task = Self {
-            inner: Arc::new(TaskInner { id, name, events }),
+            inner: Arc::new(TaskInner {
+                id,
+                name,
+                events,
+                next_seq: AtomicU64::new(0),
+                executing: Mutex::new(Vec::new()),
+                fast_permits: AtomicBool::new(false),
+            }),
         };
-        EXPECTED_TASKS.lock().unwrap().push(task.clone());
+        match registrations {
+            Some(registrations) => registrations
+                .lock()
+                .unwrap()
+                .push(TaskRegistration { task: task.clone() }),
+            None => EXPECTED_TASKS.lock().unwrap().push(task.clone()),
+        }
         task
     }
 
     pub(crate) fn send_event(&self, event: TaskEvent) {
+        let seq = self.inner.next_seq.fetch_add(1, Ordering::Relaxed);
         // ignore error
-        let _ = self.inner.events.send((self.inner.id, event));
+        let _ = self.inner.events.send(SequencedEvent {
+            task_id: self.inner.id,
+            seq,
+            event,
+        });
+    }
+
+    pub(crate) fn operation_started(&self, key: usize, metadata: &'static OperationMetadata) {
+        self.inner
+            .executing
+            .lock()
+            .unwrap()
+            .push((key, metadata, Instant::now()));
+    }
+
+    /// Allows operations of the task to start without waiting for a permit.
+    pub(crate) fn grant_fast_permits(&self) {
+        self.inner.fast_permits.store(true, Ordering::Release);
+    }
+
+    /// Starts an operation without waiting for a permit, if the controller granted fast permits
+    /// and no other operation of the task is executing. The controller still has to be notified
+    /// with [`TaskEvent::OperationPermitRequested`].
+    pub(crate) fn start_fast(&self, key: usize, metadata: &'static OperationMetadata) -> bool {
+        if !self.inner.fast_permits.load(Ordering::Acquire) {
+            return false;
+        }
+        let mut executing = self.inner.executing.lock().unwrap();
+        if !executing.is_empty() {
+            return false;
+        }
+        executing.push((key, metadata, Instant::now()));
+        true
+    }
+
+    pub(crate) fn operation_finished(&self, key: usize) {
+        let finished_at = Instant::now();
+        let (metadata, started_at) = {
+            let mut executing = self.inner.executing.lock().unwrap();
+            let index = executing
+                .iter()
+                .position(|(k, _, _)| *k == key)
+                .expect("finished operation wasn't started");
+            let (_, metadata, started_at) = executing.swap_remove(index);
+            (metadata, started_at)
+        };
+        self.send_event(TaskEvent::OperationFinished {
+            metadata,
+            started_at,
+            finished_at,
+        });
     }
 
     pub(crate) fn id(&self) -> TaskId {
@@ -184,9 +370,16 @@ impl Task {
         &self.inner.name
     }
 
+    /// Whether anything besides this handle refers to the task (e.g. a task or operation future).
+    pub(crate) fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.inner) > 1
+    }
+
     fn pop_expected_task(name: &str) -> Option<Task> {
         let mut expected = EXPECTED_TASKS.lock().unwrap();
-        let idx = expected.iter().position(|task| task.inner.name.0 == name)?;
+        let idx = expected
+            .iter()
+            .position(|task| &*task.inner.name.0 == name)?;
         Some(expected.swap_remove(idx))
     }
 }
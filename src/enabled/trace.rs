@@ -0,0 +1,640 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt::{self, Write as _},
+    ptr,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::enabled::{
+    controller::TaskState,
+    json::{self, escape},
+    operation::{Fault, OperationMetadata},
+    task::{Task, TaskId, TaskName},
+};
+
+/// Steps of an iteration in the order they were executed. Displayed (and parsed) as
+/// `task_id:task.operation!fault` steps separated by ` > ` (the task id and the fault are
+/// optional), with `:`, `.`, `!`, `>` and `\` in task and operation names escaped with `\`.
+#[derive(Clone)]
+pub struct Trace {
+    pub(crate) steps: Vec<TraceStep>,
+    #[cfg_attr(not(feature = "otel"), allow(dead_code))]
+    pub(crate) started_at: Option<SystemTime>,
+    pub(crate) cancellations: Vec<Cancellation>,
+}
+
+/// Task that was dropped before it finished (e.g. by `tokio::select!` or a timeout).
+#[derive(Debug, Clone)]
+pub struct Cancellation {
+    pub(crate) step: usize,
+    pub(crate) task_id: TaskId,
+    pub(crate) task_name: TaskName,
+}
+
+impl Cancellation {
+    /// Number of steps of the trace executed before the cancellation was observed.
+    #[must_use]
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    #[must_use]
+    pub fn task_id(&self) -> usize {
+        self.task_id.0
+    }
+
+    #[must_use]
+    pub fn task_name(&self) -> &str {
+        &self.task_name.0
+    }
+}
+
+/// A single step of a [`Trace`]: operation of a task that was allowed to execute.
+#[derive(Clone)]
+pub struct TraceStep {
+    /// Not known for hand-written steps that only name the task, resolved when replayed.
+    pub(crate) task_id: Option<TaskId>,
+    pub(crate) task_name: TaskName,
+    pub(crate) op_name: OperationName,
+    pub(crate) metadata: Option<&'static OperationMetadata>,
+    pub(crate) backtrace: Option<Arc<str>>,
+    pub(crate) timing: Option<StepTiming>,
+    pub(crate) fault: Option<Fault>,
+    pub(crate) candidates: Vec<StepCandidate>,
+}
+
+/// Step that could be chosen at a step of a trace (including the chosen one), recorded with
+/// [`Runner::record_candidates`](crate::Runner::record_candidates).
+#[derive(Debug, Clone)]
+pub struct StepCandidate {
+    pub(crate) task_id: TaskId,
+    pub(crate) task_name: TaskName,
+    pub(crate) metadata: &'static OperationMetadata,
+    pub(crate) fault: Option<Fault>,
+    pub(crate) blocked: bool,
+}
+
+impl StepCandidate {
+    /// Steps every waiting task could take: executing its operation (unless it's blocked) or
+    /// failing with an injectable fault.
+    pub(crate) fn collect(tasks: &[(Task, TaskState)]) -> Vec<Self> {
+        let mut candidates = Vec::new();
+        for (task, state) in tasks {
+            let TaskState::WaitingToStartOperation { metadata, .. } = state else {
+                continue;
+            };
+            candidates.push(Self {
+                task_id: task.id(),
+                task_name: task.name().clone(),
+                metadata,
+                fault: None,
+                blocked: !state.can_execute(),
+            });
+            if let Some(fault) = state.injectable_fault() {
+                candidates.push(Self {
+                    task_id: task.id(),
+                    task_name: task.name().clone(),
+                    metadata,
+                    fault: Some(fault),
+                    blocked: false,
+                });
+            }
+        }
+        candidates
+    }
+
+    #[must_use]
+    pub fn task_id(&self) -> usize {
+        self.task_id.0
+    }
+
+    #[must_use]
+    pub fn task_name(&self) -> &str {
+        &self.task_name.0
+    }
+
+    #[must_use]
+    pub fn operation(&self) -> &'static OperationMetadata {
+        self.metadata
+    }
+
+    /// Name of the fault injected instead of executing the operation, if any.
+    #[must_use]
+    pub fn fault(&self) -> Option<&'static str> {
+        self.fault.map(Fault::name)
+    }
+
+    /// Task is waiting for the operation, but can't execute it because of locks held by other
+    /// tasks.
+    #[must_use]
+    pub fn blocked(&self) -> bool {
+        self.blocked
+    }
+}
+
+/// Monotonic time when operation was granted a permit (or started, if it didn't wait for one) and
+/// when it finished.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StepTiming {
+    pub(crate) granted_at: Instant,
+    pub(crate) finished_at: Instant,
+}
+
+impl StepTiming {
+    pub(crate) fn duration(self) -> Duration {
+        self.finished_at.saturating_duration_since(self.granted_at)
+    }
+}
+
+/// Name of an operation: executed steps borrow it from the operation's metadata, parsed steps
+/// share an interned copy. Names of the same operation thus usually share the string, and are
+/// compared by pointer before comparing the text.
+#[derive(Debug, Clone)]
+pub(crate) enum OperationName {
+    Static(&'static str),
+    Interned(Arc<str>),
+}
+
+impl OperationName {
+    pub(crate) fn as_str(&self) -> &str {
+        match self {
+            Self::Static(name) => name,
+            Self::Interned(name) => name,
+        }
+    }
+}
+
+impl PartialEq for OperationName {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self.as_str(), other.as_str()) || self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for OperationName {}
+
+/// Task and operation names of a trace being parsed. Every name is stored once and shared by
+/// steps, so parsed traces with many steps take little memory.
+#[derive(Default)]
+pub(crate) struct Names(HashSet<Arc<str>>);
+
+impl Names {
+    pub(crate) fn task(&mut self, name: &str) -> TaskName {
+        TaskName(self.intern(name, || name.into()))
+    }
+
+    pub(crate) fn operation(&mut self, name: &str) -> OperationName {
+        OperationName::Interned(self.intern(name, || name.into()))
+    }
+
+    fn intern(&mut self, name: &str, new: impl FnOnce() -> Arc<str>) -> Arc<str> {
+        if let Some(interned) = self.0.get(name) {
+            return interned.clone();
+        }
+        let interned = new();
+        self.0.insert(interned.clone());
+        interned
+    }
+}
+
+impl Trace {
+    pub(crate) fn new() -> Self {
+        Self {
+            steps: Vec::new(),
+            started_at: Some(SystemTime::now()),
+            cancellations: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn steps(&self) -> &[TraceStep] {
+        &self.steps
+    }
+
+    /// Tasks cancelled during the iteration, in order they were cancelled in. Not part of the
+    /// textual representation: cancellations are decided by the scenario, not by the schedule.
+    #[must_use]
+    pub fn cancellations(&self) -> &[Cancellation] {
+        &self.cancellations
+    }
+
+    /// Generates source code of a `#[tokio::test]` that replays this trace. Body of the scenario
+    /// is left as `todo!()`. Task names are taken from the steps, so tasks that never executed an
+    /// operation have to be added manually.
+    #[must_use]
+    pub fn to_replay_test(&self, name: &str) -> String {
+        let mut tasks: Vec<(Option<TaskId>, &TaskName)> = Vec::new();
+        for step in &self.steps {
+            if !tasks
+                .iter()
+                .any(|(id, name)| *id == step.task_id && *name == &step.task_name)
+            {
+                tasks.push((step.task_id, &step.task_name));
+            }
+        }
+        tasks.sort_by_key(|(id, _)| id.map(|id| id.0));
+        let tasks = tasks
+            .iter()
+            .map(|(_, name)| format!("{:?}", name.0))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!(
+            r#"#[tokio::test]
+async fn {name}() {{
+    let trace: parcheck::Trace = {trace:?}.parse().unwrap();
+
+    parcheck::runner()
+        .replay(trace)
+        .run([{tasks}], || async {{
+            todo!("scenario body")
+        }})
+        .await;
+}}
+"#,
+            trace = self.to_string(),
+        )
+    }
+
+    /// Total time of the steps, from granting every permit until the operation finished. Not
+    /// available for traces that weren't executed (e.g. parsed ones).
+    #[must_use]
+    pub fn duration(&self) -> Option<Duration> {
+        self.steps
+            .iter()
+            .filter_map(TraceStep::duration)
+            .reduce(|total, duration| total + duration)
+    }
+
+    /// Serializes the trace as a JSON object with a `steps` array. Every step has `task_id`,
+    /// `task_name`, `operation`, `fault`, `file`, `line` and `duration_ns` keys (missing values
+    /// are `null`).
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let steps = self
+            .steps
+            .iter()
+            .map(TraceStep::to_json)
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(r#"{{"steps":[{steps}]}}"#)
+    }
+
+    /// Serializes the trace as JSON Lines: one step object (same as in [`Trace::to_json`]) per
+    /// line.
+    #[must_use]
+    pub fn to_jsonl(&self) -> String {
+        self.steps.iter().fold(String::new(), |mut jsonl, step| {
+            jsonl.push_str(&step.to_json());
+            jsonl.push('\n');
+            jsonl
+        })
+    }
+
+    /// Parses a trace produced by [`Trace::to_json`]. Source locations and durations are not
+    /// restored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseTraceError`] if input is not valid JSON or doesn't describe a trace.
+    pub fn from_json(s: &str) -> Result<Self, ParseTraceError> {
+        let json = json::parse(s).ok_or(ParseTraceError)?;
+        let steps = json
+            .get("steps")
+            .and_then(json::Value::as_array)
+            .ok_or(ParseTraceError)?;
+
+        let mut names = Names::default();
+        let steps = steps
+            .iter()
+            .map(|step| {
+                let field = |key| step.get(key).ok_or(ParseTraceError);
+                let task_id = match field("task_id")? {
+                    json::Value::Null => None,
+                    task_id => Some(TaskId(task_id.as_usize().ok_or(ParseTraceError)?)),
+                };
+                let task_name = field("task_name")?.as_str().ok_or(ParseTraceError)?;
+                let op_name = field("operation")?.as_str().ok_or(ParseTraceError)?;
+                let fault = match step.get("fault") {
+                    None | Some(json::Value::Null) => None,
+                    Some(fault) => Some(
+                        fault
+                            .as_str()
+                            .and_then(Fault::from_name)
+                            .ok_or(ParseTraceError)?,
+                    ),
+                };
+
+                Ok(TraceStep {
+                    task_id,
+                    task_name: names.task(task_name),
+                    op_name: names.operation(op_name),
+                    metadata: None,
+                    backtrace: None,
+                    timing: None,
+                    fault,
+                    candidates: Vec::new(),
+                })
+            })
+            .collect::<Result<Vec<_>, ParseTraceError>>()?;
+
+        Ok(Self {
+            steps,
+            started_at: None,
+            cancellations: Vec::new(),
+        })
+    }
+
+    /// Serializes the trace as CSV with a header row and one row per step:
+    /// `step,task_id,task_name,operation,fault,file,line,duration_ns`. Missing values are left
+    /// empty.
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("step,task_id,task_name,operation,fault,file,line,duration_ns\n");
+        for (index, step) in self.steps.iter().enumerate() {
+            writeln!(
+                csv,
+                "{index},{},{},{},{},{},{},{}",
+                step.task_id
+                    .map_or_else(String::new, |task_id| task_id.0.to_string()),
+                csv_field(&step.task_name.0),
+                csv_field(step.op_name.as_str()),
+                step.fault.map_or("", Fault::name),
+                step.metadata
+                    .map_or_else(String::new, |metadata| csv_field(metadata.file())),
+                step.metadata
+                    .map_or_else(String::new, |metadata| metadata.line().to_string()),
+                step.duration()
+                    .map_or_else(String::new, |duration| duration.as_nanos().to_string()),
+            )
+            .unwrap();
+        }
+        csv
+    }
+}
+
+impl TraceStep {
+    /// Id of the task, `None` for steps of parsed traces that only name the task (`task.op`).
+    #[must_use]
+    pub fn task_id(&self) -> Option<usize> {
+        self.task_id.map(|task_id| task_id.0)
+    }
+
+    #[must_use]
+    pub fn task_name(&self) -> &str {
+        &self.task_name.0
+    }
+
+    #[must_use]
+    pub fn operation_name(&self) -> &str {
+        self.op_name.as_str()
+    }
+
+    /// Source location of the operation. Not available for traces parsed from a string.
+    #[must_use]
+    pub fn metadata(&self) -> Option<&'static OperationMetadata> {
+        self.metadata
+    }
+
+    /// Name of the fault (e.g. `"timeout"`) injected instead of executing the operation.
+    #[must_use]
+    pub fn injected_fault(&self) -> Option<&'static str> {
+        self.fault.map(Fault::name)
+    }
+
+    /// Shortened backtrace captured when the operation was requested. Only available with
+    /// `backtrace` feature.
+    #[must_use]
+    pub fn backtrace(&self) -> Option<&str> {
+        self.backtrace.as_deref()
+    }
+
+    /// Steps that could be chosen instead of this one, empty unless recorded with
+    /// [`Runner::record_candidates`](crate::Runner::record_candidates).
+    #[must_use]
+    pub fn candidates(&self) -> &[StepCandidate] {
+        &self.candidates
+    }
+
+    /// Time from granting the permit until the operation finished (measured with a monotonic
+    /// clock). Operations of the last unfinished task may start before their step is picked, their
+    /// time is measured from the start. Not available for steps that weren't executed.
+    #[must_use]
+    pub fn duration(&self) -> Option<Duration> {
+        self.timing.map(StepTiming::duration)
+    }
+}
+
+impl TraceStep {
+    fn to_json(&self) -> String {
+        let string_or_null =
+            |s: Option<&str>| s.map_or("null".to_owned(), |s| format!(r#""{}""#, escape(s)));
+        format!(
+            r#"{{"task_id":{},"task_name":"{}","operation":"{}","fault":{},"file":{},"line":{},"duration_ns":{}}}"#,
+            self.task_id
+                .map_or("null".to_owned(), |task_id| task_id.0.to_string()),
+            escape(&self.task_name.0),
+            escape(self.op_name.as_str()),
+            string_or_null(self.fault.map(Fault::name)),
+            string_or_null(self.metadata.map(OperationMetadata::file)),
+            self.metadata
+                .map_or("null".to_owned(), |metadata| metadata.line().to_string()),
+            self.duration()
+                .map_or("null".to_owned(), |duration| duration
+                    .as_nanos()
+                    .to_string()),
+        )
+    }
+}
+
+impl fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(task_id) = self.task_id {
+            write!(f, "{}:", task_id.0)?;
+        }
+        write!(
+            f,
+            "{}.{}",
+            Escaped(&self.task_name.0),
+            Escaped(self.op_name.as_str())
+        )?;
+        if let Some(fault) = self.fault {
+            write!(f, "!{}", fault.name())?;
+        }
+        Ok(())
+    }
+}
+
+/// Steps are equal if they have the same textual representation (see [`Trace`]'s `Display`).
+impl PartialEq for TraceStep {
+    fn eq(&self, other: &Self) -> bool {
+        self.task_id == other.task_id
+            && self.task_name == other.task_name
+            && self.op_name == other.op_name
+            && self.fault == other.fault
+    }
+}
+
+impl Eq for TraceStep {}
+
+impl fmt::Debug for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.metadata {
+            Some(metadata) => write!(f, "{self} (at {}:{})", metadata.file(), metadata.line()),
+            None => write!(f, "{self}"),
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.steps.is_empty() {
+            return Ok(());
+        }
+
+        write!(f, "{}", self.steps[0])?;
+        for step in &self.steps[1..] {
+            write!(f, " > {step}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Traces are equal if they have equal steps.
+impl PartialEq for Trace {
+    fn eq(&self, other: &Self) -> bool {
+        self.steps == other.steps
+    }
+}
+
+impl Eq for Trace {}
+
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.steps).finish()
+    }
+}
+
+impl FromStr for Trace {
+    type Err = ParseTraceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut interned = Names::default();
+        let steps = s
+            .split(" > ")
+            .filter(|_| !s.is_empty())
+            .map(|step| {
+                // task id is optional, a task name may contain unescaped ':' after it
+                let (task_id, names) = match split_unescaped(step, ':') {
+                    Some((task_id, names))
+                        if !task_id.is_empty() && task_id.bytes().all(|b| b.is_ascii_digit()) =>
+                    {
+                        (Some(task_id), names)
+                    }
+                    _ => (None, step),
+                };
+                let (task_name, op_name) = split_unescaped(names, '.').ok_or(ParseTraceError)?;
+                let (op_name, fault) = match split_unescaped(op_name, '!') {
+                    Some((op_name, fault)) => (
+                        op_name,
+                        Some(Fault::from_name(fault).ok_or(ParseTraceError)?),
+                    ),
+                    None => (op_name, None),
+                };
+
+                let task_id = task_id
+                    .map(|task_id| task_id.parse().map(TaskId))
+                    .transpose()
+                    .map_err(|_| ParseTraceError)?;
+                Ok(TraceStep {
+                    task_id,
+                    task_name: interned.task(&unescape(task_name)?),
+                    op_name: interned.operation(&unescape(op_name)?),
+                    metadata: None,
+                    backtrace: None,
+                    timing: None,
+                    fault,
+                    candidates: Vec::new(),
+                })
+            })
+            .collect::<Result<Vec<_>, ParseTraceError>>()?;
+
+        Ok(Self {
+            steps,
+            started_at: None,
+            cancellations: Vec::new(),
+        })
+    }
+}
+
+/// Task or operation name in a step, with characters that separate parts of steps (and `\`)
+/// escaped with `\`, like in [`scope!`](crate::scope!) names.
+struct Escaped<'a>(&'a str);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            if matches!(c, '\\' | ':' | '.' | '!' | '>') {
+                f.write_char('\\')?;
+            }
+            f.write_char(c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits `s` at the first occurrence of `separator` that isn't escaped (see [`Escaped`]).
+fn split_unescaped(s: &str, separator: char) -> Option<(&str, &str)> {
+    let mut escaped = false;
+    for (idx, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == separator {
+            return Some((&s[..idx], &s[idx + c.len_utf8()..]));
+        }
+    }
+    None
+}
+
+fn unescape(s: &str) -> Result<String, ParseTraceError> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(if c == '\\' {
+            chars.next().ok_or(ParseTraceError)?
+        } else {
+            c
+        });
+    }
+    Ok(unescaped)
+}
+
+/// Quotes the field if it contains a separator, a quote or a line break.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!(r#""{}""#, s.replace('"', r#""""#))
+    } else {
+        s.to_owned()
+    }
+}
+
+pub struct ParseTraceError;
+
+impl fmt::Debug for ParseTraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid trace")
+    }
+}
+
+impl fmt::Display for ParseTraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid trace")
+    }
+}
+
+impl Error for ParseTraceError {}
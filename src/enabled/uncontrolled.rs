@@ -0,0 +1,88 @@
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Mutex,
+    },
+};
+
+use crate::enabled::operation::OperationMetadata;
+
+/// What happens when an operation is executed outside of a task controlled by a runner (e.g. a
+/// production code path in a binary accidentally compiled with `enable` feature).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UncontrolledPolicy {
+    /// Operation executes as if parcheck was disabled. This is the default.
+    Silent,
+    /// Same as `Silent`, but the first uncontrolled execution of every call site prints a warning
+    /// with its location.
+    WarnOnce,
+    /// Panics with the operation's location.
+    Panic,
+}
+
+const UNSET: u8 = u8::MAX;
+
+static POLICY: AtomicU8 = AtomicU8::new(UNSET);
+static WARNED: Mutex<Vec<(&str, u32)>> = Mutex::new(Vec::new());
+
+/// Sets process-wide [`UncontrolledPolicy`]. Takes precedence over `PARCHECK_UNCONTROLLED`
+/// environment variable (`silent`, `warn-once` or `panic`).
+pub fn set_uncontrolled_policy(policy: UncontrolledPolicy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+pub(crate) fn check(metadata: &OperationMetadata) {
+    match policy() {
+        UncontrolledPolicy::Silent => {}
+        UncontrolledPolicy::WarnOnce => {
+            let location = (metadata.file, metadata.line);
+            let mut warned = WARNED.lock().unwrap();
+            if !warned.contains(&location) {
+                warned.push(location);
+                eprintln!(
+                    "warning: parcheck operation '{}' executed outside of a controlled task (at {}:{})",
+                    metadata.name, metadata.file, metadata.line
+                );
+            }
+        }
+        UncontrolledPolicy::Panic => panic!(
+            "parcheck operation '{}' executed outside of a controlled task (at {}:{})",
+            metadata.name, metadata.file, metadata.line
+        ),
+    }
+}
+
+impl UncontrolledPolicy {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "silent" => Some(Self::Silent),
+            "warn-once" => Some(Self::WarnOnce),
+            "panic" => Some(Self::Panic),
+            _ => None,
+        }
+    }
+}
+
+fn policy() -> UncontrolledPolicy {
+    let policy = match POLICY.load(Ordering::Relaxed) {
+        UNSET => {
+            let policy =
+                env::var("PARCHECK_UNCONTROLLED").map_or(UncontrolledPolicy::Silent, |policy| {
+                    UncontrolledPolicy::from_name(&policy)
+                        .expect("failed to parse PARCHECK_UNCONTROLLED")
+                });
+            // policy set with `set_uncontrolled_policy` wins
+            POLICY
+                .compare_exchange(UNSET, policy as u8, Ordering::Relaxed, Ordering::Relaxed)
+                .map_or_else(|set| set, |_| policy as u8)
+        }
+        set => set,
+    };
+
+    match policy {
+        0 => UncontrolledPolicy::Silent,
+        1 => UncontrolledPolicy::WarnOnce,
+        _ => UncontrolledPolicy::Panic,
+    }
+}
@@ -0,0 +1,173 @@
+use crate::enabled::{
+    controller::{Controller, Mode, TaskState},
+    operation::OperationMetadata,
+    task::TaskId,
+};
+
+/// Read-only view of the scheduler state of current iteration, available to hooks through
+/// [`StepContext::controller`](crate::StepContext::controller).
+#[derive(Clone, Copy)]
+pub struct ControllerView<'a> {
+    controller: &'a Controller,
+}
+
+/// State of a task as seen by the scheduler.
+#[derive(Debug, Clone, Copy)]
+pub enum TaskStatus {
+    NotStarted,
+    /// Task is executing code outside of parcheck operations.
+    Running,
+    /// Task is waiting for a permit to start the operation.
+    Waiting(&'static OperationMetadata),
+    Executing(&'static OperationMetadata),
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+    /// Mode requested with [`ParcheckLock::Acquire`](crate::ParcheckLock::Acquire).
+    Custom(&'static str),
+}
+
+/// Lock requested by a waiting task that conflicts with locks held by other tasks, see
+/// [`ControllerView::blocked_locks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockedLock<'a> {
+    task: &'a str,
+    scope: &'a str,
+    mode: LockMode,
+    holders: Vec<(&'a str, LockMode)>,
+}
+
+impl<'a> BlockedLock<'a> {
+    /// Name of the blocked task.
+    #[must_use]
+    pub fn task(&self) -> &'a str {
+        self.task
+    }
+
+    #[must_use]
+    pub fn scope(&self) -> &'a str {
+        self.scope
+    }
+
+    /// Mode the blocked task requested.
+    #[must_use]
+    pub fn mode(&self) -> LockMode {
+        self.mode
+    }
+
+    /// Tasks holding conflicting locks on the scope.
+    #[must_use]
+    pub fn holders(&self) -> &[(&'a str, LockMode)] {
+        &self.holders
+    }
+}
+
+impl<'a> ControllerView<'a> {
+    pub(crate) fn new(controller: &'a Controller) -> Self {
+        Self { controller }
+    }
+
+    /// Names and states of all tasks of the iteration.
+    pub fn tasks(&self) -> impl Iterator<Item = (&'a str, TaskStatus)> + 'a {
+        self.controller.tasks().iter().map(|(task, state)| {
+            let status = match state {
+                TaskState::NotStarted => TaskStatus::NotStarted,
+                TaskState::ExecutingOutsideOperation => TaskStatus::Running,
+                TaskState::WaitingToStartOperation { metadata, .. } => {
+                    TaskStatus::Waiting(metadata)
+                }
+                TaskState::ExecutingOperation { metadata } => TaskStatus::Executing(metadata),
+                TaskState::Finished => TaskStatus::Finished,
+                TaskState::Invalid => unreachable!("task state observed during transition"),
+            };
+            (&*task.name().0, status)
+        })
+    }
+
+    /// State of the task with given name (first one, if there are several).
+    #[must_use]
+    pub fn task(&self, name: &str) -> Option<TaskStatus> {
+        self.tasks()
+            .find(|(task_name, _)| *task_name == name)
+            .map(|(_, status)| status)
+    }
+
+    /// Names of tasks currently holding a lock on `scope`.
+    #[must_use]
+    pub fn holders(&self, scope: &str) -> Vec<(&'a str, LockMode)> {
+        self.controller
+            .holders_by_name(scope)
+            .into_iter()
+            .map(|(task_id, mode)| (self.name(task_id), mode.into()))
+            .collect()
+    }
+
+    /// Tasks waiting for an operation whose locks are held by other tasks, with names of those
+    /// tasks.
+    #[must_use]
+    pub fn blocked(&self) -> Vec<(&'a str, Vec<&'a str>)> {
+        self.controller
+            .tasks()
+            .iter()
+            .filter_map(|(task, _)| {
+                let blockers = self.controller.blockers(task.id());
+                (!blockers.is_empty()).then(|| {
+                    let blockers = blockers.into_iter().map(|id| self.name(id)).collect();
+                    (&*task.name().0, blockers)
+                })
+            })
+            .collect()
+    }
+
+    /// Lock requests of waiting tasks that can't be granted because of locks held by other
+    /// tasks, in order of task ids. Unlike [`ControllerView::blocked`], lists every conflicting
+    /// scope with its holders.
+    #[must_use]
+    pub fn blocked_locks(&self) -> Vec<BlockedLock<'a>> {
+        let mut blocked = Vec::new();
+        for (task, state) in self.controller.tasks() {
+            let TaskState::WaitingToStartOperation { blocked_locks, .. } = state else {
+                continue;
+            };
+            for lock in blocked_locks {
+                let Some((scope, mode)) = Mode::of(lock) else {
+                    continue;
+                };
+                let holders = self
+                    .controller
+                    .holders(scope)
+                    .iter()
+                    .filter(|(holder_id, holder_mode)| {
+                        *holder_id != task.id() && self.controller.conflicts(*holder_mode, mode)
+                    })
+                    .map(|(holder_id, holder_mode)| (self.name(*holder_id), (*holder_mode).into()))
+                    .collect();
+                blocked.push(BlockedLock {
+                    task: &task.name().0,
+                    scope: scope.name(),
+                    mode: mode.into(),
+                    holders,
+                });
+            }
+        }
+        blocked
+    }
+
+    fn name(self, id: TaskId) -> &'a str {
+        &self.controller.tasks()[id.0].0.name().0
+    }
+}
+
+impl From<Mode> for LockMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Shared => LockMode::Shared,
+            Mode::Exclusive => LockMode::Exclusive,
+            Mode::Custom(mode) => LockMode::Custom(mode),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Lock templates approximating row locking of Postgres transactions, so that operations
+//! accessing a database get consistent [`ParcheckLock`]s.
+//!
+//! Scopes are named `<table>:<key>` (see [`scope!`](crate::scope!)). Locks acquired by a transaction are held until
+//! [`Transaction::commit`] (or [`Transaction::rollback`]), whose locks should be passed to the
+//! operation that finishes the transaction.
+//!
+//! ```
+//! # async fn example() {
+//! use parcheck::isolation::{Isolation, Transaction};
+//!
+//! let mut tx = Transaction::new(Isolation::ReadCommitted);
+//! parcheck::operation!("select_balance", tx.read_row("accounts", 1), { async {} }).await;
+//! parcheck::operation!("update_balance", tx.update_row("accounts", 1), { async {} }).await;
+//! parcheck::operation!("commit", tx.commit(), { async {} }).await;
+//! # }
+//! ```
+
+use std::fmt::Display;
+
+use crate::ParcheckLock;
+
+/// Transaction isolation level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Isolation {
+    /// Plain reads don't lock, writes lock rows until the end of the transaction.
+    ReadCommitted,
+    /// Snapshot isolation. Locks are the same as with [`Isolation::ReadCommitted`]: reading from
+    /// the snapshot and failing writes to concurrently updated rows have to be modeled by the
+    /// code under test.
+    RepeatableRead,
+    /// Approximated with two-phase locking: reads lock rows in shared mode until the end of the
+    /// transaction.
+    Serializable,
+}
+
+/// Tracks locks held by a database transaction.
+#[derive(Clone, Debug)]
+pub struct Transaction {
+    isolation: Isolation,
+    held: Vec<String>,
+}
+
+impl Transaction {
+    #[must_use]
+    pub fn new(isolation: Isolation) -> Self {
+        Self {
+            isolation,
+            held: Vec::new(),
+        }
+    }
+
+    /// `SELECT` of a row.
+    pub fn read_row(&mut self, table: &str, key: impl Display) -> Vec<ParcheckLock> {
+        match self.isolation {
+            Isolation::ReadCommitted | Isolation::RepeatableRead => Vec::new(),
+            Isolation::Serializable => vec![ParcheckLock::AcquireShared {
+                scope: self.hold(table, key),
+            }],
+        }
+    }
+
+    /// `SELECT ... FOR UPDATE` of a row.
+    pub fn select_for_update(&mut self, table: &str, key: impl Display) -> Vec<ParcheckLock> {
+        self.lock_exclusive(table, key)
+    }
+
+    /// `UPDATE` or `DELETE` of a row.
+    pub fn update_row(&mut self, table: &str, key: impl Display) -> Vec<ParcheckLock> {
+        self.lock_exclusive(table, key)
+    }
+
+    /// `INSERT` of a row with unique `key`: concurrent inserts of the same key wait until this
+    /// transaction finishes.
+    pub fn insert_unique(&mut self, table: &str, key: impl Display) -> Vec<ParcheckLock> {
+        self.lock_exclusive(table, key)
+    }
+
+    /// Releases all locks held by the transaction.
+    pub fn commit(&mut self) -> Vec<ParcheckLock> {
+        self.held
+            .drain(..)
+            .map(|scope| ParcheckLock::Release { scope })
+            .collect()
+    }
+
+    /// Same as [`Transaction::commit`].
+    pub fn rollback(&mut self) -> Vec<ParcheckLock> {
+        self.commit()
+    }
+
+    fn lock_exclusive(&mut self, table: &str, key: impl Display) -> Vec<ParcheckLock> {
+        vec![ParcheckLock::AcquireExclusive {
+            scope: self.hold(table, key),
+        }]
+    }
+
+    fn hold(&mut self, table: &str, key: impl Display) -> String {
+        let scope = crate::scope!(table, key);
+        if !self.held.contains(&scope) {
+            self.held.push(scope.clone());
+        }
+        scope
+    }
+}
@@ -1,26 +1,140 @@
 #![doc = include_str!("../README.md")]
 
+#[cfg(all(feature = "enable", feature = "forbid-release", not(debug_assertions)))]
+compile_error!(
+    "parcheck: `enable` feature is active in a release build (disallowed by `forbid-release` feature)"
+);
+
 #[cfg(feature = "enable")]
 mod enabled;
 
 #[cfg(not(feature = "enable"))]
 mod disabled;
 
+pub mod isolation;
+pub mod scope;
+pub mod sync;
+
 #[cfg(feature = "enable")]
 #[doc(hidden)]
 pub mod private {
     pub use super::enabled::{
-        operation::{operation, OperationMetadata},
+        operation::{operation, serializable_operation, timeout_operation, unique_operation},
         task::task,
     };
 }
 
+/// Measurement of parcheck's own overhead.
+#[cfg(feature = "enable")]
+pub mod bench {
+    pub use super::enabled::bench::Overhead;
+}
+
+/// Helpers for checking that queue consumers process every job exactly once.
+#[cfg(feature = "enable")]
+pub mod exactly_once {
+    pub use super::enabled::exactly_once::{check, JobQueue};
+}
+
+/// Helpers for checking that tasks tolerate duplicate execution.
 #[cfg(feature = "enable")]
-pub use enabled::runner::{runner, Runner, Trace};
+pub mod idempotency {
+    pub use super::enabled::idempotency::check;
+}
+
+/// Helpers for checking transactional-outbox and saga scenarios.
+#[cfg(feature = "enable")]
+pub mod outbox {
+    pub use super::enabled::outbox::{check, Outbox};
+}
+
+#[cfg(all(feature = "enable", feature = "interactive"))]
+pub use enabled::interactive::{Candidate, ChooseStep};
+
+#[cfg(feature = "enable")]
+pub use enabled::{
+    breakpoint::{wait_for_debugger, Breakpoint},
+    comparison::{ComparedSchedule, Comparison, Variant},
+    exploration::{Exploration, Schedule, ScheduleError},
+    explored_tree::{ExploredTree, NodeStatus, TreeNode},
+    failure_class::{ClassifiedFailures, FailureAction, FailureClass},
+    lock_model::{LockModel, SharedExclusive},
+    operation::{Cost, OperationMetadata},
+    random::RandomSource,
+    report::{
+        DetectionEstimate, PrunedAlternatives, Reduction, Report, Reproduction, ScheduleFamily,
+        ScheduleSpace, ScopeStats, SlowSchedule, SlowStep, UnreachableBranches,
+    },
+    reporter::Reporter,
+    runner::{
+        case_runner, runner, OperationEvent, Runner, RunnerMatrix, ScenarioError, StepContext,
+    },
+    strategy::Strategy,
+    stream::{StepEvent, StepStream},
+    structure::{ScheduleEnumeration, Structure},
+    task::{task_with, TaskRegistration},
+    trace::{Cancellation, ParseTraceError, StepCandidate, Trace, TraceStep},
+    uncontrolled::{set_uncontrolled_policy, UncontrolledPolicy},
+    view::{BlockedLock, ControllerView, LockMode, TaskStatus},
+};
 
 #[derive(Clone, Debug)]
 pub enum ParcheckLock {
-    AcquireShared { scope: String },
-    AcquireExclusive { scope: String },
-    Release { scope: String },
+    AcquireShared {
+        scope: String,
+    },
+    AcquireExclusive {
+        scope: String,
+    },
+    /// Acquires the scope in a custom mode, which conflicts with other locks according to the
+    /// runner's `LockModel` (with the default model it conflicts like an exclusive lock).
+    Acquire {
+        scope: String,
+        mode: &'static str,
+    },
+    Release {
+        scope: String,
+    },
+}
+
+/// Error returned by an operation declared with `timeout` (see [`operation!`]) in schedules where
+/// parcheck makes it time out instead of executing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimedOut;
+
+impl std::fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("operation timed out")
+    }
 }
+
+impl std::error::Error for TimedOut {}
+
+/// Error returned by an operation declared with `unique` key (see [`operation!`]) in schedules
+/// where parcheck makes it fail because another task already executed an operation with the same
+/// key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UniqueViolation;
+
+impl std::fmt::Display for UniqueViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("unique constraint violated")
+    }
+}
+
+impl std::error::Error for UniqueViolation {}
+
+/// Error returned by an operation declared as `serializable` (see [`operation!`]), typically a
+/// transaction commit, in schedules where parcheck makes it fail with a retryable serialization
+/// failure (`SQLSTATE 40001`). The task is expected to retry the transaction: an iteration where the
+/// task finishes without executing the operation again fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SerializationFailure;
+
+impl std::fmt::Display for SerializationFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("could not serialize access due to concurrent update")
+    }
+}
+
+impl std::error::Error for SerializationFailure {}
@@ -0,0 +1,185 @@
+//! Canonical lock scope names derived from typed keys, so that every operation locking the same
+//! entity names its scope the same way.
+//!
+//! [`scope!`](crate::scope!) joins a name and key components with `:`, escaping `:` and `\` in
+//! components, so that different keys never produce the same scope (e.g. `("a:b", "c")` and
+//! `("a", "b:c")`). Key types implement [`LockScope`] to build their locks in one place.
+//!
+//! ```
+//! use parcheck::{scope::LockScope, ParcheckLock};
+//!
+//! struct JobId(u64);
+//!
+//! impl LockScope for JobId {
+//!     fn lock_scope(&self) -> String {
+//!         parcheck::scope!("job", self.0)
+//!     }
+//! }
+//!
+//! assert_eq!(JobId(42).lock_scope(), "job:42");
+//! let locks = vec![JobId(42).exclusive(), JobId(42).release()];
+//! # let _ = locks;
+//! ```
+//!
+//! Scopes that don't need a name at all can be locked with [`TypedLock`], which takes any
+//! hashable key (e.g. `(TableId, RowId)`) instead of a string.
+
+use std::{
+    fmt::{Debug, Display},
+    hash::Hash,
+};
+
+use crate::ParcheckLock;
+
+/// Key of a [`TypedLock`] scope: any hashable type. Scopes are named by their `Debug`
+/// representation in reports and errors, except for `String` and `&str` keys, which are named
+/// as is.
+pub trait ScopeKey: Hash + Eq + Debug + Send + Sync + 'static {}
+
+impl<T: Hash + Eq + Debug + Send + Sync + 'static> ScopeKey for T {}
+
+/// Lock on a scope identified by a typed key, e.g. `(TableId, RowId)`, compared without
+/// converting it to a string. Operations take it in place of [`ParcheckLock`] (all locks of an
+/// operation have the same type).
+///
+/// Scopes are equal only if their keys have the same type (so lock an entity with keys of one type
+/// everywhere), except that `&str` keys are the same as `String` keys: `TypedLock` with a `&str`
+/// key conflicts with a [`ParcheckLock`] on a scope with the same text.
+#[derive(Clone, Debug)]
+pub enum TypedLock<S> {
+    AcquireShared {
+        scope: S,
+    },
+    AcquireExclusive {
+        scope: S,
+    },
+    /// Acquires the scope in a custom mode, see [`ParcheckLock::Acquire`].
+    Acquire {
+        scope: S,
+        mode: &'static str,
+    },
+    Release {
+        scope: S,
+    },
+}
+
+impl<S> TypedLock<S> {
+    pub fn scope(&self) -> &S {
+        match self {
+            Self::AcquireShared { scope }
+            | Self::AcquireExclusive { scope }
+            | Self::Acquire { scope, .. }
+            | Self::Release { scope } => scope,
+        }
+    }
+
+    /// Same lock on a scope with a different key.
+    pub fn map_scope<T>(self, f: impl FnOnce(S) -> T) -> TypedLock<T> {
+        match self {
+            Self::AcquireShared { scope } => TypedLock::AcquireShared { scope: f(scope) },
+            Self::AcquireExclusive { scope } => TypedLock::AcquireExclusive { scope: f(scope) },
+            Self::Acquire { scope, mode } => TypedLock::Acquire {
+                scope: f(scope),
+                mode,
+            },
+            Self::Release { scope } => TypedLock::Release { scope: f(scope) },
+        }
+    }
+}
+
+/// Lock taken by operations: [`ParcheckLock`] (scope with a `String` key) or [`TypedLock`].
+pub trait OperationLock {
+    type Key: ScopeKey;
+
+    fn into_typed(self) -> TypedLock<Self::Key>;
+}
+
+impl OperationLock for ParcheckLock {
+    type Key = String;
+
+    fn into_typed(self) -> TypedLock<String> {
+        match self {
+            Self::AcquireShared { scope } => TypedLock::AcquireShared { scope },
+            Self::AcquireExclusive { scope } => TypedLock::AcquireExclusive { scope },
+            Self::Acquire { scope, mode } => TypedLock::Acquire { scope, mode },
+            Self::Release { scope } => TypedLock::Release { scope },
+        }
+    }
+}
+
+impl<S: ScopeKey> OperationLock for TypedLock<S> {
+    type Key = S;
+
+    fn into_typed(self) -> Self {
+        self
+    }
+}
+
+/// Entity that operations lock, e.g. a row or a job.
+pub trait LockScope {
+    /// Scope name of the entity, equal for equal entities. Usually built with
+    /// [`scope!`](crate::scope!).
+    fn lock_scope(&self) -> String;
+
+    fn shared(&self) -> ParcheckLock {
+        ParcheckLock::AcquireShared {
+            scope: self.lock_scope(),
+        }
+    }
+
+    fn exclusive(&self) -> ParcheckLock {
+        ParcheckLock::AcquireExclusive {
+            scope: self.lock_scope(),
+        }
+    }
+
+    fn release(&self) -> ParcheckLock {
+        ParcheckLock::Release {
+            scope: self.lock_scope(),
+        }
+    }
+}
+
+impl LockScope for str {
+    fn lock_scope(&self) -> String {
+        self.to_owned()
+    }
+}
+
+impl LockScope for String {
+    fn lock_scope(&self) -> String {
+        self.clone()
+    }
+}
+
+impl<T: LockScope + ?Sized> LockScope for &T {
+    fn lock_scope(&self) -> String {
+        (**self).lock_scope()
+    }
+}
+
+/// Canonical scope name: `name:key1:key2...`, see [`scope!`](crate::scope!).
+#[macro_export]
+macro_rules! scope {
+    ($name:expr $(, $key:expr)* $(,)?) => {
+        $crate::scope::canonical(&[&$name as &dyn ::std::fmt::Display $(, &$key)*])
+    };
+}
+
+#[doc(hidden)]
+#[must_use]
+pub fn canonical(components: &[&dyn Display]) -> String {
+    let mut scope = String::new();
+    for (idx, component) in components.iter().enumerate() {
+        if idx > 0 {
+            scope.push(':');
+        }
+        for c in component.to_string().chars() {
+            if matches!(c, ':' | '\\') {
+                scope.push('\\');
+            }
+            scope.push(c);
+        }
+    }
+    scope
+}
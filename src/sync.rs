@@ -0,0 +1,114 @@
+//! Mutexes for short critical sections in async code, whose locking is visible to the scheduler.
+//!
+//! Every critical section is an operation named `lock` that holds the mutex's scope exclusively
+//! until it finishes, so other tasks' operations are interleaved around it and conflict with
+//! [`ParcheckLock`]s on the same scope.
+//!
+//! ```
+//! # async fn example() {
+//! use parcheck::sync::Mutex;
+//!
+//! let counter = Mutex::new("counter", 0);
+//! let value = counter.with_lock(|counter| {
+//!     *counter += 1;
+//!     *counter
+//! }).await;
+//! # assert_eq!(value, 1);
+//! # }
+//! ```
+
+use crate::ParcheckLock;
+
+/// [`std::sync::Mutex`] locked in an operation.
+#[derive(Debug, Default)]
+pub struct Mutex<T> {
+    scope: String,
+    inner: std::sync::Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub fn new(scope: impl Into<String>, value: T) -> Self {
+        Self {
+            scope: scope.into(),
+            inner: std::sync::Mutex::new(value),
+        }
+    }
+
+    /// Scope locked by critical sections.
+    #[must_use]
+    pub fn scope(&self) -> &str {
+        &self.scope
+    }
+
+    /// Runs `f` with the locked value in a `lock` operation. Panics if the mutex is poisoned.
+    pub async fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        crate::operation!("lock", locks(&self.scope), {
+            async { f(&mut self.inner.lock().unwrap()) }
+        })
+        .await
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut().unwrap()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner().unwrap()
+    }
+}
+
+/// Mutexes of the `parking_lot` crate (with `parking_lot` feature).
+#[cfg(feature = "parking_lot")]
+pub mod parking_lot {
+    use super::locks;
+
+    /// [`parking_lot::Mutex`](::parking_lot::Mutex) locked in an operation.
+    #[derive(Debug, Default)]
+    pub struct Mutex<T> {
+        scope: String,
+        inner: ::parking_lot::Mutex<T>,
+    }
+
+    impl<T> Mutex<T> {
+        pub fn new(scope: impl Into<String>, value: T) -> Self {
+            Self {
+                scope: scope.into(),
+                inner: ::parking_lot::Mutex::new(value),
+            }
+        }
+
+        /// Scope locked by critical sections.
+        #[must_use]
+        pub fn scope(&self) -> &str {
+            &self.scope
+        }
+
+        /// Runs `f` with the locked value in a `lock` operation.
+        pub async fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+            crate::operation!("lock", locks(&self.scope), {
+                async { f(&mut self.inner.lock()) }
+            })
+            .await
+        }
+
+        pub fn get_mut(&mut self) -> &mut T {
+            self.inner.get_mut()
+        }
+
+        pub fn into_inner(self) -> T {
+            self.inner.into_inner()
+        }
+    }
+}
+
+/// Locks of a critical section: the scope is held only while the operation executes.
+fn locks(scope: &str) -> Vec<ParcheckLock> {
+    vec![
+        ParcheckLock::AcquireExclusive {
+            scope: scope.to_owned(),
+        },
+        ParcheckLock::Release {
+            scope: scope.to_owned(),
+        },
+    ]
+}
@@ -1,10 +1,10 @@
 use std::collections::HashMap;
 use std::future;
 use std::mem::size_of_val;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use parcheck::Trace;
+use parcheck::{OperationEvent, Trace};
 use tokio::sync::oneshot;
 
 struct Observer {
@@ -250,3 +250,3038 @@ async fn handles_cancellation() {
     .await
     .unwrap();
 }
+
+#[tokio::test]
+async fn runs_seed_schedules_first() {
+    let seed: Trace = "1:execute:b.append:1 > 1:execute:b.append:2 > 1:execute:b.append:3"
+        .parse()
+        .unwrap();
+
+    let traces = parcheck::runner()
+        .seed_schedules([seed])
+        .max_iterations(0)
+        .run_with_state(["execute:a", "execute:b"], Vec::new(), |mut traces| async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+            traces.push(obs.take_trace());
+            traces
+        })
+        .await;
+
+    assert_eq!(traces, ["bbbaaa"]);
+}
+
+#[tokio::test]
+async fn passes_resource_through_iteration() {
+    let finished = Mutex::new(Vec::new());
+
+    parcheck::runner()
+        .run_with_resource(
+            ["execute:a", "execute:b"],
+            || async { Observer::new() },
+            |obs| async move {
+                tokio::join!(obs.execute("a"), obs.execute("b"));
+                obs
+            },
+            |obs| {
+                finished.lock().unwrap().push(obs.take_trace());
+                async {}
+            },
+        )
+        .await;
+
+    let finished = finished.into_inner().unwrap();
+    assert_eq!(finished.len(), 20);
+    assert!(finished.iter().all(|trace| trace.len() == 6));
+}
+
+#[tokio::test]
+async fn runs_matrix_of_configurations() {
+    let report = parcheck::Runner::matrix([
+        ("quick", parcheck::runner().max_iterations(3)),
+        ("exhaustive", parcheck::runner()),
+    ])
+    .run(["execute:a", "execute:b"], || async {
+        let obs = Observer::new();
+        tokio::join!(obs.execute("a"), obs.execute("b"));
+    })
+    .await;
+
+    assert_eq!(report.iterations(), 23);
+    assert!(report.exhausted());
+}
+
+#[tokio::test]
+async fn continues_matrix_exploration_after_known_failures() {
+    let runner =
+        || parcheck::runner().classify_failure(|_, _| parcheck::FailureClass::known("stale read"));
+    let report = parcheck::Runner::matrix([("first", runner()), ("second", runner())])
+        .run(["execute:a", "execute:b"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+            if obs.take_trace().starts_with('b') {
+                panic!("stale read");
+            }
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 40);
+    assert_eq!(report.failure_classes()[0].failures(), 20);
+}
+
+#[tokio::test]
+async fn exposes_operation_metadata_in_hooks() {
+    let steps = Arc::new(Mutex::new(Vec::new()));
+
+    parcheck::runner()
+        .max_iterations(1)
+        .before_step_with_context(Box::new({
+            let steps = Arc::clone(&steps);
+            move |ctx| {
+                let metadata = ctx.step().metadata().unwrap();
+                steps.lock().unwrap().push((
+                    ctx.index(),
+                    ctx.step().task_name().to_owned(),
+                    metadata.name(),
+                    metadata.file(),
+                ));
+                Box::pin(async {})
+            }
+        }))
+        .run(["metadata"], || async {
+            parcheck::task!("metadata", {
+                async {
+                    parcheck::operation!("first", { async {} }).await;
+                    parcheck::operation!("second", { async {} }).await;
+                }
+            })
+            .await;
+        })
+        .await;
+
+    let file = "tests/examples/basic.rs";
+    assert_eq!(
+        *steps.lock().unwrap(),
+        [
+            (0, "metadata".to_owned(), "first", file),
+            (1, "metadata".to_owned(), "second", file),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn calls_step_hooks_without_context() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+
+    parcheck::runner()
+        .max_iterations(1)
+        .before_step(Box::new({
+            let calls = Arc::clone(&calls);
+            move || {
+                calls.lock().unwrap().push("before");
+                Box::pin(async {})
+            }
+        }))
+        .after_step(Box::new({
+            let calls = Arc::clone(&calls);
+            move || {
+                calls.lock().unwrap().push("after");
+                Box::pin(async {})
+            }
+        }))
+        .run(["no_context"], || async {
+            parcheck::task!("no_context", {
+                async {
+                    parcheck::operation!("first", { async {} }).await;
+                    parcheck::operation!("second", { async {} }).await;
+                }
+            })
+            .await;
+        })
+        .await;
+
+    assert_eq!(
+        *calls.lock().unwrap(),
+        ["before", "after", "before", "after"]
+    );
+}
+
+#[test]
+fn parsed_trace_has_steps_without_metadata() {
+    let trace: Trace = "0:task.first > 1:other.second".parse().unwrap();
+
+    let steps = trace.steps();
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[1].task_id(), Some(1));
+    assert_eq!(steps[1].task_name(), "other");
+    assert_eq!(steps[1].operation_name(), "second");
+    assert!(steps[1].metadata().is_none());
+
+    let trace: Trace = "task.first".parse().unwrap();
+    assert_eq!(trace.steps()[0].task_id(), None);
+}
+
+#[test]
+fn escapes_separators_in_names_of_trace_steps() {
+    let trace = parcheck::Structure::new()
+        .task(r"7:job.run!now\", ["op.x:y!z > w"])
+        .schedules()
+        .next()
+        .unwrap();
+    let text = trace.to_string();
+    assert_eq!(text, r"0:7\:job\.run\!now\\.op\.x\:y\!z \> w");
+
+    let parsed: Trace = text.parse().unwrap();
+    assert_eq!(parsed, trace);
+    assert_eq!(parsed.steps()[0].task_name(), r"7:job.run!now\");
+    assert_eq!(parsed.steps()[0].operation_name(), "op.x:y!z > w");
+
+    // an escaped ':' doesn't separate a task id
+    let trace: Trace = r"12\:30.op!timeout".parse().unwrap();
+    assert_eq!(trace.steps()[0].task_id(), None);
+    assert_eq!(trace.steps()[0].task_name(), "12:30");
+    assert_eq!(trace.to_string(), r"12\:30.op!timeout");
+
+    let empty: Trace = "".parse().unwrap();
+    assert!(empty.steps().is_empty());
+    assert_eq!(empty.to_string(), "");
+
+    assert!(r"task.op\".parse::<Trace>().is_err());
+}
+
+#[cfg(feature = "backtrace")]
+#[tokio::test]
+async fn captures_operation_backtraces() {
+    async fn helper() {
+        parcheck::operation!("helper", { async {} }).await;
+    }
+
+    let backtraces = Arc::new(Mutex::new(Vec::new()));
+
+    parcheck::runner()
+        .before_step_with_context(Box::new({
+            let backtraces = Arc::clone(&backtraces);
+            move |ctx| {
+                backtraces
+                    .lock()
+                    .unwrap()
+                    .push(ctx.step().backtrace().unwrap().to_owned());
+                Box::pin(async {})
+            }
+        }))
+        .run(["backtraces"], || async {
+            parcheck::task!("backtraces", { helper() }).await;
+        })
+        .await;
+
+    let backtraces = backtraces.lock().unwrap();
+    assert!(backtraces[0].contains("::helper::"));
+    assert!(!backtraces[0].contains("tokio::"));
+}
+
+#[tokio::test]
+async fn adaptive_strategy_stops_when_saturated() {
+    let report = parcheck::runner()
+        .strategy(parcheck::Strategy::Adaptive {
+            window: 5,
+            min_discovery_rate: 100.0,
+        })
+        .run(["execute:a", "execute:b"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 5);
+    assert!(!report.exhausted());
+
+    let report = parcheck::runner()
+        .strategy(parcheck::Strategy::Adaptive {
+            window: 5,
+            min_discovery_rate: 0.0,
+        })
+        .run(["execute:a", "execute:b"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 20);
+    assert!(report.exhausted());
+}
+
+#[tokio::test]
+#[should_panic(
+    expected = "task 'slow_worker' exceeded deadline of 10ms (last operation: 'before_sleep'"
+)]
+async fn reports_task_exceeding_deadline() {
+    parcheck::runner()
+        .task_deadline("slow_worker", Duration::from_millis(10))
+        .run(["slow_worker", "fast_worker"], || async {
+            tokio::join!(
+                parcheck::task!("slow_worker", {
+                    async {
+                        parcheck::operation!("before_sleep", { async {} }).await;
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        parcheck::operation!("after_sleep", { async {} }).await;
+                    }
+                }),
+                parcheck::task!("fast_worker", {
+                    async {
+                        parcheck::operation!("op", { async {} }).await;
+                    }
+                }),
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn calls_hooks_for_named_operations() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    parcheck::runner()
+        .on_operation(
+            "append:2",
+            Box::new({
+                let events = Arc::clone(&events);
+                move |event, ctx| {
+                    events
+                        .lock()
+                        .unwrap()
+                        .push((event, ctx.step().task_name().to_owned()));
+                    Box::pin(async {})
+                }
+            }),
+        )
+        .run(["execute:a"], || async {
+            Observer::new().execute("a").await;
+        })
+        .await;
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            (OperationEvent::Granted, "execute:a".to_owned()),
+            (OperationEvent::Finished, "execute:a".to_owned()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn injects_step_latency() {
+    let started = std::time::Instant::now();
+    parcheck::runner()
+        .max_iterations(1)
+        .step_latency(Box::new(|ctx| {
+            Duration::from_millis(if ctx.step().operation_name() == "append:2" {
+                100
+            } else {
+                10
+            })
+        }))
+        .run(["execute:a"], || async {
+            Observer::new().execute("a").await;
+        })
+        .await;
+
+    assert!(started.elapsed() >= Duration::from_millis(120));
+}
+
+#[tokio::test]
+async fn isolates_iterations_on_separate_runtimes() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+    struct Guard;
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            LIVE.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    let report = parcheck::runner()
+        .run_isolated(["execute:a", "execute:b"], || async {
+            assert_eq!(
+                LIVE.load(Ordering::SeqCst),
+                0,
+                "leaked from previous iteration"
+            );
+
+            LIVE.fetch_add(1, Ordering::SeqCst);
+            let guard = Guard;
+            tokio::spawn(async move {
+                let _guard = guard;
+                future::pending::<()>().await;
+            });
+
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 20);
+    assert_eq!(LIVE.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn isolated_iterations_dont_block_calling_runtime() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static TICKS: AtomicUsize = AtomicUsize::new(0);
+
+    let ticker = tokio::spawn(async {
+        loop {
+            TICKS.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    });
+
+    parcheck::runner()
+        .run_isolated(["execute:a"], || async {
+            let start = TICKS.load(Ordering::SeqCst);
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while TICKS.load(Ordering::SeqCst) == start {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            })
+            .await
+            .expect("calling runtime is blocked");
+
+            Observer::new().execute("a").await;
+        })
+        .await;
+
+    ticker.abort();
+}
+
+#[tokio::test]
+#[should_panic(expected = "task 'leaky' is still alive after iteration finished")]
+async fn detects_leaked_operations() {
+    let leaked = Mutex::new(Vec::new());
+
+    parcheck::runner()
+        .run(["leaky"], || async {
+            parcheck::task!("leaky", {
+                async {
+                    let mut op = Box::pin(parcheck::operation!("leaked", { async {} }));
+                    // poll once so that operation requests a permit, then leak it
+                    tokio::select! {
+                        biased;
+                        () = &mut op => unreachable!(),
+                        () = async {} => {}
+                    }
+                    leaked.lock().unwrap().push(op);
+                }
+            })
+            .await;
+        })
+        .await;
+}
+
+#[test]
+fn generates_replay_test() {
+    let trace: Trace = "1:worker:b.claim > 0:worker:a.claim > 1:worker:b.ack"
+        .parse()
+        .unwrap();
+
+    assert_eq!(
+        trace.to_replay_test("double_claim"),
+        r#"#[tokio::test]
+async fn double_claim() {
+    let trace: parcheck::Trace = "1:worker\\:b.claim > 0:worker\\:a.claim > 1:worker\\:b.ack".parse().unwrap();
+
+    parcheck::runner()
+        .replay(trace)
+        .run(["worker:a", "worker:b"], || async {
+            todo!("scenario body")
+        })
+        .await;
+}
+"#
+    );
+}
+
+#[cfg(feature = "tracing")]
+#[tokio::test]
+async fn links_operation_spans_to_scheduling_decisions() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use tracing::{span, Event, Metadata, Subscriber};
+
+    #[derive(Default)]
+    struct Recorder {
+        next_id: AtomicU64,
+        names: Mutex<HashMap<u64, &'static str>>,
+        follows_from: Mutex<Vec<(&'static str, &'static str)>>,
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+            self.names
+                .lock()
+                .unwrap()
+                .insert(id, span.metadata().name());
+            span::Id::from_u64(id)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, span: &span::Id, follows: &span::Id) {
+            let names = self.names.lock().unwrap();
+            self.follows_from
+                .lock()
+                .unwrap()
+                .push((names[&span.into_u64()], names[&follows.into_u64()]));
+        }
+
+        fn event(&self, _: &Event<'_>) {}
+
+        fn enter(&self, _: &span::Id) {}
+
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    let recorder = Arc::new(Recorder::default());
+    let _guard = tracing::subscriber::set_default(Arc::clone(&recorder));
+
+    parcheck::runner()
+        .max_iterations(1)
+        .run(["execute:a"], || async {
+            Observer::new().execute("a").await;
+        })
+        .await;
+
+    assert_eq!(
+        *recorder.follows_from.lock().unwrap(),
+        [
+            ("parcheck.operation", "parcheck.step"),
+            ("parcheck.step", "parcheck.step"),
+            ("parcheck.operation", "parcheck.step"),
+            ("parcheck.step", "parcheck.step"),
+            ("parcheck.operation", "parcheck.step"),
+        ]
+    );
+}
+
+#[cfg(feature = "otel")]
+#[tokio::test]
+async fn exports_iterations_as_otel_traces() {
+    use std::{fs, thread};
+
+    let path = std::env::temp_dir().join(format!(
+        "parcheck-otel-{}-exports_iterations.jsonl",
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+
+    let report = parcheck::runner()
+        .otel_export(&path)
+        .run(["execute:a", "execute:b"], || async {
+            let observer = Observer::new();
+            tokio::join!(observer.execute("a"), observer.execute("b"));
+        })
+        .await;
+
+    let exported = fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = exported.lines().collect();
+    assert_eq!(lines.len() as u64, report.iterations());
+    for line in &lines {
+        assert!(line.starts_with(r#"{"resourceSpans":"#));
+        assert_eq!(line.matches(r#""name":"parcheck.iteration""#).count(), 1);
+        assert_eq!(line.matches(r#""name":"append:1""#).count(), 2);
+        assert_eq!(line.matches(r#""name":"append:2""#).count(), 2);
+        assert_eq!(line.matches(r#""name":"append:3""#).count(), 2);
+        assert_eq!(line.matches(r#""key":"code.filepath""#).count(), 6);
+        assert!(!line.contains(r#""status""#));
+    }
+
+    let failing_path = path.with_extension("failing.jsonl");
+    let _ = fs::remove_file(&failing_path);
+    let result = thread::spawn({
+        let failing_path = failing_path.clone();
+        move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(async {
+                parcheck::runner()
+                    .otel_export(&failing_path)
+                    .on_panic(Box::new(|_| {}))
+                    .run(["execute:a"], || async {
+                        Observer::new().execute("a").await;
+                        panic!("broken \"invariant\"");
+                    })
+                    .await;
+            });
+        }
+    })
+    .join();
+    assert!(result.is_err());
+
+    let exported = fs::read_to_string(&failing_path).unwrap();
+    assert_eq!(exported.lines().count(), 1);
+    assert!(exported.contains(r#""status":{"code":2,"message":"broken \"invariant\""}"#));
+
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(&failing_path);
+}
+
+#[cfg(feature = "otel")]
+#[tokio::test]
+async fn exports_otlp_json_lines() {
+    use std::fs;
+
+    // ids and timestamps differ between runs
+    fn normalize(line: &str) -> String {
+        let mut line = line.to_owned();
+        for key in [
+            "traceId",
+            "spanId",
+            "parentSpanId",
+            "startTimeUnixNano",
+            "endTimeUnixNano",
+        ] {
+            let pattern = format!(r#""{key}":""#);
+            let mut from = 0;
+            while let Some(start) = line[from..].find(&pattern) {
+                let start = from + start + pattern.len();
+                let end = start + line[start..].find('"').unwrap();
+                line.replace_range(start..end, "_");
+                from = start;
+            }
+        }
+        line
+    }
+
+    let path = std::env::temp_dir().join(format!(
+        "parcheck-otel-{}-exports_otlp_json_lines.jsonl",
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+
+    let line = line!() + 7;
+    parcheck::runner()
+        .max_iterations(1)
+        .otel_export(&path)
+        .run(["otlp"], || async {
+            parcheck::task!("otlp", {
+                async {
+                    parcheck::operation!("write", { async {} }).await;
+                }
+            })
+            .await;
+        })
+        .await;
+
+    let exported = fs::read_to_string(&path).unwrap();
+    let lines: Vec<String> = exported.lines().map(normalize).collect();
+    let expected = format!(
+        concat!(
+            r#"{{"resourceSpans":[{{"resource":{{"attributes":[{{"key":"service.name","value":{{"stringValue":"parcheck"}}}}]}},"scopeSpans":[{{"scope":{{"name":"parcheck"}},"spans":["#,
+            r#"{{"traceId":"_","spanId":"_","name":"parcheck.iteration","kind":1,"startTimeUnixNano":"_","endTimeUnixNano":"_","attributes":["#,
+            r#"{{"key":"parcheck.iteration","value":{{"intValue":"0"}}}},"#,
+            r#"{{"key":"parcheck.trace","value":{{"stringValue":"0:otlp.write"}}}}]}},"#,
+            r#"{{"traceId":"_","spanId":"_","parentSpanId":"_","name":"write","kind":1,"startTimeUnixNano":"_","endTimeUnixNano":"_","attributes":["#,
+            r#"{{"key":"parcheck.step","value":{{"intValue":"0"}}}},"#,
+            r#"{{"key":"parcheck.task.id","value":{{"intValue":"0"}}}},"#,
+            r#"{{"key":"parcheck.task.name","value":{{"stringValue":"otlp"}}}},"#,
+            r#"{{"key":"parcheck.operation.name","value":{{"stringValue":"write"}}}},"#,
+            r#"{{"key":"code.filepath","value":{{"stringValue":"tests/examples/basic.rs"}}}},"#,
+            r#"{{"key":"code.lineno","value":{{"intValue":"{line}"}}}}]}}"#,
+            r#"]}}]}}]}}"#,
+        ),
+        line = line,
+    );
+    assert_eq!(lines, [expected]);
+
+    let _ = fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn pct_strategy_estimates_detection_probability() {
+    let report = parcheck::runner()
+        .strategy(parcheck::Strategy::Pct { depth: 2 })
+        .max_iterations(100)
+        .run(["execute:a", "execute:b"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 100);
+    assert!(!report.exhausted());
+
+    let detection = report.detection().unwrap();
+    assert_eq!(detection.tasks(), 2);
+    assert_eq!(detection.steps(), 6);
+    assert!((detection.per_iteration() - 1.0 / 12.0).abs() < f64::EPSILON);
+    assert_eq!(
+        detection.to_string(),
+        "with PCT depth 2, 100 iterations, bugs requiring ≤2 ordering constraints are found with ≥99.98% probability (2 tasks, 6 steps)"
+    );
+}
+
+#[tokio::test]
+async fn pct_strategy_runs_default_number_of_iterations() {
+    let report = parcheck::runner()
+        .strategy(parcheck::Strategy::Pct { depth: 2 })
+        .run(["execute:a", "execute:b"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 1000);
+    assert!(report
+        .detection()
+        .unwrap()
+        .to_string()
+        .contains("1000 iterations"));
+}
+
+#[tokio::test]
+async fn clusters_schedules_into_families() {
+    let report = parcheck::runner()
+        .run(["execute:a", "execute:b"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+        })
+        .await;
+
+    let families = report.families();
+    assert_eq!(families.len(), 10);
+    assert_eq!(
+        families
+            .iter()
+            .map(|family| family.iterations())
+            .sum::<u64>(),
+        report.iterations()
+    );
+    assert!(families.iter().all(|family| family.failures() == 0));
+
+    let sequential = families
+        .iter()
+        .find(|family| family.task_order() == ["execute:a", "execute:b"])
+        .unwrap();
+    assert_eq!(
+        sequential.to_string(),
+        "execute:a > execute:b (1 schedules)"
+    );
+    assert_eq!(
+        sequential.example().to_string(),
+        "0:execute\\:a.append\\:1 > 0:execute\\:a.append\\:2 > 0:execute\\:a.append\\:3 > 1:execute\\:b.append\\:1 > 1:execute\\:b.append\\:2 > 1:execute\\:b.append\\:3"
+    );
+}
+
+#[tokio::test]
+async fn explores_operation_timeouts() {
+    use parcheck::TimedOut;
+
+    async fn send_with_retry(attempts: usize) -> Result<usize, TimedOut> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match parcheck::operation!("send", timeout, { async { attempt } }).await {
+                Ok(attempt) => return Ok(attempt),
+                Err(TimedOut) if attempt < attempts => continue,
+                Err(TimedOut) => return Err(TimedOut),
+            }
+        }
+    }
+
+    let outcomes = Arc::new(Mutex::new(Vec::new()));
+    let report = parcheck::runner()
+        .run(["sender"], || {
+            let outcomes = Arc::clone(&outcomes);
+            async move {
+                let outcome = parcheck::task!("sender", { send_with_retry(2) }).await;
+                outcomes.lock().unwrap().push(outcome);
+            }
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 3);
+    assert!(report.exhausted());
+    let mut outcomes = outcomes.lock().unwrap().clone();
+    outcomes.sort_by_key(|outcome| outcome.ok());
+    assert_eq!(outcomes, [Err(TimedOut), Ok(1), Ok(2)]);
+
+    let outcome = Arc::new(Mutex::new(None));
+    parcheck::runner()
+        .replay("0:sender.send!timeout".parse().unwrap())
+        .before_step_with_context(Box::new(|ctx| {
+            assert_eq!(
+                ctx.step().injected_fault(),
+                (ctx.index() == 0).then_some("timeout")
+            );
+            Box::pin(async {})
+        }))
+        .run(["sender"], || {
+            let outcome = Arc::clone(&outcome);
+            async move {
+                *outcome.lock().unwrap() =
+                    Some(parcheck::task!("sender", { send_with_retry(1) }).await);
+            }
+        })
+        .await;
+    assert_eq!(*outcome.lock().unwrap(), Some(Err(TimedOut)));
+}
+
+#[tokio::test]
+async fn checks_idempotent_tasks() {
+    use std::collections::HashSet;
+
+    #[derive(Default)]
+    struct Db {
+        rows: Mutex<HashSet<String>>,
+    }
+
+    let report = parcheck::idempotency::check(
+        ["insert:a", "insert:b"],
+        |db: Arc<Db>, name| {
+            let key = name.to_string();
+            async move {
+                let exists = parcheck::operation!("select", {
+                    async { db.rows.lock().unwrap().contains(&key) }
+                })
+                .await;
+                if !exists {
+                    parcheck::operation!("insert", {
+                        async { db.rows.lock().unwrap().insert(key) }
+                    })
+                    .await;
+                }
+            }
+        },
+        |db| {
+            let mut rows: Vec<String> = db.rows.lock().unwrap().iter().cloned().collect();
+            rows.sort();
+            rows
+        },
+    )
+    .await;
+
+    assert!(report.exhausted());
+    assert!(report.iterations() > 0);
+}
+
+#[tokio::test]
+#[should_panic(
+    expected = "effect of executing task 'increment' twice differs from executing it once"
+)]
+async fn detects_non_idempotent_tasks() {
+    #[derive(Default)]
+    struct Counter {
+        value: Mutex<u64>,
+    }
+
+    parcheck::idempotency::check(
+        ["increment"],
+        |counter: Arc<Counter>, _| async move {
+            parcheck::operation!("increment", {
+                async { *counter.value.lock().unwrap() += 1 }
+            })
+            .await;
+        },
+        |counter| *counter.value.lock().unwrap(),
+    )
+    .await;
+}
+
+#[derive(Default)]
+struct TestQueue {
+    racy_claim: bool,
+    pending: Mutex<Vec<String>>,
+    in_flight: Mutex<HashMap<usize, String>>,
+}
+
+impl parcheck::exactly_once::JobQueue for TestQueue {
+    fn claim(&self, worker: usize) -> futures_util::future::BoxFuture<'_, Option<String>> {
+        Box::pin(async move {
+            if self.racy_claim {
+                let job = parcheck::operation!("peek", {
+                    async { self.pending.lock().unwrap().last().cloned() }
+                })
+                .await?;
+                parcheck::operation!("take", {
+                    async {
+                        self.pending
+                            .lock()
+                            .unwrap()
+                            .retain(|pending| *pending != job)
+                    }
+                })
+                .await;
+                return Some(job);
+            }
+
+            parcheck::operation!("claim", {
+                async {
+                    let mut in_flight = self.in_flight.lock().unwrap();
+                    if let Some(job) = in_flight.get(&worker) {
+                        return Some(job.clone());
+                    }
+                    let job = self.pending.lock().unwrap().pop()?;
+                    in_flight.insert(worker, job.clone());
+                    Some(job)
+                }
+            })
+            .await
+        })
+    }
+
+    fn ack<'a>(&'a self, worker: usize, _job: &'a str) -> futures_util::future::BoxFuture<'a, ()> {
+        Box::pin(async move {
+            parcheck::operation!("ack", {
+                async {
+                    self.in_flight.lock().unwrap().remove(&worker);
+                }
+            })
+            .await;
+        })
+    }
+}
+
+#[tokio::test]
+async fn checks_exactly_once_job_processing() {
+    let report = parcheck::exactly_once::check(2, ["job:1"], |jobs| TestQueue {
+        pending: Mutex::new(jobs.to_vec()),
+        ..TestQueue::default()
+    })
+    .await;
+
+    assert!(report.exhausted());
+    assert!(report.iterations() > 1);
+
+    // Same task names can't be used by tests running in parallel, so this runs after the check
+    // above (on a separate thread to catch the panic).
+    let error = std::thread::spawn(|| {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            parcheck::exactly_once::check(2, ["job:1"], |jobs| TestQueue {
+                racy_claim: true,
+                pending: Mutex::new(jobs.to_vec()),
+                ..TestQueue::default()
+            })
+            .await;
+        });
+    })
+    .join()
+    .unwrap_err();
+    let message = error.downcast_ref::<String>().unwrap();
+    assert!(
+        message == "job 'job:1' was processed 2 times" || message == "job 'job:1' was lost",
+        "{message}"
+    );
+}
+
+#[tokio::test]
+async fn checks_outbox_invariants() {
+    use parcheck::outbox::Outbox;
+
+    async fn place_order(outbox: &Outbox, id: &str) {
+        parcheck::task!(format!("order:{id}"), {
+            async {
+                outbox.commit(id, async {}).await;
+                outbox.publish(id, async {}).await;
+            }
+        })
+        .await;
+    }
+
+    let report = parcheck::outbox::check(["order:1", "order:2"], |outbox| async move {
+        tokio::join!(place_order(&outbox, "1"), place_order(&outbox, "2"));
+    })
+    .await;
+    assert!(report.exhausted());
+
+    let error = std::thread::spawn(|| {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            parcheck::outbox::check(["order:3", "relay:3"], |outbox| async move {
+                tokio::join!(
+                    parcheck::task!("order:3", { async { outbox.commit("3", async {}).await } }),
+                    parcheck::task!("relay:3", { async { outbox.publish("3", async {}).await } }),
+                );
+            })
+            .await;
+        });
+    })
+    .join()
+    .unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<String>().unwrap(),
+        "message '3' was published before it was committed"
+    );
+}
+
+#[tokio::test]
+#[should_panic(expected = "message 'shipment' was committed but never published")]
+async fn detects_unpublished_outbox_messages() {
+    parcheck::outbox::check(["ship", "relay"], |outbox| async move {
+        let stored = Mutex::new(false);
+        tokio::join!(
+            parcheck::task!("ship", {
+                async {
+                    outbox
+                        .commit("shipment", async { *stored.lock().unwrap() = true })
+                        .await
+                }
+            }),
+            parcheck::task!("relay", {
+                async {
+                    // polls the outbox table only once
+                    let stored =
+                        parcheck::operation!("poll", { async { *stored.lock().unwrap() } }).await;
+                    if stored {
+                        outbox.publish("shipment", async {}).await;
+                    }
+                }
+            }),
+        );
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn explores_unique_violations() {
+    use parcheck::UniqueViolation;
+
+    async fn signup(name: &str, outcomes: &Mutex<Vec<String>>) {
+        parcheck::task!(format!("signup:{name}"), {
+            async {
+                let inserted =
+                    parcheck::operation!("insert", unique = "user:alice", { async {} }).await;
+                if let Err(UniqueViolation) = inserted {
+                    parcheck::operation!("update", { async {} }).await;
+                    outcomes.lock().unwrap().push(format!("{name} updated"));
+                }
+            }
+        })
+        .await;
+    }
+
+    let outcomes = Arc::new(Mutex::new(Vec::new()));
+    let report = parcheck::runner()
+        .run(["signup:a", "signup:b"], || {
+            let outcomes = Arc::clone(&outcomes);
+            async move {
+                tokio::join!(signup("a", &outcomes), signup("b", &outcomes));
+            }
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 4);
+    assert!(report.exhausted());
+    let mut outcomes = outcomes.lock().unwrap().clone();
+    outcomes.sort();
+    assert_eq!(outcomes, ["a updated", "b updated"]);
+}
+
+#[tokio::test]
+async fn explores_serialization_failures() {
+    use parcheck::SerializationFailure;
+
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+    let report = parcheck::runner()
+        .run(["transfer:retried"], || {
+            let attempts = Arc::clone(&attempts);
+            async move {
+                parcheck::task!("transfer:retried", {
+                    async {
+                        let mut attempt = 0;
+                        loop {
+                            attempt += 1;
+                            parcheck::operation!("update", { async {} }).await;
+                            match parcheck::operation!("commit", serializable, { async {} }).await {
+                                Ok(()) => break,
+                                Err(SerializationFailure) => continue,
+                            }
+                        }
+                        attempts.lock().unwrap().push(attempt);
+                    }
+                })
+                .await;
+            }
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 2);
+    let mut attempts = attempts.lock().unwrap().clone();
+    attempts.sort_unstable();
+    assert_eq!(attempts, [1, 2]);
+}
+
+#[tokio::test]
+#[should_panic(expected = "finished without retrying operation 'commit'")]
+async fn detects_missing_retries_of_serialization_failures() {
+    parcheck::runner()
+        .run(["transfer:not_retried"], || async {
+            parcheck::task!("transfer:not_retried", {
+                async {
+                    parcheck::operation!("update", { async {} }).await;
+                    let _ = parcheck::operation!("commit", serializable, { async {} }).await;
+                }
+            })
+            .await;
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn exposes_controller_state_in_hooks() {
+    use parcheck::{LockMode, ParcheckLock, TaskStatus};
+
+    async fn lead(name: &str) {
+        parcheck::task!(format!("lead:{name}"), {
+            async {
+                parcheck::operation!(
+                    "acquire",
+                    vec![ParcheckLock::AcquireExclusive {
+                        scope: "leader".into()
+                    }],
+                    { async {} }
+                )
+                .await;
+                parcheck::operation!(
+                    "release",
+                    vec![ParcheckLock::Release {
+                        scope: "leader".into()
+                    }],
+                    { async {} }
+                )
+                .await;
+            }
+        })
+        .await;
+    }
+
+    let blocked = Arc::new(Mutex::new(Vec::new()));
+    parcheck::runner()
+        .after_step_with_context(Box::new({
+            let blocked = Arc::clone(&blocked);
+            move |ctx| {
+                let view = ctx.controller();
+                let holders = view.holders("leader");
+                assert!(holders.len() <= 1, "several leaders: {holders:?}");
+                if let [(leader, mode)] = holders[..] {
+                    assert_eq!(mode, LockMode::Exclusive);
+                    assert!(matches!(
+                        view.task(leader),
+                        Some(TaskStatus::Running | TaskStatus::Waiting(_))
+                    ));
+                }
+                for (task, blockers) in view.blocked() {
+                    blocked
+                        .lock()
+                        .unwrap()
+                        .push(format!("{task} < {}", blockers.join(",")));
+                }
+                Box::pin(async {})
+            }
+        }))
+        .run(["lead:a", "lead:b"], || async {
+            tokio::join!(lead("a"), lead("b"));
+        })
+        .await;
+
+    let mut blocked = blocked.lock().unwrap().clone();
+    blocked.sort();
+    blocked.dedup();
+    assert_eq!(blocked, ["lead:a < lead:b", "lead:b < lead:a"]);
+}
+
+#[tokio::test]
+async fn reports_lock_usage_per_scope() {
+    use parcheck::ParcheckLock;
+
+    async fn read(name: &str) {
+        parcheck::task!(format!("scopes:{name}"), {
+            async {
+                let scope = String::from("cache");
+                parcheck::operation!(
+                    "lock",
+                    vec![ParcheckLock::AcquireShared {
+                        scope: scope.clone()
+                    }],
+                    { async {} }
+                )
+                .await;
+                parcheck::operation!("unlock", vec![ParcheckLock::Release { scope }], {
+                    async {}
+                })
+                .await;
+            }
+        })
+        .await;
+    }
+
+    async fn write() {
+        parcheck::task!("scopes:writer", {
+            async {
+                let scope = String::from("cache");
+                parcheck::operation!(
+                    "write",
+                    vec![
+                        ParcheckLock::AcquireExclusive {
+                            scope: scope.clone()
+                        },
+                        ParcheckLock::Release { scope }
+                    ],
+                    { async {} }
+                )
+                .await;
+            }
+        })
+        .await;
+    }
+
+    let report = parcheck::runner()
+        .run(["scopes:a", "scopes:b", "scopes:writer"], || async {
+            tokio::join!(read("a"), read("b"), write());
+        })
+        .await;
+
+    let cache = report.scope("cache").unwrap();
+    assert_eq!(cache.max_shared_holders(), 2);
+    assert_eq!(cache.exclusive_acquisitions(), report.iterations());
+    assert_eq!(report.scopes().count(), 1);
+    assert!(report.scope("other").is_none());
+}
+
+#[tokio::test]
+async fn explores_lock_acquisition_order() {
+    use parcheck::ParcheckLock;
+
+    fn lock(scope: &str) -> Vec<ParcheckLock> {
+        vec![ParcheckLock::AcquireExclusive {
+            scope: scope.into(),
+        }]
+    }
+
+    fn unlock(scope: &str) -> Vec<ParcheckLock> {
+        vec![ParcheckLock::Release {
+            scope: scope.into(),
+        }]
+    }
+
+    async fn scenario(events: Arc<Mutex<Vec<&'static str>>>) -> Vec<&'static str> {
+        let event = |name| {
+            let events = Arc::clone(&events);
+            async move { events.lock().unwrap().push(name) }
+        };
+        tokio::join!(
+            parcheck::task!("fair:holder", {
+                async {
+                    parcheck::operation!("acquire", lock("rw"), { event("acquire") }).await;
+                    parcheck::operation!("release", unlock("rw"), { event("release") }).await;
+                }
+            }),
+            parcheck::task!("fair:a", {
+                async {
+                    let mut locks = lock("rw");
+                    locks.extend(unlock("rw"));
+                    parcheck::operation!("take", locks, { event("a") }).await;
+                }
+            }),
+            parcheck::task!("fair:b", {
+                async {
+                    parcheck::operation!("prepare", { event("prepare") }).await;
+                    let mut locks = lock("rw");
+                    locks.extend(unlock("rw"));
+                    parcheck::operation!("take", locks, { event("b") }).await;
+                }
+            }),
+        );
+        let events = events.lock().unwrap().clone();
+        events
+    }
+
+    for fair in [false, true] {
+        let schedules = Arc::new(Mutex::new(Vec::new()));
+        parcheck::runner()
+            .fair_locks(fair)
+            .run(["fair:holder", "fair:a", "fair:b"], || {
+                let schedules = Arc::clone(&schedules);
+                async move {
+                    let events = scenario(Arc::new(Mutex::new(Vec::new()))).await;
+                    schedules.lock().unwrap().push(events);
+                }
+            })
+            .await;
+
+        let schedules = schedules.lock().unwrap();
+        let explored = |events: &[&str]| schedules.iter().any(|schedule| schedule == events);
+        // `b` got blocked after `a`
+        assert!(explored(&["acquire", "prepare", "release", "a", "b"]));
+        assert_eq!(
+            explored(&["acquire", "prepare", "release", "b", "a"]),
+            !fair
+        );
+        // `a` and `b` got blocked at the same time
+        assert!(explored(&["prepare", "acquire", "release", "a", "b"]));
+        assert!(explored(&["prepare", "acquire", "release", "b", "a"]));
+    }
+}
+
+#[tokio::test]
+async fn accepts_shared_task_names() {
+    let names: Vec<Arc<str>> = vec!["shared:a".into(), "shared:b".into()];
+    let report = parcheck::runner()
+        .run(&names, || async {
+            tokio::join!(
+                parcheck::task!("shared:a", {
+                    async { parcheck::operation!("op", { async {} }).await }
+                }),
+                parcheck::task!("shared:b", {
+                    async { parcheck::operation!("op", { async {} }).await }
+                }),
+            );
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 2);
+}
+
+#[tokio::test]
+async fn allows_repeated_operations_with_unique_names() {
+    parcheck::runner()
+        .unique_op_names(true)
+        .run(["op_names:repeated"], || async {
+            parcheck::task!("op_names:repeated", {
+                async {
+                    for _ in 0..2 {
+                        parcheck::operation!("op", { async {} }).await;
+                    }
+                    parcheck::operation!("other", { async {} }).await;
+                }
+            })
+            .await;
+        })
+        .await;
+}
+
+#[tokio::test]
+#[should_panic(expected = "have the same name 'op'")]
+async fn detects_duplicate_operation_names() {
+    parcheck::runner()
+        .unique_op_names(true)
+        .run(["op_names:duplicate"], || async {
+            parcheck::task!("op_names:duplicate", {
+                async {
+                    parcheck::operation!("op", { async {} }).await;
+                    parcheck::operation!("op", { async {} }).await;
+                }
+            })
+            .await;
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn warns_about_tasks_without_operations() {
+    let report = parcheck::runner()
+        .run(["idle:lenient"], || async {
+            parcheck::task!("idle:lenient", { async {} }).await;
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 1);
+}
+
+#[tokio::test]
+#[should_panic(expected = "task 'idle:strict' finished without executing any operations")]
+async fn fails_on_tasks_without_operations_in_strict_mode() {
+    parcheck::runner()
+        .strict(true)
+        .run(["idle:strict", "idle:busy"], || async {
+            tokio::join!(
+                parcheck::task!("idle:strict", { async {} }),
+                parcheck::task!("idle:busy", {
+                    async { parcheck::operation!("op", { async {} }).await }
+                }),
+            );
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn streams_step_events() {
+    use futures_util::StreamExt;
+    use parcheck::{ParcheckLock, StepEvent};
+
+    let mut stream = parcheck::runner().stream(["stream:a", "stream:b"], || async {
+        tokio::join!(
+            parcheck::task!("stream:a", {
+                async {
+                    parcheck::operation!(
+                        "write",
+                        vec![
+                            ParcheckLock::AcquireExclusive {
+                                scope: "row".into()
+                            },
+                            ParcheckLock::Release {
+                                scope: "row".into()
+                            },
+                        ],
+                        { async {} }
+                    )
+                    .await;
+                }
+            }),
+            parcheck::task!("stream:b", {
+                async { parcheck::operation!("read", { async {} }).await }
+            }),
+        );
+    });
+
+    let mut events = Vec::new();
+    while let Some(event) = stream.next().await {
+        events.push(match event {
+            StepEvent::IterationStarted { iteration } => format!("start {iteration}"),
+            StepEvent::Step {
+                iteration,
+                step,
+                locks,
+            } => format!(
+                "{iteration}: {}.{} ({} locks)",
+                step.task_name(),
+                step.operation_name(),
+                locks.len()
+            ),
+            StepEvent::IterationFinished { iteration, failed } => {
+                format!("finish {iteration} (failed: {failed})")
+            }
+        });
+    }
+
+    assert_eq!(stream.report().unwrap().iterations(), 2);
+    let mut orders = Vec::new();
+    for (iteration, events) in events.chunks(4).enumerate() {
+        assert_eq!(events[0], format!("start {iteration}"));
+        assert_eq!(events[3], format!("finish {iteration} (failed: false)"));
+        orders.push(
+            events[1..3]
+                .join(", ")
+                .replace(&format!("{iteration}: "), ""),
+        );
+    }
+    orders.sort();
+    assert_eq!(
+        orders,
+        [
+            "stream:a.write (2 locks), stream:b.read (0 locks)",
+            "stream:b.read (0 locks), stream:a.write (2 locks)",
+        ]
+    );
+}
+
+#[cfg(feature = "interactive")]
+#[tokio::test]
+async fn chooses_steps_interactively() {
+    let offered = Arc::new(Mutex::new(Vec::new()));
+    let executed = Arc::new(Mutex::new(Vec::new()));
+
+    let report = parcheck::runner()
+        .interactive(Box::new({
+            let offered = Arc::clone(&offered);
+            move |candidates| {
+                offered.lock().unwrap().push(
+                    candidates
+                        .iter()
+                        .map(|candidate| {
+                            format!("{}.{}", candidate.task_name(), candidate.operation().name())
+                        })
+                        .collect::<Vec<_>>(),
+                );
+                candidates.len() - 1
+            }
+        }))
+        .run(["interactive:a", "interactive:b"], || {
+            let executed = Arc::clone(&executed);
+            async move {
+                let op = |name: &'static str| {
+                    let executed = Arc::clone(&executed);
+                    async move { executed.lock().unwrap().push(name) }
+                };
+                tokio::join!(
+                    parcheck::task!("interactive:a", {
+                        async { parcheck::operation!("first", { op("a") }).await }
+                    }),
+                    parcheck::task!("interactive:b", {
+                        async { parcheck::operation!("second", { op("b") }).await }
+                    }),
+                );
+            }
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 1);
+    assert_eq!(*executed.lock().unwrap(), ["b", "a"]);
+    assert_eq!(
+        *offered.lock().unwrap(),
+        [
+            vec!["interactive:a.first", "interactive:b.second"],
+            vec!["interactive:a.first"],
+        ]
+    );
+}
+
+#[cfg(feature = "interactive")]
+#[tokio::test]
+async fn executes_steps_without_alternatives_once_chosen_interactively() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+
+    parcheck::runner()
+        .interactive(Box::new({
+            let log = Arc::clone(&log);
+            move |candidates| {
+                let chosen = candidates[0].operation().name();
+                log.lock().unwrap().push(format!("choose {chosen}"));
+                0
+            }
+        }))
+        .run(["interactive:single"], || {
+            let log = Arc::clone(&log);
+            async move {
+                let op = |name: &'static str| {
+                    let log = Arc::clone(&log);
+                    async move { log.lock().unwrap().push(format!("execute {name}")) }
+                };
+                parcheck::task!("interactive:single", {
+                    async {
+                        parcheck::operation!("first", { op("first") }).await;
+                        parcheck::operation!("second", { op("second") }).await;
+                    }
+                })
+                .await;
+            }
+        })
+        .await;
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            "choose first",
+            "execute first",
+            "choose second",
+            "execute second"
+        ]
+    );
+}
+
+#[test]
+fn converts_trace_to_json_and_back() {
+    let trace: Trace = r#"0:"quoted", task.first > 1:other.second!timeout"#
+        .parse()
+        .unwrap();
+
+    let json = trace.to_json();
+    assert_eq!(
+        json,
+        r#"{"steps":[{"task_id":0,"task_name":"\"quoted\", task","operation":"first","fault":null,"file":null,"line":null,"duration_ns":null},{"task_id":1,"task_name":"other","operation":"second","fault":"timeout","file":null,"line":null,"duration_ns":null}]}"#
+    );
+    assert_eq!(
+        Trace::from_json(&json).unwrap().to_string(),
+        trace.to_string()
+    );
+    assert_eq!(
+        trace.to_jsonl(),
+        r#"{"task_id":0,"task_name":"\"quoted\", task","operation":"first","fault":null,"file":null,"line":null,"duration_ns":null}
+{"task_id":1,"task_name":"other","operation":"second","fault":"timeout","file":null,"line":null,"duration_ns":null}
+"#
+    );
+
+    assert!(Trace::from_json(r#"{"steps":[{"task_id":0}]}"#).is_err());
+    assert!(Trace::from_json("[").is_err());
+}
+
+#[test]
+fn converts_trace_to_csv() {
+    let trace: Trace = r#"0:"quoted", task.first > 1:other.second!timeout"#
+        .parse()
+        .unwrap();
+
+    assert_eq!(
+        trace.to_csv(),
+        "step,task_id,task_name,operation,fault,file,line,duration_ns\n\
+         0,0,\"\"\"quoted\"\", task\",first,,,,\n\
+         1,1,other,second,timeout,,,\n"
+    );
+}
+
+#[test]
+fn panics_on_uncontrolled_operation_if_configured() {
+    use std::process::Command;
+
+    if std::env::var_os("PARCHECK_UNCONTROLLED").is_some() {
+        // executed in a child process, policy is process-wide
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(parcheck::operation!("stray", { async {} }));
+        return;
+    }
+
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args([
+            "--exact",
+            "examples::basic::panics_on_uncontrolled_operation_if_configured",
+        ])
+        .env("PARCHECK_UNCONTROLLED", "panic")
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("parcheck operation 'stray' executed outside of a controlled task (at tests/examples/basic.rs:"),
+        "{stdout}"
+    );
+}
+
+#[tokio::test]
+async fn bounds_expensive_preemptions() {
+    async fn scenario() {
+        let task = |name: &'static str| {
+            parcheck::task!(name, {
+                async {
+                    parcheck::operation!("prepare", { async {} }).await;
+                    parcheck::operation!("io", cost = High, { async {} }).await;
+                }
+            })
+        };
+        tokio::join!(task("cost:a"), task("cost:b"));
+    }
+
+    let report = parcheck::runner().run(["cost:a", "cost:b"], scenario).await;
+    assert_eq!(report.iterations(), 6);
+
+    // switching to other task's "io" while the previous task can continue isn't explored
+    let report = parcheck::runner()
+        .max_expensive_preemptions(0)
+        .run(["cost:a", "cost:b"], scenario)
+        .await;
+    assert_eq!(report.iterations(), 4);
+    assert!(report.exhausted());
+}
+
+#[tokio::test]
+async fn prefers_cheap_operations() {
+    for _ in 0..5 {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        parcheck::runner()
+            .max_iterations(1)
+            .prefer_cheap_operations(true)
+            .run(["cheap:io", "cheap:mem"], || {
+                let order = Arc::clone(&order);
+                async move {
+                    let io = parcheck::task!("cheap:io", {
+                        parcheck::operation!("io", cost = High, {
+                            async { order.lock().unwrap().push("io") }
+                        })
+                    });
+                    let mem = parcheck::task!("cheap:mem", {
+                        parcheck::operation!("mem", cost = Low, {
+                            async { order.lock().unwrap().push("mem") }
+                        })
+                    });
+                    tokio::join!(io, mem);
+                }
+            })
+            .await;
+
+        assert_eq!(*order.lock().unwrap(), ["mem", "io"]);
+    }
+}
+
+#[tokio::test]
+async fn finds_minimal_iterations_reproducing_failure() {
+    let scenario = || async {
+        let obs = Observer::new();
+        tokio::join!(obs.execute("min_x"), obs.execute("min_y"));
+        assert_ne!(
+            obs.take_trace(),
+            "min_xmin_ymin_ymin_xmin_xmin_y",
+            "rare interleaving"
+        );
+    };
+
+    let reproduction = parcheck::runner()
+        .find_minimal_iterations(0..4, 1000, ["execute:min_x", "execute:min_y"], scenario)
+        .await
+        .unwrap();
+    assert_eq!(
+        reproduction.trace().to_string(),
+        "0:execute\\:min_x.append\\:1 > 1:execute\\:min_y.append\\:1 > 1:execute\\:min_y.append\\:2 > \
+         0:execute\\:min_x.append\\:2 > 0:execute\\:min_x.append\\:3 > 1:execute\\:min_y.append\\:3"
+    );
+    assert!(reproduction.to_string().starts_with(&format!(
+        "PARCHECK_SEED={} PARCHECK_MAX_ITERATIONS={} ",
+        reproduction.seed(),
+        reproduction.iterations()
+    )));
+
+    let seed = reproduction.seed();
+    let reproduce = |iterations| {
+        std::thread::spawn(move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(
+                parcheck::runner()
+                    .seed(seed)
+                    .max_iterations(iterations)
+                    .on_panic(Box::new(|_| {}))
+                    .run(["execute:min_x", "execute:min_y"], scenario),
+            )
+        })
+        .join()
+    };
+    assert!(reproduce(reproduction.iterations()).is_err());
+    let report = reproduce(reproduction.iterations() - 1).unwrap();
+    assert_eq!(report.iterations(), reproduction.iterations() - 1);
+
+    let none = parcheck::runner()
+        .find_minimal_iterations(0..1, 20, ["execute:min_x", "execute:min_y"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("min_x"), obs.execute("min_y"));
+        })
+        .await;
+    assert!(none.is_none());
+}
+
+#[tokio::test]
+async fn stops_searching_for_minimal_iterations_at_cap() {
+    let executions = Arc::new(Mutex::new(0));
+    let none = parcheck::runner()
+        .strategy(parcheck::Strategy::Pct { depth: 2 })
+        .find_minimal_iterations(0..2, 100, ["execute:cap_x", "execute:cap_y"], || {
+            let executions = Arc::clone(&executions);
+            async move {
+                *executions.lock().unwrap() += 1;
+                let obs = Observer::new();
+                tokio::join!(obs.execute("cap_x"), obs.execute("cap_y"));
+            }
+        })
+        .await;
+
+    assert!(none.is_none());
+    // 1 + 2 + 4 + ... + 64 + 100 iterations for each seed
+    assert_eq!(*executions.lock().unwrap(), 2 * 227);
+}
+
+#[tokio::test]
+async fn searches_minimal_iterations_without_persisted_state_or_classification() {
+    let path = |kind: &str| {
+        std::env::temp_dir().join(format!(
+            "parcheck-{kind}-{}-searches_minimal_iterations.txt",
+            std::process::id()
+        ))
+    };
+    let (checkpoint, history) = (path("checkpoint"), path("history"));
+
+    let reproduction = parcheck::runner()
+        .checkpoint(&checkpoint, Duration::ZERO)
+        .failure_history(&history)
+        .classify_failure(|_, _| parcheck::FailureClass::known("rare interleaving"))
+        .find_minimal_iterations(
+            0..1,
+            1000,
+            ["execute:search_x", "execute:search_y"],
+            || async {
+                let obs = Observer::new();
+                tokio::join!(obs.execute("search_x"), obs.execute("search_y"));
+                assert_ne!(
+                    obs.take_trace(),
+                    "search_xsearch_ysearch_ysearch_xsearch_xsearch_y",
+                    "rare interleaving"
+                );
+            },
+        )
+        .await;
+
+    assert!(reproduction.is_some());
+    assert!(!checkpoint.exists());
+    assert!(!history.exists());
+}
+
+#[tokio::test]
+async fn enumerates_schedules_without_executing_them() {
+    let executions = Arc::new(Mutex::new(0));
+    let enumeration = parcheck::runner()
+        .enumerate_schedules(3, ["execute:enum_x", "execute:enum_y"], || {
+            let executions = Arc::clone(&executions);
+            async move {
+                *executions.lock().unwrap() += 1;
+                let obs = Observer::new();
+                tokio::join!(obs.execute("enum_x"), obs.execute("enum_y"));
+            }
+        })
+        .await;
+
+    assert_eq!(*executions.lock().unwrap(), 1);
+    assert_eq!(enumeration.count(), 20);
+    assert_eq!(
+        enumeration.structure().to_string(),
+        "0:execute:enum_x: append:1 > append:2 > append:3\n\
+         1:execute:enum_y: append:1 > append:2 > append:3\n"
+    );
+    let schedules = enumeration
+        .schedules()
+        .iter()
+        .map(|trace| trace.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        schedules,
+        [
+            "0:execute\\:enum_x.append\\:1 > 0:execute\\:enum_x.append\\:2 > 0:execute\\:enum_x.append\\:3 > \
+             1:execute\\:enum_y.append\\:1 > 1:execute\\:enum_y.append\\:2 > 1:execute\\:enum_y.append\\:3",
+            "0:execute\\:enum_x.append\\:1 > 0:execute\\:enum_x.append\\:2 > 1:execute\\:enum_y.append\\:1 > \
+             0:execute\\:enum_x.append\\:3 > 1:execute\\:enum_y.append\\:2 > 1:execute\\:enum_y.append\\:3",
+            "0:execute\\:enum_x.append\\:1 > 0:execute\\:enum_x.append\\:2 > 1:execute\\:enum_y.append\\:1 > \
+             1:execute\\:enum_y.append\\:2 > 0:execute\\:enum_x.append\\:3 > 1:execute\\:enum_y.append\\:3",
+        ]
+    );
+}
+
+#[test]
+fn enumerates_schedules_of_a_model() {
+    let model = parcheck::Structure::new()
+        .task("writer", ["write"])
+        .task("reader", ["read:1", "read:2"]);
+
+    assert_eq!(model.count_schedules(), 3);
+    assert_eq!(
+        model
+            .schedules()
+            .map(|trace| trace.to_string())
+            .collect::<Vec<_>>(),
+        [
+            r"0:writer.write > 1:reader.read\:1 > 1:reader.read\:2",
+            r"1:reader.read\:1 > 0:writer.write > 1:reader.read\:2",
+            r"1:reader.read\:1 > 1:reader.read\:2 > 0:writer.write",
+        ]
+    );
+
+    let wide = (0..64).fold(parcheck::Structure::new(), |model, i| {
+        model.task(format!("task:{i}"), ["a", "b", "c", "d"])
+    });
+    assert_eq!(wide.count_schedules(), u128::MAX);
+}
+
+#[tokio::test]
+async fn captures_structure_of_scenario() {
+    let report = parcheck::runner()
+        .run(["execute:struct_x", "execute:struct_y"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("struct_x"), obs.execute("struct_y"));
+        })
+        .await;
+
+    let structure = report.structure().unwrap();
+    assert_eq!(
+        structure.tasks().collect::<Vec<_>>(),
+        [
+            (
+                "execute:struct_x",
+                &["append:1", "append:2", "append:3"].map(String::from)[..]
+            ),
+            (
+                "execute:struct_y",
+                &["append:1", "append:2", "append:3"].map(String::from)[..]
+            ),
+        ]
+    );
+    assert!(report.exhausted());
+    assert_eq!(structure.count_schedules(), u128::from(report.iterations()));
+
+    parcheck::runner()
+        .expect_structure(structure.clone())
+        .run(["execute:struct_x", "execute:struct_y"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("struct_x"), obs.execute("struct_y"));
+        })
+        .await;
+}
+
+#[tokio::test]
+#[should_panic(
+    expected = "structure of the scenario changed\nexpected:\n0:drift: read > write\n\
+                           actual:\n0:drift: read > check > write\n"
+)]
+async fn detects_changed_structure() {
+    parcheck::runner()
+        .expect_structure(parcheck::Structure::new().task("drift", ["read", "write"]))
+        .run(["drift"], || async {
+            parcheck::task!("drift", {
+                async {
+                    parcheck::operation!("read", { async {} }).await;
+                    parcheck::operation!("check", { async {} }).await;
+                    parcheck::operation!("write", { async {} }).await;
+                }
+            })
+            .await;
+        })
+        .await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn explores_same_schedules_on_multi_threaded_runtime() {
+    let scenario = || async {
+        let tasks = ["threaded:a", "threaded:b", "threaded:c"].map(|name| {
+            tokio::spawn(parcheck::task!(name, {
+                async {
+                    parcheck::operation!("first", { async {} }).await;
+                    parcheck::operation!("second", { async {} }).await;
+                }
+            }))
+        });
+        for task in tasks {
+            task.await.unwrap();
+        }
+    };
+
+    let report = parcheck::runner()
+        .run(["threaded:a", "threaded:b", "threaded:c"], scenario)
+        .await;
+    assert!(report.exhausted());
+    assert_eq!(report.iterations(), 90);
+
+    let trace = report.families()[0].example();
+    let replayed = parcheck::runner()
+        .replay(trace.clone())
+        .run(["threaded:a", "threaded:b", "threaded:c"], scenario)
+        .await;
+    assert_eq!(
+        replayed.families()[0].example().to_string(),
+        trace.to_string()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn executes_operations_one_at_a_time_on_multi_threaded_runtime() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let executing = Arc::new(AtomicUsize::new(0));
+    let report = parcheck::runner()
+        .run(["parallel:a", "parallel:b"], || {
+            let executing = Arc::clone(&executing);
+            async move {
+                let tasks = ["parallel:a", "parallel:b"].map(|name| {
+                    let executing = Arc::clone(&executing);
+                    tokio::spawn(async move {
+                        parcheck::task!(name, {
+                            async {
+                                for _ in 0..2 {
+                                    parcheck::operation!("op", {
+                                        async {
+                                            assert_eq!(executing.fetch_add(1, Ordering::SeqCst), 0);
+                                            tokio::task::yield_now().await;
+                                            tokio::task::spawn_blocking(|| {}).await.unwrap();
+                                            executing.fetch_sub(1, Ordering::SeqCst);
+                                        }
+                                    })
+                                    .await;
+                                }
+                            }
+                        })
+                        .await;
+                    })
+                });
+                for task in tasks {
+                    task.await.unwrap();
+                }
+            }
+        })
+        .await;
+    assert!(report.exhausted());
+    assert_eq!(report.iterations(), 6);
+}
+
+#[tokio::test]
+#[should_panic(expected = "of task 'overlap:b' executed in parallel with operation 'slow'")]
+async fn detects_overlapping_operations() {
+    // `fast` is requested while `slow` waits for a permit, so `slow` executes without one
+    parcheck::runner()
+        .detect_overlapping_operations(true)
+        .replay("1:overlap:b.b".parse().unwrap())
+        .run(["overlap:a", "overlap:b"], || async {
+            let (tx, rx) = oneshot::channel();
+            let a = parcheck::task!("overlap:a", {
+                async {
+                    tokio::join!(
+                        parcheck::operation!("slow", {
+                            async { tokio::time::sleep(Duration::from_millis(50)).await }
+                        }),
+                        async {
+                            rx.await.unwrap();
+                            parcheck::operation!("fast", { async {} }).await;
+                        },
+                    );
+                }
+            });
+            let b = parcheck::task!("overlap:b", {
+                async {
+                    parcheck::operation!("b", {
+                        async {
+                            tx.send(()).unwrap();
+                            tokio::time::sleep(Duration::from_millis(20)).await;
+                        }
+                    })
+                    .await;
+                }
+            });
+            tokio::join!(a, b);
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn binds_tasks_to_handles() {
+    let report = parcheck::runner()
+        .run_with_handles(["twin", "twin"], |handles| async move {
+            let [first, second]: [parcheck::TaskRegistration; 2] = handles.try_into().unwrap();
+            assert_eq!(first.name(), "twin");
+
+            let execute = |handle| {
+                parcheck::task_with(handle, async {
+                    parcheck::operation!("a", { async {} }).await;
+                    parcheck::operation!("b", { async {} }).await;
+                })
+            };
+            // not controlled: tasks are only bound with handles
+            let named = parcheck::task!("twin", {
+                async {
+                    parcheck::operation!("uncontrolled", { async {} }).await;
+                }
+            });
+            tokio::join!(execute(first), execute(second), named);
+        })
+        .await;
+
+    assert!(report.exhausted());
+    assert_eq!(report.iterations(), 6);
+    assert_eq!(
+        report.structure().unwrap().to_string(),
+        "0:twin: a > b\n1:twin: a > b\n"
+    );
+}
+
+#[test]
+fn redirects_notes_to_reporter() {
+    let notes = Arc::new(Mutex::new(Vec::new()));
+    let result = std::thread::spawn({
+        let notes = Arc::clone(&notes);
+        move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(
+                parcheck::runner()
+                    .reporter(parcheck::Reporter::Custom(Box::new(move |note| {
+                        notes.lock().unwrap().push(note.to_owned());
+                    })))
+                    .run(["reporter:failing"], || async {
+                        parcheck::task!("reporter:failing", {
+                            async {
+                                parcheck::operation!("fail", { async { panic!("failed") } }).await;
+                            }
+                        })
+                        .await;
+                    }),
+            );
+        }
+    })
+    .join();
+
+    assert!(result.is_err());
+    assert_eq!(
+        notes.lock().unwrap()[..2],
+        [
+            "note: failed schedule belongs to family reporter:failing (1 schedules, 1 failed)",
+            "note: use `PARCHECK_REPLAY=\"0:reporter\\\\:failing.fail\"` to replay the same schedule",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn replays_steps_without_task_ids() {
+    let task = |name: &'static str| {
+        parcheck::task!(name, {
+            async {
+                parcheck::operation!("first", { async {} }).await;
+                parcheck::operation!("second", { async {} }).await;
+            }
+        })
+    };
+
+    let trace: Trace = "byname:b.first > byname:a.first > byname:b.second"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        trace.to_string(),
+        r"byname\:b.first > byname\:a.first > byname\:b.second"
+    );
+
+    let report = parcheck::runner()
+        .replay(trace)
+        .run(["byname:a", "byname:b"], || async {
+            tokio::join!(task("byname:a"), task("byname:b"));
+        })
+        .await;
+    assert_eq!(
+        report.families()[0].example().to_string(),
+        "1:byname\\:b.first > 0:byname\\:a.first > 1:byname\\:b.second > 0:byname\\:a.second"
+    );
+}
+
+#[tokio::test]
+async fn explores_schedules_step_by_step() {
+    use parcheck::Exploration;
+
+    let task = |name: &'static str| {
+        parcheck::task!(name, {
+            async {
+                parcheck::operation!("first", { async {} }).await;
+                parcheck::operation!("second", { async {} }).await;
+            }
+        })
+    };
+
+    let mut exploration = Exploration::new(["explore:a", "explore:b"]);
+    let mut traces = Vec::new();
+    while let Some(mut schedule) = exploration.next_schedule() {
+        let steps = async {
+            let mut steps = 0;
+            while schedule.step().await.unwrap().is_some() {
+                steps += 1;
+            }
+            steps
+        };
+        let (steps, ..) = tokio::join!(steps, task("explore:a"), task("explore:b"));
+        assert_eq!(steps, 4);
+        traces.push(schedule.finish().unwrap().to_string());
+    }
+
+    assert!(exploration.exhausted());
+    assert_eq!(exploration.report().iterations(), 6);
+    traces.sort();
+    traces.dedup();
+    assert_eq!(traces.len(), 6);
+}
+
+#[test]
+fn mentions_case_in_replay_note() {
+    let notes = Arc::new(Mutex::new(Vec::new()));
+    let result = std::thread::spawn({
+        let notes = Arc::clone(&notes);
+        move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(
+                parcheck::case_runner("large")
+                    .reporter(parcheck::Reporter::Custom(Box::new(move |note| {
+                        notes.lock().unwrap().push(note.to_owned());
+                    })))
+                    .run(["case:failing"], || async {
+                        parcheck::task!("case:failing", {
+                            async {
+                                parcheck::operation!("fail", { async { panic!("failed") } }).await;
+                            }
+                        })
+                        .await;
+                    }),
+            );
+        }
+    })
+    .join();
+
+    assert!(result.is_err());
+    assert_eq!(
+        notes.lock().unwrap()[1],
+        "note: use `PARCHECK_CASE=\"large\" PARCHECK_REPLAY=\"0:case\\\\:failing.fail\"` to replay the same schedule"
+    );
+}
+
+#[test]
+fn retries_failed_schedule() {
+    use std::process::Command;
+
+    let task = |name: &'static str| {
+        parcheck::task!(name, {
+            async {
+                parcheck::operation!("op", { async {} }).await;
+            }
+        })
+    };
+    let run = |fail: bool, tasks: [&'static str; 2]| {
+        let notes = Arc::new(Mutex::new(Vec::new()));
+        let result = std::thread::Builder::new()
+            .name(format!("retries_failed_schedule:{}", std::process::id()))
+            .spawn({
+                let notes = Arc::clone(&notes);
+                move || {
+                    tokio::runtime::Runtime::new().unwrap().block_on(
+                        parcheck::runner()
+                            .retry_same_schedule(true)
+                            .reporter(parcheck::Reporter::Custom(Box::new(move |note| {
+                                notes.lock().unwrap().push(note.to_owned());
+                            })))
+                            .run(tasks, || async move {
+                                tokio::join!(task(tasks[0]), task(tasks[1]));
+                                assert!(!fail, "failed");
+                            }),
+                    )
+                }
+            })
+            .unwrap()
+            .join();
+        let notes = notes.lock().unwrap().clone();
+        (result, notes)
+    };
+
+    if std::env::var_os("NEXTEST_RUN_ID").is_none() {
+        // schedules aren't kept outside of a nextest run
+        assert!(run(true, ["retry:a", "retry:b"]).0.is_err());
+        assert_eq!(
+            run(false, ["retry:a", "retry:b"]).0.unwrap().iterations(),
+            2
+        );
+
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "examples::basic::retries_failed_schedule"])
+            .env("NEXTEST", "1")
+            .env(
+                "NEXTEST_RUN_ID",
+                format!("retries_failed_schedule-{}", std::process::id()),
+            )
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+        return;
+    }
+
+    let (failed, notes) = run(true, ["retry:a", "retry:b"]);
+    assert!(failed.is_err());
+    // another scenario of the same test doesn't replay the schedule
+    let (other, _) = run(false, ["retry:c", "retry:d"]);
+    assert_eq!(other.unwrap().iterations(), 2);
+    let (retried, _) = run(false, ["retry:a", "retry:b"]);
+    let retried = retried.unwrap();
+    assert_eq!(retried.iterations(), 1);
+    let trace = retried.families()[0].example().to_string();
+    assert!(notes
+        .iter()
+        .any(|note| note.contains(&format!("{trace:?}"))));
+
+    let (explored, _) = run(false, ["retry:a", "retry:b"]);
+    assert_eq!(explored.unwrap().iterations(), 2);
+}
+
+#[tokio::test]
+async fn shallow_first_strategy_explores_shallow_schedules_first() {
+    let traces = Arc::new(Mutex::new(Vec::new()));
+    let report = parcheck::runner()
+        .strategy(parcheck::Strategy::ShallowFirst { depth: 1 })
+        .max_iterations(10)
+        .run(["execute:a", "execute:b"], || {
+            let traces = Arc::clone(&traces);
+            async move {
+                let obs = Observer::new();
+                tokio::join!(obs.execute("a"), obs.execute("b"));
+                traces.lock().unwrap().push(obs.take_trace());
+            }
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 10);
+    assert!(!report.exhausted());
+    let traces = traces.lock().unwrap().clone();
+    let mut first_steps = traces[..2]
+        .iter()
+        .map(|trace| &trace[..1])
+        .collect::<Vec<_>>();
+    first_steps.sort_unstable();
+    assert_eq!(first_steps, ["a", "b"]);
+
+    let report = parcheck::runner()
+        .strategy(parcheck::Strategy::ShallowFirst { depth: 6 })
+        .max_iterations(25)
+        .run(["execute:a", "execute:b"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 25);
+    assert!(report.exhausted());
+}
+
+#[tokio::test]
+async fn shallow_first_strategy_runs_default_number_of_iterations() {
+    let report = parcheck::runner()
+        .strategy(parcheck::Strategy::ShallowFirst { depth: 1 })
+        .run(["execute:a", "execute:b"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 1000);
+    assert!(!report.exhausted());
+}
+
+#[tokio::test]
+async fn measures_overhead() {
+    use std::time::Duration;
+
+    use parcheck::bench::Overhead;
+
+    let overhead = Overhead::measure(2, 2, 100).await;
+    // 6 schedules of 4 steps
+    assert_eq!(overhead.steps(), 24);
+    assert!(overhead.step() >= overhead.permit_round_trip());
+    assert!(!overhead.regressed(Duration::from_secs(1), 0.0));
+    assert!(overhead.to_string().ends_with(", 24 steps"));
+}
+
+#[tokio::test]
+async fn records_steps_of_last_unfinished_task() {
+    let ops = |name: &'static str, count: usize| {
+        parcheck::task!(name, {
+            async move {
+                for _ in 0..count {
+                    parcheck::operation!("tail", { async {} }).await;
+                }
+            }
+        })
+    };
+
+    let report = parcheck::runner()
+        .run(["tail:short", "tail:long"], || async {
+            tokio::join!(ops("tail:short", 1), ops("tail:long", 3));
+        })
+        .await;
+    assert_eq!(report.iterations(), 4);
+    assert!(report.exhausted());
+    for family in report.families() {
+        assert_eq!(family.example().steps().len(), 4);
+        let last = family.example().steps().last().unwrap();
+        assert_eq!(last.operation_name(), "tail");
+    }
+}
+
+#[tokio::test]
+async fn shares_operation_names_of_executed_steps() {
+    let names = Arc::new(Mutex::new(Vec::new()));
+    let report = parcheck::runner()
+        .before_step_with_context(Box::new({
+            let names = Arc::clone(&names);
+            move |ctx| {
+                let step = ctx.step();
+                // borrowed from operation metadata instead of being allocated for every step
+                assert_eq!(
+                    step.operation_name().as_ptr(),
+                    step.metadata().unwrap().name().as_ptr()
+                );
+                names
+                    .lock()
+                    .unwrap()
+                    .push(step.operation_name().as_ptr() as usize);
+                Box::pin(async {})
+            }
+        }))
+        .run(["shared:a", "shared:b"], || async {
+            let op = || parcheck::operation!("shared", { async {} });
+            tokio::join!(
+                parcheck::task!("shared:a", { op() }),
+                parcheck::task!("shared:b", { op() }),
+            );
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 2);
+    let mut names = names.lock().unwrap().clone();
+    assert_eq!(names.len(), 4);
+    names.dedup();
+    assert_eq!(names.len(), 1);
+}
+
+#[test]
+fn interns_names_of_trace_steps() {
+    let trace: parcheck::Trace = "0:intern:a.op > 1:intern:b.op > 0:intern:a.op"
+        .parse()
+        .unwrap();
+    let steps = trace.steps();
+    assert_eq!(steps[0].task_name().as_ptr(), steps[2].task_name().as_ptr());
+    assert_eq!(
+        steps[0].operation_name().as_ptr(),
+        steps[1].operation_name().as_ptr()
+    );
+
+    assert_eq!(trace, trace.to_string().parse().unwrap());
+    assert_eq!(trace, parcheck::Trace::from_json(&trace.to_json()).unwrap());
+    assert_ne!(trace, "0:intern:a.op > 1:intern:b.op".parse().unwrap());
+    assert_ne!(
+        trace,
+        "0:intern:a.op > 1:intern:b.op!timeout > 0:intern:a.op"
+            .parse()
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn draws_random_decisions_from_custom_source() {
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
+
+    use parcheck::RandomSource;
+
+    /// SplitMix64, counting values drawn from all of its splits.
+    struct SplitMix(u64, Arc<AtomicU64>);
+
+    impl RandomSource for SplitMix {
+        fn next_u64(&mut self) -> u64 {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+
+        fn split(&mut self) -> Box<dyn RandomSource> {
+            Box::new(SplitMix(self.next_u64(), Arc::clone(&self.1)))
+        }
+    }
+
+    let explore = |drawn: Arc<AtomicU64>| async move {
+        let report = parcheck::runner()
+            .rng(SplitMix(7, drawn))
+            .run(["custom_rng:a", "custom_rng:b"], || async {
+                let ops = |name: &'static str| {
+                    parcheck::task!(name, {
+                        async {
+                            parcheck::operation!("first", { async {} }).await;
+                            parcheck::operation!("second", { async {} }).await;
+                        }
+                    })
+                };
+                tokio::join!(ops("custom_rng:a"), ops("custom_rng:b"));
+            })
+            .await;
+        report
+            .families()
+            .iter()
+            .map(|family| family.example().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let drawn = Arc::new(AtomicU64::new(0));
+    let first = explore(Arc::clone(&drawn)).await;
+    assert_eq!(first.len(), 6);
+    assert!(drawn.load(Ordering::Relaxed) > 0);
+    assert_eq!(first, explore(Arc::new(AtomicU64::new(0))).await);
+}
+
+#[tokio::test]
+async fn exposes_explored_schedule_tree() {
+    use parcheck::NodeStatus;
+
+    let explore = |max_iterations: u64| async move {
+        parcheck::runner()
+            .max_iterations(max_iterations)
+            .run(["tree:a", "tree:b"], || async {
+                let ops = |name: &'static str| {
+                    parcheck::task!(name, {
+                        async {
+                            parcheck::operation!("first", { async {} }).await;
+                            parcheck::operation!("second", { async {} }).await;
+                        }
+                    })
+                };
+                tokio::join!(ops("tree:a"), ops("tree:b"));
+            })
+            .await
+    };
+
+    let report = explore(100).await;
+    let tree = report.schedule_tree().unwrap();
+    let root = tree.root();
+    assert_eq!(root.task(), None);
+    assert_eq!(root.status(), NodeStatus::Visited);
+    assert_eq!(root.children().count(), 2);
+    let b_first = root.child("tree:b").unwrap();
+    assert_eq!(b_first.operation(), Some("first"));
+    assert!(!b_first.injects_fault());
+    assert!(b_first.fully_explored());
+    let finished = b_first
+        .child("tree:b")
+        .and_then(|node| node.child("tree:b"))
+        .unwrap();
+    assert_eq!(
+        finished.status(),
+        NodeStatus::Unreachable {
+            reason: "task finished"
+        }
+    );
+
+    let report = explore(1).await;
+    let root = report.schedule_tree().unwrap().root();
+    assert!(!root.fully_explored());
+    assert_eq!(
+        root.children()
+            .filter(|child| child.status() == NodeStatus::Visited)
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn reports_slowest_steps_and_schedules() {
+    use std::time::Duration;
+
+    let report = parcheck::runner()
+        .run(["durations:fast", "durations:slow"], || async {
+            tokio::join!(
+                parcheck::task!("durations:fast", {
+                    async {
+                        parcheck::operation!("quick", { async {} }).await;
+                    }
+                }),
+                parcheck::task!("durations:slow", {
+                    async {
+                        parcheck::operation!("sleep", {
+                            async { tokio::time::sleep(Duration::from_millis(10)).await }
+                        })
+                        .await;
+                        parcheck::operation!("quick", { async {} }).await;
+                    }
+                }),
+            );
+        })
+        .await;
+
+    let steps = report.slowest_steps();
+    assert_eq!(steps.len(), 5);
+    assert!(steps
+        .windows(2)
+        .all(|pair| pair[0].duration() >= pair[1].duration()));
+    assert_eq!(steps[0].step().operation_name(), "sleep");
+    assert!(steps[0].duration() >= Duration::from_millis(10));
+    assert_eq!(
+        steps[0].trace().steps()[steps[0].index()].duration(),
+        Some(steps[0].duration())
+    );
+
+    let schedules = report.slowest_schedules();
+    assert_eq!(schedules.len(), 3);
+    for schedule in schedules {
+        assert!(schedule.duration() >= Duration::from_millis(10));
+        assert_eq!(schedule.trace().duration(), Some(schedule.duration()));
+        assert!(!schedule.trace().to_json().contains(r#""duration_ns":null"#));
+    }
+}
+
+#[test]
+fn explores_schedules_that_failed_before_first() {
+    use std::{
+        fs,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    let path = std::env::temp_dir().join(format!(
+        "parcheck-history-{}-explores_failed_first.txt",
+        std::process::id()
+    ));
+    fs::write(
+        &path,
+        "2 2:history:c.op > 1:history:b.op > 0:history:a.op\n1 0:history:a.op > 1:history:b.op > 2:history:c.op\n",
+    )
+    .unwrap();
+
+    let result = thread::spawn({
+        let path = path.clone();
+        move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(async {
+                parcheck::runner()
+                    .failure_history(&path)
+                    .max_iterations(1)
+                    .run(["history:a", "history:b", "history:c"], || async {
+                        let order = Arc::new(Mutex::new(Vec::new()));
+                        let op = |name: &'static str| {
+                            let order = Arc::clone(&order);
+                            parcheck::task!(name, {
+                                async move {
+                                    parcheck::operation!("op", {
+                                        async { order.lock().unwrap().push(name) }
+                                    })
+                                    .await;
+                                }
+                            })
+                        };
+                        tokio::join!(op("history:a"), op("history:b"), op("history:c"));
+                        let order = order.lock().unwrap();
+                        assert_ne!(*order, ["history:c", "history:b", "history:a"]);
+                    })
+                    .await;
+            });
+        }
+    })
+    .join();
+
+    assert!(result.is_err());
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "3 2:history\\:c.op > 1:history\\:b.op > 0:history\\:a.op\n1 0:history\\:a.op > 1:history\\:b.op > 2:history\\:c.op\n"
+    );
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn replaces_oldest_least_failed_schedule_in_full_history() {
+    use std::{fs, thread};
+
+    let path = std::env::temp_dir().join(format!(
+        "parcheck-history-{}-replaces_oldest_least_failed.txt",
+        std::process::id()
+    ));
+    let old = |idx: usize| format!(r"0:evict\:old.op{idx}");
+    let history = (0..100)
+        .map(|idx| {
+            let failures = if idx == 3 || idx == 7 { 1 } else { 2 };
+            format!("{failures} {}\n", old(idx))
+        })
+        .collect::<String>();
+    fs::write(&path, history).unwrap();
+
+    let result = thread::spawn({
+        let path = path.clone();
+        move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(
+                parcheck::runner()
+                    .failure_history(&path)
+                    .run(["evict:new"], || async {
+                        parcheck::task!("evict:new", {
+                            async { parcheck::operation!("op", { async {} }).await }
+                        })
+                        .await;
+                        panic!("failed");
+                    }),
+            )
+        }
+    })
+    .join();
+
+    assert!(result.is_err());
+    let history = fs::read_to_string(&path).unwrap();
+    let lines = history.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 100);
+    assert!(!lines.contains(&format!("1 {}", old(3)).as_str()));
+    assert_eq!(lines[98], format!("1 {}", old(7)));
+    assert_eq!(lines[99], r"1 0:evict\:new.op");
+    let _ = fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn presets_configure_exploration() {
+    async fn scenario() {
+        let obs = Observer::new();
+        tokio::join!(obs.execute("a"), obs.execute("b"));
+    }
+
+    let report = parcheck::Runner::quick()
+        .run(["execute:a", "execute:b"], scenario)
+        .await;
+    // shallow schedules first, then random ones until the iteration budget runs out
+    assert_eq!(report.iterations(), 100);
+
+    let report = parcheck::Runner::thorough()
+        .run(["execute:a", "execute:b"], scenario)
+        .await;
+    assert_eq!(report.iterations(), 20);
+    assert!(report.exhausted());
+
+    let report = parcheck::Runner::ci()
+        .run(["execute:a", "execute:b"], scenario)
+        .await;
+    assert_eq!(report.iterations(), 20);
+    assert!(report.exhausted());
+}
+
+#[tokio::test]
+async fn warns_about_large_schedule_space() {
+    let notes = Arc::new(Mutex::new(Vec::new()));
+    let ops = |name: &'static str, count: usize| {
+        parcheck::task!(name, {
+            async move {
+                for _ in 0..count {
+                    parcheck::operation!("write", { async {} }).await;
+                }
+            }
+        })
+    };
+
+    let report = parcheck::runner()
+        .schedule_space_warning(100)
+        .max_iterations(20)
+        .reporter(parcheck::Reporter::Custom(Box::new({
+            let notes = Arc::clone(&notes);
+            move |note| notes.lock().unwrap().push(note.to_owned())
+        })))
+        .run(["space:a", "space:b", "space:c"], || async {
+            tokio::join!(ops("space:a", 4), ops("space:b", 4), ops("space:c", 2));
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 20);
+    let notes = notes.lock().unwrap();
+    assert_eq!(notes.len(), 1, "{notes:?}");
+    assert!(notes[0].starts_with("warning: schedule space is estimated at "));
+    assert!(notes[0].contains("most alternatives come from: task 'space:"));
+}
+
+#[tokio::test]
+async fn returns_error_of_failed_iteration() {
+    async fn scenario(lost_update: bool) -> Result<(), String> {
+        let obs = Observer::new();
+        tokio::join!(obs.execute("a"), obs.execute("b"));
+        let trace = obs.take_trace();
+        if lost_update && trace.starts_with("ab") {
+            return Err(format!("lost update in {trace}"));
+        }
+        Ok(())
+    }
+
+    let notes = Arc::new(Mutex::new(Vec::new()));
+    let error = parcheck::runner()
+        .reporter(parcheck::Reporter::Custom(Box::new({
+            let notes = Arc::clone(&notes);
+            move |note| notes.lock().unwrap().push(note.to_owned())
+        })))
+        .try_run(["execute:a", "execute:b"], || scenario(true))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().starts_with("lost update in ab"));
+    assert!(notes
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|note| note.contains("PARCHECK_REPLAY=")));
+
+    let report = parcheck::runner()
+        .try_run(["execute:a", "execute:b"], || scenario(false))
+        .await
+        .unwrap();
+    assert_eq!(report.iterations(), 20);
+
+    let iterations = parcheck::runner()
+        .try_run_with_state(["execute:a", "execute:b"], 0, |iterations| async move {
+            scenario(false).await.map(|()| iterations + 1)
+        })
+        .await;
+    assert_eq!(iterations.unwrap(), 20);
+}
+
+#[tokio::test]
+async fn exposes_errors_of_failed_iterations_to_classifiers() {
+    #[derive(Debug)]
+    struct LostUpdate(String);
+
+    impl std::fmt::Display for LostUpdate {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "lost update in {}", self.0)
+        }
+    }
+
+    impl std::error::Error for LostUpdate {}
+
+    async fn scenario() -> Result<(), LostUpdate> {
+        let obs = Observer::new();
+        tokio::join!(obs.execute("a"), obs.execute("b"));
+        let trace = obs.take_trace();
+        if trace.starts_with("ab") {
+            return Err(LostUpdate(trace));
+        }
+        Ok(())
+    }
+
+    let report = parcheck::runner()
+        .classify_failure(|payload, _| {
+            let error = payload
+                .downcast_ref::<parcheck::ScenarioError>()
+                .unwrap()
+                .error();
+            match error.downcast_ref::<LostUpdate>() {
+                Some(LostUpdate(trace)) if trace.starts_with("ab") => {
+                    parcheck::FailureClass::known("lost update")
+                }
+                _ => parcheck::FailureClass::bug("unexpected"),
+            }
+        })
+        .try_run(["execute:a", "execute:b"], scenario)
+        .await
+        .unwrap();
+    assert_eq!(report.iterations(), 20);
+    assert_eq!(report.failure_classes()[0].class().name(), "lost update");
+
+    let report = parcheck::runner()
+        .expect_panic_matching("lost update in ab")
+        .try_run(["execute:a", "execute:b"], scenario)
+        .await
+        .unwrap();
+    assert!(report.expected_panic().is_some());
+}
+
+#[tokio::test]
+async fn passes_when_schedule_panics_as_expected() {
+    let report = parcheck::runner()
+        .reporter(parcheck::Reporter::Silent)
+        .expect_panic_matching("duplicate execution")
+        .run(["execute:a", "execute:b"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+            let trace = obs.take_trace();
+            assert!(!trace.starts_with("abab"), "duplicate execution: {trace}");
+        })
+        .await;
+
+    let schedule = report.expected_panic().unwrap();
+    let tasks = schedule
+        .steps()
+        .iter()
+        .map(|step| step.task_name())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        tasks[..4],
+        ["execute:a", "execute:b", "execute:a", "execute:b"]
+    );
+}
+
+#[tokio::test]
+#[should_panic(expected = "no explored schedule panicked with a message matching \"never\"")]
+async fn fails_when_no_schedule_panics_as_expected() {
+    parcheck::runner()
+        .expect_panic_matching("never")
+        .run(["execute:a", "execute:b"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn pauses_at_breakpoints() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let paused = Arc::new(Mutex::new(Vec::new()));
+    let write = |name: &'static str| {
+        let log = Arc::clone(&log);
+        parcheck::task!(name, {
+            async move {
+                parcheck::operation!("read", { async {} }).await;
+                parcheck::operation!("write", { async { log.lock().unwrap().push(name) } }).await;
+            }
+        })
+    };
+
+    parcheck::runner()
+        .replay(
+            "0:break:a.read > 1:break:b.read > 0:break:a.write > 1:break:b.write"
+                .parse()
+                .unwrap(),
+        )
+        .break_at(
+            3,
+            Box::new({
+                let log = Arc::clone(&log);
+                let paused = Arc::clone(&paused);
+                move |ctx| {
+                    let state = log.lock().unwrap().join(",");
+                    paused
+                        .lock()
+                        .unwrap()
+                        .push(format!("{}: {state}", ctx.step()));
+                    Box::pin(async {})
+                }
+            }),
+        )
+        .break_at(
+            "read",
+            Box::new({
+                let paused = Arc::clone(&paused);
+                move |ctx| {
+                    paused.lock().unwrap().push(ctx.step().to_string());
+                    Box::pin(async {})
+                }
+            }),
+        )
+        .run(["break:a", "break:b"], || async {
+            log.lock().unwrap().clear();
+            tokio::join!(write("break:a"), write("break:b"));
+        })
+        .await;
+
+    // `write` of b is paused after `write` of a
+    assert_eq!(
+        *paused.lock().unwrap(),
+        [
+            r"0:break\:a.read",
+            r"1:break\:b.read",
+            r"1:break\:b.write: break:a",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn resumes_exploration_from_checkpoint() {
+    use std::{collections::HashSet, fs, time::Duration};
+
+    let path = std::env::temp_dir().join(format!(
+        "parcheck-checkpoint-{}-resumes_exploration.txt",
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+    let schedules = Arc::new(Mutex::new(HashSet::new()));
+    let scenario = || {
+        let schedules = Arc::clone(&schedules);
+        async move {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+            schedules.lock().unwrap().insert(obs.take_trace());
+        }
+    };
+
+    let report = parcheck::runner()
+        .checkpoint(&path, Duration::ZERO)
+        .max_iterations(5)
+        .run(["execute:a", "execute:b"], scenario)
+        .await;
+    assert_eq!(report.iterations(), 5);
+    assert!(!report.exhausted());
+    assert!(path.exists());
+
+    let report = parcheck::runner()
+        .checkpoint(&path, Duration::from_secs(3600))
+        .max_iterations(100)
+        .run(["execute:a", "execute:b"], scenario)
+        .await;
+    // the second run explores only the schedules the first one didn't
+    assert_eq!(report.iterations(), 15);
+    assert!(report.exhausted());
+    assert_eq!(schedules.lock().unwrap().len(), 20);
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn classifies_failures() {
+    use parcheck::{FailureAction, FailureClass};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let classify = |payload: &(dyn std::any::Any + Send), _: &parcheck::Trace| {
+        let message = payload.downcast_ref::<&str>().copied().unwrap_or_default();
+        if message.contains("connection refused") {
+            FailureClass::infrastructure("infrastructure")
+        } else if message.contains("stale read") {
+            FailureClass::known("stale read")
+        } else {
+            FailureClass::bug("bug")
+        }
+    };
+
+    // known failures are counted, the exploration continues
+    let report = parcheck::runner()
+        .classify_failure(classify)
+        .run(["execute:a", "execute:b"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+            if obs.take_trace().starts_with('b') {
+                panic!("stale read");
+            }
+        })
+        .await;
+    assert_eq!(report.iterations(), 20);
+    assert!(report.exhausted());
+    let classes = report.failure_classes();
+    assert_eq!(classes.len(), 1);
+    assert_eq!(classes[0].class().name(), "stale read");
+    assert_eq!(classes[0].class().action(), FailureAction::Continue);
+    assert_eq!(classes[0].failures(), 10);
+
+    // schedules failing because of the infrastructure are retried
+    let attempts = AtomicUsize::new(0);
+    let report = parcheck::runner()
+        .classify_failure(classify)
+        .run(["execute:a", "execute:b"], || async {
+            let obs = Observer::new();
+            tokio::join!(obs.execute("a"), obs.execute("b"));
+            if attempts.fetch_add(1, Ordering::Relaxed) == 3 {
+                panic!("connection refused");
+            }
+        })
+        .await;
+    // the failed iteration is counted, too
+    assert_eq!(report.iterations(), 21);
+    assert!(report.exhausted());
+    assert_eq!(report.failure_classes()[0].failures(), 1);
+    assert_eq!(
+        report.failure_classes()[0].to_string(),
+        format!(
+            "infrastructure: 1 failures (e.g. {})",
+            report.failure_classes()[0].example()
+        )
+    );
+}
+
+#[tokio::test]
+#[should_panic(expected = "connection refused")]
+async fn fails_after_retrying_schedule_too_many_times() {
+    parcheck::runner()
+        .classify_failure(|_, _| parcheck::FailureClass::infrastructure("infrastructure"))
+        .run(["retried:a"], || async {
+            parcheck::task!("retried:a", {
+                async {
+                    parcheck::operation!("connect", { async {} }).await;
+                }
+            })
+            .await;
+            panic!("connection refused");
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn retries_schedules_failing_on_infrastructure() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let attempts = AtomicUsize::new(0);
+    let report = parcheck::runner()
+        .classify_failure(|payload, _| match payload.downcast_ref::<&str>() {
+            Some(message) if message.contains("database unavailable") => {
+                parcheck::FailureClass::bug("database")
+            }
+            _ => parcheck::FailureClass::bug("bug"),
+        })
+        .retry_on(|class| class.name() == "database", 5)
+        .run(["flaky:a"], || async {
+            parcheck::task!("flaky:a", {
+                async {
+                    parcheck::operation!("query", { async {} }).await;
+                }
+            })
+            .await;
+            // the database comes up after a few attempts
+            if attempts.fetch_add(1, Ordering::Relaxed) < 4 {
+                panic!("database unavailable");
+            }
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 5);
+    assert_eq!(report.failure_classes()[0].class().name(), "database");
+    assert_eq!(report.failure_classes()[0].failures(), 4);
+}
@@ -48,3 +48,143 @@ async fn doesnt_complain_about_unused_vars() {
     .await;
     assert_eq!(result, 123);
 }
+
+#[tokio::test]
+async fn timeout_operations_succeed_when_disabled() {
+    let result = parcheck::task!("task", {
+        async { parcheck::operation!("op", timeout, { async { 123 } }).await }
+    })
+    .await;
+    assert_eq!(result, Ok(123));
+
+    let result = parcheck::task!("task", {
+        async {
+            parcheck::operation!(
+                "op",
+                vec![ParcheckLock::AcquireExclusive {
+                    scope: "scope".into()
+                }],
+                timeout,
+                { async { 123 } }
+            )
+            .await
+        }
+    })
+    .await;
+    assert_eq!(result, Ok(123));
+}
+
+#[tokio::test]
+async fn unique_operations_succeed_when_disabled() {
+    let result = parcheck::task!("task", {
+        async { parcheck::operation!("op", unique = "key", { async { 123 } }).await }
+    })
+    .await;
+    assert_eq!(result, Ok(123));
+
+    let result = parcheck::task!("task", {
+        async {
+            parcheck::operation!(
+                "op",
+                vec![ParcheckLock::AcquireExclusive {
+                    scope: "scope".into()
+                }],
+                unique = 42,
+                { async { 123 } }
+            )
+            .await
+        }
+    })
+    .await;
+    assert_eq!(result, Ok(123));
+}
+
+#[tokio::test]
+async fn serializable_operations_succeed_when_disabled() {
+    let result = parcheck::task!("task", {
+        async { parcheck::operation!("commit", serializable, { async { 123 } }).await }
+    })
+    .await;
+    assert_eq!(result, Ok(123));
+
+    let result = parcheck::task!("task", {
+        async {
+            parcheck::operation!(
+                "commit",
+                vec![ParcheckLock::Release {
+                    scope: "scope".into()
+                }],
+                serializable,
+                { async { 123 } }
+            )
+            .await
+        }
+    })
+    .await;
+    assert_eq!(result, Ok(123));
+}
+
+#[tokio::test]
+async fn cost_hints_are_ignored_when_disabled() {
+    let result = parcheck::task!("task", {
+        async { parcheck::operation!("op", cost = High, { async { 123 } }).await }
+    })
+    .await;
+    assert_eq!(result, 123);
+
+    let result = parcheck::task!("task", {
+        async {
+            parcheck::operation!(
+                "op",
+                vec![ParcheckLock::AcquireShared {
+                    scope: "scope".into()
+                }],
+                cost = Low,
+                { async { 123 } }
+            )
+            .await
+        }
+    })
+    .await;
+    assert_eq!(result, 123);
+}
+
+#[tokio::test]
+async fn locks_sync_mutex_when_disabled() {
+    let counter = parcheck::sync::Mutex::new("counter", 1);
+    let value = counter
+        .with_lock(|counter| {
+            *counter += 1;
+            *counter
+        })
+        .await;
+    assert_eq!(value, 2);
+}
+
+#[test]
+fn derives_canonical_scopes() {
+    use parcheck::scope::LockScope;
+
+    struct Row<'a>(&'a str, u64);
+
+    impl LockScope for Row<'_> {
+        fn lock_scope(&self) -> String {
+            parcheck::scope!("row", self.0, self.1)
+        }
+    }
+
+    assert_eq!(Row("accounts", 1).lock_scope(), "row:accounts:1");
+    assert_ne!(
+        parcheck::scope!("row", "a:b", "c"),
+        parcheck::scope!("row", "a", "b:c")
+    );
+    assert_eq!(parcheck::scope!("row", r"a\:b"), r"row:a\\\:b");
+    assert!(matches!(
+        Row("accounts", 1).exclusive(),
+        ParcheckLock::AcquireExclusive { scope } if scope == "row:accounts:1"
+    ));
+    assert!(matches!(
+        "plain".release(),
+        ParcheckLock::Release { scope } if scope == "plain"
+    ));
+}
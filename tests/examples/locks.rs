@@ -1,6 +1,6 @@
 use std::sync::atomic::{AtomicBool, Ordering};
 
-use parcheck::ParcheckLock;
+use parcheck::{scope::TypedLock, ParcheckLock};
 
 static LOCK: AtomicBool = AtomicBool::new(false);
 
@@ -134,3 +134,627 @@ async fn detects_deadlocks() {
         })
         .await;
 }
+
+#[tokio::test]
+async fn models_row_locks_of_transactions() {
+    use std::collections::BTreeSet;
+    use std::sync::{Arc, Mutex};
+
+    use parcheck::isolation::{Isolation, Transaction};
+
+    async fn withdraw(balance: &Mutex<u64>, name: &str, for_update: bool) {
+        parcheck::task!(name, {
+            async {
+                let mut tx = Transaction::new(Isolation::ReadCommitted);
+                let locks = if for_update {
+                    tx.select_for_update("accounts", 1)
+                } else {
+                    tx.read_row("accounts", 1)
+                };
+                let current =
+                    parcheck::operation!("select", locks, { async { *balance.lock().unwrap() } })
+                        .await;
+                parcheck::operation!("update", tx.update_row("accounts", 1), {
+                    async { *balance.lock().unwrap() = current - 10 }
+                })
+                .await;
+                parcheck::operation!("commit", tx.commit(), { async {} }).await;
+            }
+        })
+        .await;
+    }
+
+    for (for_update, expected) in [(false, vec![80, 90]), (true, vec![80])] {
+        let outcomes = Arc::new(Mutex::new(BTreeSet::new()));
+        parcheck::runner()
+            .run(["withdraw:a", "withdraw:b"], || {
+                let outcomes = Arc::clone(&outcomes);
+                async move {
+                    let balance = Mutex::new(100);
+                    tokio::join!(
+                        withdraw(&balance, "withdraw:a", for_update),
+                        withdraw(&balance, "withdraw:b", for_update),
+                    );
+                    outcomes.lock().unwrap().insert(*balance.lock().unwrap());
+                }
+            })
+            .await;
+
+        assert_eq!(
+            outcomes
+                .lock()
+                .unwrap()
+                .iter()
+                .copied()
+                .collect::<Vec<u64>>(),
+            expected
+        );
+    }
+}
+
+#[test]
+fn reports_controller_errors_with_failed_schedule() {
+    use std::sync::{Arc, Mutex};
+
+    let failed = Arc::new(Mutex::new(None));
+    let result = std::thread::spawn({
+        let failed = Arc::clone(&failed);
+        move || {
+            tokio::runtime::Runtime::new().unwrap().block_on(
+                parcheck::runner()
+                    .on_panic(Box::new(move |trace| {
+                        *failed.lock().unwrap() = Some(trace.to_string());
+                    }))
+                    .run(["unreleased_locks:reported"], || async {
+                        parcheck::task!("unreleased_locks:reported", {
+                            async {
+                                parcheck::operation!(
+                                    "acquire",
+                                    vec![ParcheckLock::AcquireExclusive {
+                                        scope: "reported-scope".into()
+                                    }],
+                                    { async {} }
+                                )
+                                .await;
+                            }
+                        })
+                        .await;
+                    }),
+            );
+        }
+    })
+    .join();
+
+    let message = *result.unwrap_err().downcast::<String>().unwrap();
+    assert_eq!(
+        message,
+        "task 'unreleased_locks:reported': finished without releasing locks: [\"reported-scope\"]"
+    );
+    assert_eq!(
+        failed.lock().unwrap().as_deref(),
+        Some(r"0:unreleased_locks\:reported.acquire")
+    );
+}
+
+async fn cancelled_holder() {
+    let holder = parcheck::task!("cancel:holder", {
+        async {
+            parcheck::operation!(
+                "acquire",
+                vec![ParcheckLock::AcquireExclusive {
+                    scope: "cancel-scope".into()
+                }],
+                { async {} }
+            )
+            .await;
+            std::future::pending::<()>().await;
+        }
+    });
+    let other = parcheck::task!("cancel:other", {
+        async {
+            parcheck::operation!(
+                "acquire",
+                vec![ParcheckLock::AcquireExclusive {
+                    scope: "cancel-scope".into()
+                }],
+                { async {} }
+            )
+            .await;
+            parcheck::operation!(
+                "release",
+                vec![ParcheckLock::Release {
+                    scope: "cancel-scope".into()
+                }],
+                { async {} }
+            )
+            .await;
+        }
+    });
+    let (cancelled, ()) = tokio::join!(
+        tokio::time::timeout(std::time::Duration::from_millis(10), holder),
+        other
+    );
+    assert!(cancelled.is_err());
+}
+
+#[tokio::test]
+async fn releases_locks_of_cancelled_tasks() {
+    let report = parcheck::runner()
+        .run(["cancel:holder", "cancel:other"], cancelled_holder)
+        .await;
+
+    assert!(report.exhausted());
+    for family in report.families() {
+        let cancellations = family.example().cancellations();
+        assert_eq!(cancellations.len(), 1);
+        assert_eq!(cancellations[0].task_name(), "cancel:holder");
+    }
+    let holder_first = report
+        .families()
+        .iter()
+        .find(|family| family.task_order()[0] == "cancel:holder")
+        .unwrap();
+    assert_eq!(holder_first.example().cancellations()[0].step(), 1);
+}
+
+#[tokio::test]
+#[should_panic(expected = "some tasks did not finish")]
+async fn leaks_locks_of_cancelled_tasks_if_configured() {
+    parcheck::runner()
+        .leak_cancelled_locks(true)
+        .run(["cancel:holder", "cancel:other"], cancelled_holder)
+        .await;
+}
+
+#[tokio::test]
+async fn records_candidates_of_steps() {
+    let exclusive = |process: &'static str| {
+        parcheck::task!(process, {
+            async {
+                parcheck::operation!(
+                    "acquire",
+                    vec![ParcheckLock::AcquireExclusive {
+                        scope: "candidates".into()
+                    }],
+                    { async {} }
+                )
+                .await;
+                parcheck::operation!(
+                    "release",
+                    vec![ParcheckLock::Release {
+                        scope: "candidates".into()
+                    }],
+                    { async {} }
+                )
+                .await;
+            }
+        })
+    };
+
+    let report = parcheck::runner()
+        .record_candidates(true)
+        .replay("0:cand:a.acquire".parse().unwrap())
+        .run(["cand:a", "cand:b"], || async {
+            tokio::join!(exclusive("cand:a"), exclusive("cand:b"));
+        })
+        .await;
+
+    let candidates = report.families()[0]
+        .example()
+        .steps()
+        .iter()
+        .map(|step| {
+            step.candidates()
+                .iter()
+                .map(|candidate| {
+                    format!(
+                        "{}.{}{}",
+                        candidate.task_name(),
+                        candidate.operation().name(),
+                        if candidate.blocked() {
+                            " (blocked)"
+                        } else {
+                            ""
+                        }
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        candidates,
+        [
+            vec!["cand:a.acquire", "cand:b.acquire"],
+            vec!["cand:a.release", "cand:b.acquire (blocked)"],
+            vec!["cand:b.acquire"],
+            vec!["cand:b.release"],
+        ]
+    );
+}
+
+#[tokio::test]
+async fn exposes_blocked_locks_before_step() {
+    use parcheck::LockMode;
+    use std::sync::{Arc, Mutex};
+
+    let locking = |process: &'static str, acquire: ParcheckLock| {
+        parcheck::task!(process, {
+            async move {
+                let scope = "contended".to_string();
+                parcheck::operation!("acquire", vec![acquire], { async {} }).await;
+                parcheck::operation!("release", vec![ParcheckLock::Release { scope }], {
+                    async {}
+                })
+                .await;
+            }
+        })
+    };
+
+    let steps = Arc::new(Mutex::new(Vec::new()));
+    parcheck::runner()
+        .replay("0:contention:writer.acquire".parse().unwrap())
+        .before_step_with_context(Box::new({
+            let steps = Arc::clone(&steps);
+            move |ctx| {
+                let blocked = ctx
+                    .controller()
+                    .blocked_locks()
+                    .iter()
+                    .map(|blocked| {
+                        assert_eq!(blocked.mode(), LockMode::Shared);
+                        let holders = blocked
+                            .holders()
+                            .iter()
+                            .map(|(holder, _)| *holder)
+                            .collect::<Vec<_>>();
+                        format!(
+                            "{} < {}:{}",
+                            blocked.task(),
+                            blocked.scope(),
+                            holders.join(",")
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                steps.lock().unwrap().push(blocked);
+                Box::pin(async {})
+            }
+        }))
+        .run(["contention:writer", "contention:reader"], || async {
+            tokio::join!(
+                locking(
+                    "contention:writer",
+                    ParcheckLock::AcquireExclusive {
+                        scope: "contended".into()
+                    }
+                ),
+                locking(
+                    "contention:reader",
+                    ParcheckLock::AcquireShared {
+                        scope: "contended".into()
+                    }
+                ),
+            );
+        })
+        .await;
+
+    let steps = steps.lock().unwrap().clone();
+    assert_eq!(steps.len(), 4);
+    assert!(steps[0].is_empty());
+    assert_eq!(
+        steps[1],
+        ["contention:reader < contended:contention:writer"]
+    );
+    assert!(steps[2..].iter().all(Vec::is_empty));
+}
+
+#[tokio::test]
+async fn uses_custom_lock_model() {
+    use parcheck::{LockMode, LockModel};
+
+    struct Leases;
+
+    impl LockModel for Leases {
+        fn compatible(&self, held: LockMode, requested: LockMode) -> bool {
+            held == LockMode::Custom("lease") && requested == LockMode::Custom("lease")
+        }
+    }
+
+    let leasing = |process: &'static str| {
+        parcheck::task!(process, {
+            async move {
+                let acquire = ParcheckLock::Acquire {
+                    scope: "leased".into(),
+                    mode: "lease",
+                };
+                parcheck::operation!("acquire", vec![acquire], { async {} }).await;
+                let release = ParcheckLock::Release {
+                    scope: "leased".into(),
+                };
+                parcheck::operation!("release", vec![release], { async {} }).await;
+            }
+        })
+    };
+
+    let report = parcheck::runner()
+        .lock_model(Leases)
+        .run(["leases:a", "leases:b"], || async {
+            tokio::join!(leasing("leases:a"), leasing("leases:b"));
+        })
+        .await;
+    assert_eq!(report.iterations(), 6);
+
+    // default model treats custom modes as exclusive
+    let report = parcheck::runner()
+        .run(["leases:a", "leases:b"], || async {
+            tokio::join!(leasing("leases:a"), leasing("leases:b"));
+        })
+        .await;
+    assert_eq!(report.iterations(), 2);
+}
+
+#[tokio::test]
+async fn reports_schedules_pruned_by_locks() {
+    use parcheck::Reduction;
+
+    let locking = |process: &'static str| {
+        parcheck::task!(process, {
+            async move {
+                let acquire = ParcheckLock::AcquireExclusive {
+                    scope: "pruned".into(),
+                };
+                parcheck::operation!("acquire", vec![acquire], { async {} }).await;
+                let release = ParcheckLock::Release {
+                    scope: "pruned".into(),
+                };
+                parcheck::operation!("release", vec![release], { async {} }).await;
+            }
+        })
+    };
+
+    let report = parcheck::runner()
+        .run(["pruning:a", "pruning:b"], || async {
+            tokio::join!(locking("pruning:a"), locking("pruning:b"));
+        })
+        .await;
+
+    let space = report.schedule_space().unwrap();
+    assert_eq!(space.raw_schedules(), Some(6));
+    assert_eq!(space.reduced_schedules(), 2);
+    assert_eq!(space.pruned_by(Reduction::Locks), 2);
+    assert_eq!(space.pruned_by(Reduction::PreemptionBound), 0);
+    let mut pruned = space
+        .pruned()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    pruned.sort();
+    assert_eq!(
+        pruned,
+        [
+            "locks: task 'pruning:a' acquire (1 alternatives)",
+            "locks: task 'pruning:b' acquire (1 alternatives)"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn locks_scope_of_sync_mutex() {
+    use parcheck::sync::Mutex;
+
+    let report = parcheck::runner()
+        .run(["mutex:a", "mutex:b"], || async {
+            let cache = Mutex::new("cache", Vec::new());
+            tokio::join!(
+                parcheck::task!("mutex:a", {
+                    async {
+                        let acquire = ParcheckLock::AcquireExclusive {
+                            scope: "cache".into(),
+                        };
+                        parcheck::operation!("begin", vec![acquire], { async {} }).await;
+                        let release = ParcheckLock::Release {
+                            scope: "cache".into(),
+                        };
+                        parcheck::operation!("end", vec![release], { async {} }).await;
+                    }
+                }),
+                parcheck::task!("mutex:b", {
+                    async { cache.with_lock(|cache| cache.push("b")).await }
+                }),
+            );
+            assert_eq!(cache.into_inner(), ["b"]);
+        })
+        .await;
+
+    // `lock` of b can't execute between `begin` and `end` of a
+    assert_eq!(report.iterations(), 2);
+    assert_eq!(report.scope("cache").unwrap().exclusive_acquisitions(), 4);
+}
+
+#[cfg(feature = "parking_lot")]
+#[tokio::test]
+async fn locks_scope_of_parking_lot_mutex() {
+    use parcheck::sync::parking_lot::Mutex;
+
+    let report = parcheck::runner()
+        .run(["parking_lot:a", "parking_lot:b"], || async {
+            let counter = Mutex::new("counter", 0);
+            let increment = |name: &'static str| {
+                parcheck::task!(name, {
+                    async { counter.with_lock(|counter| *counter += 1).await }
+                })
+            };
+            tokio::join!(increment("parking_lot:a"), increment("parking_lot:b"));
+            assert_eq!(counter.into_inner(), 2);
+        })
+        .await;
+
+    assert_eq!(report.iterations(), 2);
+    assert_eq!(report.scope("counter").unwrap().exclusive_acquisitions(), 4);
+}
+
+#[tokio::test]
+async fn locks_scopes_with_typed_keys() {
+    #[derive(Debug, Hash, PartialEq, Eq)]
+    struct RowId(u32, u64);
+
+    async fn update(name: &'static str) {
+        parcheck::task!(name, {
+            async {
+                let acquire = TypedLock::AcquireExclusive { scope: RowId(1, 2) };
+                parcheck::operation!("begin", vec![acquire], { async {} }).await;
+                let release = TypedLock::Release { scope: RowId(1, 2) };
+                parcheck::operation!("end", vec![release], { async {} }).await;
+            }
+        })
+        .await;
+    }
+
+    let report = parcheck::runner()
+        .run(["typed:a", "typed:b"], || async {
+            tokio::join!(update("typed:a"), update("typed:b"));
+        })
+        .await;
+
+    // only serial schedules: `begin` of one task can't execute between `begin` and `end` of other
+    assert_eq!(report.iterations(), 2);
+    assert_eq!(
+        report
+            .scope("RowId(1, 2)")
+            .unwrap()
+            .exclusive_acquisitions(),
+        4
+    );
+}
+
+#[tokio::test]
+async fn str_and_string_scope_keys_conflict() {
+    let report = parcheck::runner()
+        .run(["mixed:string", "mixed:str"], || async {
+            let string = parcheck::task!("mixed:string", {
+                async {
+                    let acquire = ParcheckLock::AcquireExclusive {
+                        scope: "mixed".into(),
+                    };
+                    parcheck::operation!("begin", vec![acquire], { async {} }).await;
+                    let release = ParcheckLock::Release {
+                        scope: "mixed".into(),
+                    };
+                    parcheck::operation!("end", vec![release], { async {} }).await;
+                }
+            });
+            let str = parcheck::task!("mixed:str", {
+                async {
+                    let acquire = TypedLock::AcquireExclusive { scope: "mixed" };
+                    parcheck::operation!("begin", vec![acquire], { async {} }).await;
+                    let release = TypedLock::Release { scope: "mixed" };
+                    parcheck::operation!("end", vec![release], { async {} }).await;
+                }
+            });
+            tokio::join!(string, str);
+        })
+        .await;
+
+    // `String` and `&str` keys are the same scope, so critical sections don't interleave
+    assert_eq!(report.iterations(), 2);
+    let stats = report.scope("mixed").unwrap();
+    assert_eq!(stats.exclusive_acquisitions(), 4);
+    assert_eq!(stats.max_shared_holders(), 0);
+}
+
+#[tokio::test]
+async fn compares_outcomes_of_variants_per_schedule() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use parcheck::Variant;
+
+    async fn increment(name: &'static str, counter: &AtomicU64, variant: Variant) {
+        parcheck::task!(name, {
+            async {
+                let (acquire, release) = match variant {
+                    Variant::Before => (Vec::new(), Vec::new()),
+                    Variant::After => (
+                        vec![ParcheckLock::AcquireExclusive {
+                            scope: "counter".into(),
+                        }],
+                        vec![ParcheckLock::Release {
+                            scope: "counter".into(),
+                        }],
+                    ),
+                };
+                let value = parcheck::operation!("read", acquire, {
+                    async { counter.load(Ordering::SeqCst) }
+                })
+                .await;
+                parcheck::operation!("write", release, {
+                    async { counter.store(value + 1, Ordering::SeqCst) }
+                })
+                .await;
+            }
+        })
+        .await;
+    }
+
+    let comparison = parcheck::runner()
+        .compare(["compare:a", "compare:b"], |variant| async move {
+            let counter = AtomicU64::new(0);
+            tokio::join!(
+                increment("compare:a", &counter, variant),
+                increment("compare:b", &counter, variant)
+            );
+            assert_eq!(counter.load(Ordering::SeqCst), 2, "lost update");
+        })
+        .await;
+
+    assert_eq!(comparison.schedules().len(), 6);
+    // locks only change the outcome of interleaved schedules, which can't be followed anymore
+    let differences = comparison.differences().collect::<Vec<_>>();
+    assert_eq!(differences.len(), 4);
+    for schedule in differences {
+        assert!(schedule.before().unwrap().contains("lost update"));
+        assert_eq!(schedule.after(), None);
+        assert!(schedule.diverged());
+    }
+    for schedule in comparison.schedules().iter().filter(|s| !s.changed()) {
+        assert_eq!(schedule.before(), None);
+        assert!(!schedule.diverged());
+    }
+    assert_eq!(comparison.to_string().lines().count(), 4);
+}
+
+#[tokio::test]
+async fn reports_unreachable_branches() {
+    let locking = |process: &'static str| {
+        parcheck::task!(process, {
+            async move {
+                let acquire = ParcheckLock::AcquireExclusive {
+                    scope: "jobs".into(),
+                };
+                parcheck::operation!("claim", vec![acquire], { async {} }).await;
+                let release = ParcheckLock::Release {
+                    scope: "jobs".into(),
+                };
+                parcheck::operation!("finish", vec![release], { async {} }).await;
+            }
+        })
+    };
+
+    let report = parcheck::runner()
+        .run(["unreachable:a", "unreachable:b"], || async {
+            tokio::join!(locking("unreachable:a"), locking("unreachable:b"));
+        })
+        .await;
+
+    let space = report.schedule_space().unwrap();
+    let blocked = &space.unreachable()[0];
+    assert_eq!(blocked.reason(), "blocked by locks on scope 'jobs'");
+    assert_eq!(blocked.branches(), 2);
+    // one task claimed the scope, the other one couldn't
+    let example = blocked.example().split(" > ").collect::<Vec<_>>();
+    assert_eq!(example.len(), 2);
+    assert!(example.iter().all(|step| step.ends_with(".claim")));
+    assert_eq!(space.unreachable_by("blocked by locks"), 2);
+    assert_eq!(space.unreachable_by("task finished"), 8);
+    assert!(space
+        .to_string()
+        .contains("2 branches unreachable: blocked by locks on scope 'jobs' (e.g. "));
+}